@@ -0,0 +1,68 @@
+// Minimal chitin.key reader: only the header and the bif entries, the resource entries are ignored.
+//
+// KEY V1 layout (little endian):
+//   0x00 signature "KEY "
+//   0x04 version "V1  "
+//   0x08 bif count (u32)
+//   0x0c resource count (u32)
+//   0x10 offset of the bif entries (u32)
+//   0x14 offset of the resource entries (u32)
+// each bif entry is 12 bytes: file length (u32), file name offset (u32), file name length (u16), location (u16)
+
+use anyhow::anyhow;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const HEADER_SIZE: usize = 0x18;
+const BIF_ENTRY_SIZE: usize = 12;
+
+pub struct Key {
+    pub resource_count: u32,
+    /// bif file names, as written in the key (for ex. `data\Default.bif`)
+    pub bif_names: Vec<String>,
+}
+
+pub fn read_key(path: &Path) -> Result<Key> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; HEADER_SIZE];
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"KEY " || &header[4..8] != b"V1  " {
+        return Err(anyhow!("{} is not a KEY V1 file", path.to_string_lossy()));
+    }
+    let bif_count = read_u32(&header, 0x08) as usize;
+    let resource_count = read_u32(&header, 0x0c);
+    let bif_offset = read_u32(&header, 0x10) as u64;
+
+    let mut entries = vec![0u8; bif_count * BIF_ENTRY_SIZE];
+    file.seek(SeekFrom::Start(bif_offset))?;
+    file.read_exact(&mut entries)?;
+
+    let mut bif_names = Vec::with_capacity(bif_count);
+    for entry in entries.chunks(BIF_ENTRY_SIZE) {
+        let name_offset = read_u32(entry, 4) as u64;
+        let name_length = read_u16(entry, 8) as usize;
+        let mut name = vec![0u8; name_length];
+        file.seek(SeekFrom::Start(name_offset))?;
+        file.read_exact(&mut name)?;
+        // the length includes the terminating nul
+        let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+        bif_names.push(String::from_utf8_lossy(&name[..end]).into_owned());
+    }
+
+    Ok(Key { resource_count, bif_names })
+}
+
+/// file name of a bif, without the `data\` (or `data/`) part
+pub fn bif_file_name(bif_name: &str) -> &str {
+    bif_name.rsplit(|c| c == '\\' || c == '/').next().unwrap_or(bif_name)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
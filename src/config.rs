@@ -0,0 +1,181 @@
+// Configuration file (--config), in TOML:
+//
+// # how to duplicate the entries iedup doesn't know about (mod frameworks, tools...), by name or by path relative
+// # to the source when the pattern has a /. Also applies to support/ and gameinfo (linked by default), and to the
+// # files of the game dirs, which it then duplicates differently than usual
+// [rules]
+// "EEex.dll" = "copy"
+// "support" = "copy"
+// "weidu_external" = "link"
+// "game/*.bak" = "skip"
+// "*.bif" = "hardlink"
+// "*.mus" = "copy"
+//
+// # how to duplicate the files of music/ and of its song dirs, by extension (.mus are copied, .acm, .ogg and .wav
+// # linked by default)
+// [music]
+// ogg = "copy"
+//
+// The same rules can be given on the command line, as --rule 'game/*.bak=skip'. They come before those of the file
+// and of [music].
+
+use anyhow::{anyhow, Context, Error as AnyError, Result};
+use glob::Pattern;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RuleAction {
+    /// copy the file, or the dir with all its content
+    Copy,
+    /// symlink it (the default)
+    Link,
+    /// hard link the file, or each file of the dir: the game sees real files, the target must be on the filesystem of
+    /// the source
+    Hardlink,
+    /// leave it out of the target
+    Skip,
+}
+
+impl FromStr for RuleAction {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "copy" => Ok(RuleAction::Copy),
+            "link" | "symlink" => Ok(RuleAction::Link),
+            "hardlink" => Ok(RuleAction::Hardlink),
+            "skip" => Ok(RuleAction::Skip),
+            _ => Err(anyhow!("unknown action {} (expected copy, link, symlink, hardlink or skip)", value)),
+        }
+    }
+}
+
+pub struct Rule {
+    pub pattern: Pattern,
+    /// the pattern has a / and applies to the path relative to the source, not to the name
+    pub is_path: bool,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    pub fn new(pattern: &str, action: RuleAction) -> Result<Rule> {
+        Ok(Rule {
+            pattern: Pattern::new(pattern).with_context(|| format!("invalid pattern {}", pattern))?,
+            is_path: pattern.contains('/'),
+            action,
+        })
+    }
+
+    pub fn matches(&self, relative: &Path) -> bool {
+        if self.is_path {
+            self.pattern.matches(&relative.to_string_lossy())
+        } else {
+            relative.file_name().map_or(false, |name| self.pattern.matches(&name.to_string_lossy()))
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ConfigFile {
+    pub rules: Vec<Rule>,
+    /// the actions for the music files by extension, before the default ones
+    pub music: Vec<(String, RuleAction)>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<ConfigFile> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+        ConfigFile::parse(&content).with_context(|| format!("invalid config file {}", path.to_string_lossy()))
+    }
+
+    pub fn parse(content: &str) -> Result<ConfigFile> {
+        let value: toml::Value = content.parse()?;
+        let mut config = ConfigFile::default();
+        for (key, value) in value.as_table().into_iter().flatten() {
+            match key.as_str() {
+                "rules" => config.rules = parse_rules(value)?,
+                "music" => config.music = parse_music(value)?,
+                _ => return Err(anyhow!("unknown key {}", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// the action of the first rule matching `relative` (a path relative to the source)
+    pub fn rule_for(&self, relative: &Path) -> Option<RuleAction> {
+        self.rules.iter().find(|rule| rule.matches(relative)).map(|rule| rule.action)
+    }
+}
+
+/// Where iedup looks for profiles.toml and defaults.toml (~/.config/iedup on Linux)
+pub fn config_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "iedup").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// `value` if it's a string, `key` is for the error
+pub fn string<'a>(key: &str, value: &'a toml::Value) -> Result<&'a str> {
+    value.as_str().ok_or_else(|| anyhow!("{} should be a string", key))
+}
+
+/// `value` if it's an array of strings, `key` is for the error
+pub fn strings(key: &str, value: &toml::Value) -> Result<Vec<String>> {
+    let values = value.as_array().ok_or_else(|| anyhow!("{} should be an array", key))?;
+    values.iter().map(|value| string(key, value).map(String::from)).collect()
+}
+
+/// The rules of a `[rules]` table ("pattern" = "action")
+pub fn parse_rules(value: &toml::Value) -> Result<Vec<Rule>> {
+    let rules = value.as_table().ok_or_else(|| anyhow!("rules should be a table"))?;
+    let mut parsed = Vec::with_capacity(rules.len());
+    for (pattern, action) in rules {
+        let action = action
+            .as_str()
+            .ok_or_else(|| anyhow!("the action for {} should be a string", pattern))?
+            .parse()
+            .with_context(|| format!("invalid rule for {}", pattern))?;
+        parsed.push(Rule::new(pattern, action)?);
+    }
+    Ok(parsed)
+}
+
+/// The actions of a `[music]` table ("extension" = "action")
+fn parse_music(value: &toml::Value) -> Result<Vec<(String, RuleAction)>> {
+    let extensions = value.as_table().ok_or_else(|| anyhow!("music should be a table"))?;
+    let mut parsed = Vec::with_capacity(extensions.len());
+    for (extension, action) in extensions {
+        let action = action
+            .as_str()
+            .ok_or_else(|| anyhow!("the action for {} should be a string", extension))?
+            .parse()
+            .with_context(|| format!("invalid music action for {}", extension))?;
+        parsed.push((extension.trim_start_matches('.').to_string(), action));
+    }
+    Ok(parsed)
+}
+
+/// The rules of the command line (PATTERN=ACTION), fails on a pattern given twice with different actions
+pub fn parse_rule_args(args: &[String]) -> Result<Vec<Rule>> {
+    let mut rules = Vec::with_capacity(args.len());
+    // the pattern, the action and the argument of each rule, to find the conflicts
+    let mut given: Vec<(&str, RuleAction, &str)> = Vec::with_capacity(args.len());
+    for arg in args {
+        let (pattern, action) = match arg.rfind('=') {
+            Some(index) => (&arg[..index], &arg[index + 1..]),
+            None => return Err(anyhow!("invalid rule {} (expected PATTERN=ACTION, for ex. *.bif=link)", arg)),
+        };
+        if pattern.is_empty() {
+            return Err(anyhow!("invalid rule {}: the pattern is empty", arg));
+        }
+        let action: RuleAction = action.parse().with_context(|| format!("invalid rule {}", arg))?;
+        match given.iter().find(|(given_pattern, _, _)| *given_pattern == pattern) {
+            Some((_, given_action, _)) if *given_action == action => {}
+            Some((_, _, given_arg)) => return Err(anyhow!("conflicting rules {} and {}", given_arg, arg)),
+            None => {
+                rules.push(Rule::new(pattern, action)?);
+                given.push((pattern, action, arg));
+            }
+        }
+    }
+    Ok(rules)
+}
@@ -0,0 +1,55 @@
+// Finds the copied files with the same content (--dedup-copies), so that they can share their data through hard links.
+// The files are first grouped by size, only those of the same size are hashed.
+
+use anyhow::{Context, Result};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+pub struct Duplicate {
+    pub path: PathBuf,
+    /// the first file with the same content
+    pub original: PathBuf,
+    pub size: u64,
+}
+
+/// The files of `files` with the same content as a previous one
+pub fn find_duplicates(files: &[PathBuf]) -> Result<Vec<Duplicate>> {
+    let mut by_size: BTreeMap<u64, Vec<&PathBuf>> = BTreeMap::new();
+    for file in files {
+        let size = file
+            .metadata()
+            .with_context(|| format!("failed to read the metadata of {}", file.to_string_lossy()))?
+            .len();
+        // nothing to save on empty files
+        if size > 0 {
+            by_size.entry(size).or_default().push(file);
+        }
+    }
+    let mut duplicates = Vec::new();
+    for (size, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        let mut originals: HashMap<blake3::Hash, &PathBuf> = HashMap::new();
+        for file in files {
+            match originals.entry(hash_file(file)?) {
+                Entry::Occupied(original) => duplicates.push(Duplicate {
+                    path: file.clone(),
+                    original: original.get().to_path_buf(),
+                    size,
+                }),
+                Entry::Vacant(entry) => {
+                    entry.insert(file);
+                }
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// The hash of the content of a file, also used to check the copies
+pub fn hash_file(path: &Path) -> Result<blake3::Hash> {
+    let mut file = File::open(path).with_context(|| format!("failed to open {}", path.to_string_lossy()))?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    Ok(hasher.finalize())
+}
@@ -0,0 +1,130 @@
+// Default options, from defaults.toml in the config dir (~/.config/iedup on Linux), with the names of the options:
+//
+// color = "never"
+// verbose = 1
+// override_mode = "link"
+// exclude = ["game/lang/de_DE", "game/lang/ru_RU"]
+// copy_all = true
+//
+// The options given on the command line win, the excludes and includes are added to those of the command line. A flag
+// set here can't be unset on the command line.
+
+use crate::config::{config_dir, string, strings};
+use crate::Config;
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// defaults.toml in the config dir, if there is a config dir
+pub fn file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("defaults.toml"))
+}
+
+pub fn read(path: &Path, config: &mut Config, is_given: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    apply(&content, config, is_given).with_context(|| format!("invalid defaults file {}", path.to_string_lossy()))
+}
+
+/// Sets the options of `config` that aren't given on the command line (`is_given` tells it by argument name) to their
+/// value in `content`, returns the names of the options changed
+pub fn apply(content: &str, config: &mut Config, is_given: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+    let value: toml::Value = content.parse()?;
+    let mut applied = Vec::new();
+    for (key, value) in value.as_table().into_iter().flatten() {
+        let is_applied = match key.as_str() {
+            "color" => {
+                let color = string(key, value)?.parse()?;
+                if !is_given("color") {
+                    config.color = color;
+                    true
+                } else {
+                    false
+                }
+            }
+            "preserve" => {
+                let preserve = string(key, value)?.parse()?;
+                if !is_given("preserve") {
+                    config.preserve = preserve;
+                    true
+                } else {
+                    false
+                }
+            }
+            "override_mode" => {
+                let override_mode = string(key, value)?.parse()?;
+                if config.override_mode.is_none() {
+                    config.override_mode = Some(override_mode);
+                    true
+                } else {
+                    false
+                }
+            }
+            "verbose" => {
+                let verbose = value.as_integer().ok_or_else(|| anyhow!("{} should be a number", key))?;
+                if !is_given("verbose") && !is_given("quiet") {
+                    config.verbose = verbose as i32;
+                    true
+                } else {
+                    false
+                }
+            }
+            "quiet" => {
+                let quiet = boolean(key, value)?;
+                if !is_given("verbose") && !is_given("quiet") {
+                    config.quiet = quiet;
+                    true
+                } else {
+                    false
+                }
+            }
+            "exclude" => patterns(strings(key, value)?, &mut config.exclude),
+            "include_only" => patterns(strings(key, value)?, &mut config.include_only),
+            "dlc_patterns" => patterns(strings(key, value)?, &mut config.dlc_patterns),
+            "config" => text(string(key, value)?, &mut config.config_file),
+            "profile" => text(string(key, value)?, &mut config.profile),
+            "no_progress" => flag(boolean(key, value)?, &mut config.no_progress),
+            "ignore_case" => flag(boolean(key, value)?, &mut config.ignore_case),
+            "copy_all" => flag(boolean(key, value)?, &mut config.copy_all),
+            "auto_fallback" => flag(boolean(key, value)?, &mut config.auto_fallback),
+            "strict" => flag(boolean(key, value)?, &mut config.strict),
+            "follow_existing" => flag(boolean(key, value)?, &mut config.follow_existing),
+            "check_bifs" => flag(boolean(key, value)?, &mut config.check_bifs),
+            "fail_on_empty" => flag(boolean(key, value)?, &mut config.fail_on_empty),
+            "verify_copies" => flag(boolean(key, value)?, &mut config.verify_copies),
+            "sanity_check" => flag(boolean(key, value)?, &mut config.sanity_check),
+            _ => return Err(anyhow!("unknown option {}", key)),
+        };
+        if is_applied {
+            applied.push(key.clone());
+        }
+    }
+    Ok(applied)
+}
+
+fn boolean(key: &str, value: &toml::Value) -> Result<bool> {
+    value.as_bool().ok_or_else(|| anyhow!("{} should be true or false", key))
+}
+
+fn flag(value: bool, option: &mut bool) -> bool {
+    let is_applied = value && !*option;
+    *option |= value;
+    is_applied
+}
+
+fn text<'a, T: From<&'a str>>(value: &'a str, option: &mut Option<T>) -> bool {
+    if option.is_none() {
+        *option = Some(value.into());
+        true
+    } else {
+        false
+    }
+}
+
+/// the default patterns come before those of the command line
+fn patterns(mut defaults: Vec<String>, option: &mut Vec<String>) -> bool {
+    if defaults.is_empty() {
+        return false;
+    }
+    defaults.append(option);
+    *option = defaults;
+    true
+}
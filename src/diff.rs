@@ -0,0 +1,153 @@
+// What a rebuild would change in an existing target (--diff): the actions planned as for an empty target are compared
+// with what the target has, without changing anything. The copies are compared by content, the links by where they
+// point, the hard links by inode.
+
+use crate::dedup::hash_file;
+use crate::plan::{Action, ActionKind};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub enum Difference {
+    /// planned but missing in the target
+    Added(PathBuf, ActionKind),
+    /// in the target but not planned
+    Removed(PathBuf),
+    /// in the target, but not as planned
+    Changed(PathBuf, String),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Added(path, kind) => write!(f, "+ {} ({})", path.to_string_lossy(), kind_name(*kind)),
+            Difference::Removed(path) => write!(f, "- {}", path.to_string_lossy()),
+            Difference::Changed(path, change) => write!(f, "~ {}: {}", path.to_string_lossy(), change),
+        }
+    }
+}
+
+fn kind_name(kind: ActionKind) -> &'static str {
+    match kind {
+        ActionKind::CreateDir => "dir",
+        ActionKind::Copy => "copy",
+        ActionKind::Link => "link",
+        _ => kind.name(),
+    }
+}
+
+/// The differences between `target` and the `actions` planned for it, with the paths relative to `target`. The
+/// entries of `generated` at the root of the target (written by iedup itself) are left out
+pub fn compare(actions: &[Action], target: &Path, generated: &[&str]) -> Result<Vec<Difference>> {
+    let relative = |path: &Path| path.strip_prefix(target).unwrap_or(path).to_path_buf();
+    let mut differences = Vec::new();
+    let mut planned = HashMap::new();
+    let actions: Vec<&Action> = actions
+        .iter()
+        .filter(|action| {
+            matches!(
+                action.kind,
+                ActionKind::CreateDir | ActionKind::Copy | ActionKind::Link | ActionKind::Hardlink | ActionKind::Extract
+            )
+        })
+        .collect();
+    // the last action on a path wins (a file extracted from a dlc over a link...)
+    let last: HashMap<&Path, usize> = actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| (action.target.as_path(), index))
+        .collect();
+    for (index, action) in actions.iter().enumerate() {
+        if last[action.target.as_path()] != index {
+            continue;
+        }
+        planned.insert(action.target.as_path(), action.kind);
+        let metadata = match action.target.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                differences.push(Difference::Added(relative(&action.target), action.kind));
+                continue;
+            }
+        };
+        let file_type = metadata.file_type();
+        let change = match action.kind {
+            ActionKind::CreateDir if file_type.is_dir() => None,
+            ActionKind::Copy if file_type.is_file() => match &action.source {
+                Some(source) if differs(source, &action.target)? => Some(String::from("the content differs")),
+                _ => None,
+            },
+            ActionKind::Link if file_type.is_symlink() => {
+                let link =
+                    std::fs::read_link(&action.target).with_context(|| format!("failed to read the link {}", action.target.to_string_lossy()))?;
+                match &action.source {
+                    Some(source) if link != *source => Some(format!("links to {} instead of {}", link.to_string_lossy(), source.to_string_lossy())),
+                    _ => None,
+                }
+            }
+            // the file of the archive isn't read, only its size compared
+            ActionKind::Extract if file_type.is_file() => match action.size {
+                Some(size) if size != metadata.len() => Some(String::from("the content differs")),
+                _ => None,
+            },
+            ActionKind::Hardlink if file_type.is_file() => match &action.source {
+                Some(source) if !is_hard_link(source, &action.target) => Some(format!("not a hard link to {}", source.to_string_lossy())),
+                _ => None,
+            },
+            _ => {
+                let was = if file_type.is_symlink() {
+                    "link"
+                } else if file_type.is_dir() {
+                    "dir"
+                } else {
+                    "file"
+                };
+                Some(format!("a {}, would be a {}", was, kind_name(action.kind)))
+            }
+        };
+        if let Some(change) = change {
+            differences.push(Difference::Changed(relative(&action.target), change));
+        }
+    }
+    let mut removed = Vec::new();
+    if target.is_dir() {
+        find_removed(target, target, &planned, generated, &mut removed)?;
+    }
+    removed.sort();
+    differences.extend(removed.into_iter().map(|path| Difference::Removed(relative(&path))));
+    Ok(differences)
+}
+
+/// whether the copy `target` doesn't have the content of `source`
+pub fn differs(source: &Path, target: &Path) -> Result<bool> {
+    let (source_len, target_len) = (source.metadata()?.len(), target.metadata()?.len());
+    Ok(source_len != target_len || hash_file(source)? != hash_file(target)?)
+}
+
+/// whether `target` is a hard link to the file `source` (the same file of the same filesystem)
+pub fn is_hard_link(source: &Path, target: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (source.metadata(), target.symlink_metadata()) {
+        (Ok(source), Ok(target)) => source.dev() == target.dev() && source.ino() == target.ino(),
+        _ => false,
+    }
+}
+
+/// Adds to `removed` the entries of `dir` that aren't planned, going down the dirs that are
+fn find_removed(target: &Path, dir: &Path, planned: &HashMap<&Path, ActionKind>, generated: &[&str], removed: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).with_context(|| format!("failed to read directory {}", dir.to_string_lossy()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if dir == target && generated.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        match planned.get(path.as_path()) {
+            None => removed.push(path),
+            // file_type() doesn't follow the links
+            Some(ActionKind::CreateDir) if entry.file_type()?.is_dir() => find_removed(target, &path, planned, generated, removed)?,
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
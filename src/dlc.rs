@@ -0,0 +1,92 @@
+// The dlc archives extracted into the game dir of the target (--extract-dlc) instead of being linked, the way the
+// community DLC merger does it: sod-dlc.zip holds a game dir (data/, lang/, movies/, override/...) whose files take
+// precedence over those of the game. An archive is read whole (names and checksums) before anything is extracted.
+// Each file extracted is an action of its own, whose source is <archive>/<path in the archive>.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// a file of an archive
+pub struct Entry {
+    index: usize,
+    /// relative to the game dir
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+pub struct Archive {
+    path: PathBuf,
+    zip: ZipArchive<File>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Archive> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path.to_string_lossy()))?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            zip: ZipArchive::new(file).with_context(|| format!("{} is not a zip archive", path.to_string_lossy()))?,
+        })
+    }
+
+    /// The files of the archive, once each was read back with the right checksum and none leads out of the game dir
+    pub fn validate(&mut self) -> Result<Vec<Entry>> {
+        self.list(true)
+    }
+
+    /// The files of the archive, without reading them (a dry run)
+    pub fn entries(&mut self) -> Result<Vec<Entry>> {
+        self.list(false)
+    }
+
+    /// The file at `path` in the archive
+    pub fn entry(&mut self, path: &Path) -> Result<Entry> {
+        self.entries()?
+            .into_iter()
+            .find(|entry| entry.path == path)
+            .ok_or_else(|| anyhow!("no {} in {}", path.to_string_lossy(), self.path.to_string_lossy()))
+    }
+
+    fn list(&mut self, read: bool) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let archive = self.path.to_string_lossy();
+        for index in 0..self.zip.len() {
+            let mut file = self
+                .zip
+                .by_index(index)
+                .with_context(|| format!("failed to read entry {} of {}", index, archive))?;
+            let path = match file.enclosed_name() {
+                Some(path) => path.to_path_buf(),
+                None => return Err(anyhow!("{} leads out of the game dir", file.name())),
+            };
+            if file.is_dir() {
+                continue;
+            }
+            // reading a file to its end checks its checksum
+            if read {
+                std::io::copy(&mut file, &mut std::io::sink()).with_context(|| format!("{} is corrupted", file.name()))?;
+            }
+            entries.push(Entry {
+                index,
+                path,
+                size: file.size(),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Writes the file `entry` of the archive to `target`
+    pub fn extract(&mut self, entry: &Entry, target: &Path) -> Result<()> {
+        let mut file = self.zip.by_index(entry.index)?;
+        let mut writer = File::create(target).with_context(|| format!("failed to create {}", target.to_string_lossy()))?;
+        std::io::copy(&mut file, &mut writer).with_context(|| format!("failed to extract {}", target.to_string_lossy()))?;
+        Ok(())
+    }
+}
+
+/// The archive and the path in it of `source`, the source of an extract action, None if no archive is found there
+pub fn split_source(source: &Path) -> Option<(&Path, &Path)> {
+    let archive = source.ancestors().skip(1).find(|path| path.is_file())?;
+    Some((archive, source.strip_prefix(archive).ok()?))
+}
@@ -0,0 +1,116 @@
+// The errors of the library, for the callers that react to them: run, recorded_source and watch::run fail with an
+// IedupError, whose kind is what the exit code of iedup tells. The errors without a variant of their own are Other,
+// with their causes.
+
+use crate::FailureKind;
+use anyhow::Error as AnyError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum IedupError {
+    /// invalid arguments, or options that can't go together
+    #[error("{0}")]
+    Usage(String),
+    #[error("source {} is not a directory", .path.display())]
+    SourceNotDir { path: PathBuf },
+    /// the source isn't a game install iedup knows about
+    #[error("{} is not a game install iedup knows about", .path.display())]
+    DetectionFailed {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// the source recorded in a target (`path`) holds another game than when it was built
+    #[error("the source recorded in {} holds {found}, not {expected}", .path.display())]
+    GameChanged { path: PathBuf, found: String, expected: String },
+    /// the install is of a variant (classic) that can't do what was asked
+    #[error("{} is a classic install, it has no {missing}", .path.display())]
+    UnsupportedVariant { path: PathBuf, missing: &'static str },
+    /// --expect-version, `found` is None when the source doesn't tell its version
+    #[error("the source is version {}, expected {expected}", .found.as_deref().unwrap_or("unknown"))]
+    WrongVersion { found: Option<String>, expected: String },
+    /// --check-bifs, the bifs listed in chitin.key that aren't in the game dir `path`
+    #[error("{} bif(s) of chitin.key missing in {}: {}", .missing.len(), .path.display(), .missing.join(", "))]
+    MissingBifs { path: PathBuf, missing: Vec<String> },
+    /// --strict, some links created lead out of the source `path` through links in it
+    #[error("{count} link(s) lead out of the source {}", .path.display())]
+    LinksOutOfSource { count: usize, path: PathBuf },
+    #[error("source {} is not readable", .path.display())]
+    SourceNotReadable { path: PathBuf, source: std::io::Error },
+    #[error("target {} is not a directory", .path.display())]
+    TargetNotDir { path: PathBuf },
+    /// checked before the run, not to fail at the first copy
+    #[error("target {} is not writable", .path.display())]
+    TargetNotWritable { path: PathBuf, source: std::io::Error },
+    /// and --auto-fallback wasn't given
+    #[error("target {} can't hold symbolic links (FAT or exFAT?), use --copy-all to copy everything instead", .path.display())]
+    LinksUnsupported { path: PathBuf },
+    /// the link would lead to itself, the source and the target are the same dir through links
+    #[error("refusing to link {} to itself", .path.display())]
+    LinkToItself { path: PathBuf },
+    /// --max-links, with the category that has the most
+    #[error("{links} links to create, more than --max-links {max_links} ({most} in {category}), use --copy-all to copy instead")]
+    TooManyLinks {
+        links: u64,
+        max_links: u64,
+        most: u64,
+        category: String,
+    },
+    #[error("target dir {} is not empty", .path.display())]
+    TargetNotEmpty { path: PathBuf },
+    /// the dir to create (--into, --only) is already there
+    #[error("{} already exists (use --force to replace it)", .path.display())]
+    AlreadyExists { path: PathBuf },
+    #[error("failed to copy {} -> {}", .original.display(), .path.display())]
+    CopyFailed {
+        path: PathBuf,
+        original: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to link {} -> {}", .original.display(), .path.display())]
+    LinkFailed {
+        path: PathBuf,
+        original: PathBuf,
+        source: std::io::Error,
+    },
+    /// some items failed with --continue-on-error, the others were duplicated
+    #[error("{failed} item(s) failed in {}", .target.display())]
+    Partial { failed: usize, target: PathBuf },
+    /// the target or a copy doesn't match the source
+    #[error("{0}")]
+    Mismatch(String),
+    /// --sanity-check, the entries the game needs that the target is missing (or has broken)
+    #[error("target {} isn't launch-ready", .path.display())]
+    NotLaunchReady { path: PathBuf, missing: Vec<String> },
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl IedupError {
+    /// What went wrong, None for the errors without a kind of their own
+    pub fn kind(&self) -> Option<FailureKind> {
+        match self {
+            IedupError::Usage(_)
+            | IedupError::UnsupportedVariant { .. }
+            | IedupError::LinksUnsupported { .. }
+            | IedupError::LinkToItself { .. }
+            | IedupError::TooManyLinks { .. } => Some(FailureKind::Usage),
+            IedupError::SourceNotDir { .. }
+            | IedupError::DetectionFailed { .. }
+            | IedupError::GameChanged { .. }
+            | IedupError::WrongVersion { .. }
+            | IedupError::MissingBifs { .. }
+            | IedupError::LinksOutOfSource { .. } => Some(FailureKind::InvalidSource),
+            IedupError::TargetNotEmpty { .. } | IedupError::AlreadyExists { .. } => Some(FailureKind::TargetNotEmpty),
+            IedupError::Partial { .. } => Some(FailureKind::Partial),
+            IedupError::Mismatch(_) | IedupError::NotLaunchReady { .. } => Some(FailureKind::Mismatch),
+            IedupError::SourceNotReadable { .. } | IedupError::TargetNotWritable { .. } => Some(FailureKind::Permission),
+            IedupError::TargetNotDir { .. } | IedupError::CopyFailed { .. } | IedupError::LinkFailed { .. } | IedupError::Other(_) => None,
+        }
+    }
+
+    /// The IedupError that `error` is (or was caused by), else `error` as Other
+    pub(crate) fn from_any(error: AnyError) -> IedupError {
+        error.downcast::<IedupError>().unwrap_or_else(|error| IedupError::Other(error.into()))
+    }
+}
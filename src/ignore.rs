@@ -0,0 +1,96 @@
+// The .iedupignore files of the source, gitignore-style: in any dir of the source, they leave out of the target the
+// entries of that dir and of its subdirs that match their patterns, one per line:
+//
+// # the cutscenes we never watch
+// intro*.wbm
+// !intro.wbm
+// /480/
+// lo/*.wbm
+//
+// A pattern without a / (but a trailing one) matches the names at any depth, one with a / the paths relative to the
+// dir of the file (the leading / only anchors it). A trailing / matches only the dirs, a leading ! takes back an entry
+// left out by a previous pattern. The last matching pattern wins, those of the deeper files after those of their
+// parents. The .iedupignore files themselves are never duplicated.
+
+use anyhow::{anyhow, Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+pub const IGNORE_FILE: &str = ".iedupignore";
+
+pub struct Rule {
+    /// the line of the file, to report it
+    pub text: String,
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Rule {
+    /// whether the rule matches `relative` (to the dir of its file). None when the rule doesn't apply, else whether it
+    /// leaves the entry out (false for the ! rules)
+    pub fn matches(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        if self.dir_only && !is_dir {
+            return None;
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let matched = if self.anchored {
+            self.pattern.matches_with(&relative.to_string_lossy(), options)
+        } else {
+            relative
+                .file_name()
+                .map_or(false, |name| self.pattern.matches_with(&name.to_string_lossy(), options))
+        };
+        if matched {
+            Some(!self.negated)
+        } else {
+            None
+        }
+    }
+}
+
+/// The rules of a .iedupignore file
+pub fn parse(content: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let text = line.trim_end();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let (negated, pattern) = match text.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, text),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return Err(anyhow!("empty pattern at line {}", number + 1));
+        }
+        rules.push(Rule {
+            text: text.to_string(),
+            pattern: Pattern::new(pattern).with_context(|| format!("invalid pattern {} at line {}", text, number + 1))?,
+            negated,
+            dir_only,
+            anchored,
+        });
+    }
+    Ok(rules)
+}
+
+/// The rules of the .iedupignore file of `dir`, none when it has no such file
+pub fn read(dir: &Path) -> Result<Vec<Rule>> {
+    let path = dir.join(IGNORE_FILE);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    parse(&content).with_context(|| format!("invalid ignore file {}", path.to_string_lossy()))
+}
@@ -0,0 +1,206 @@
+// A reader of the JSON that iedup writes itself (the manifests of --write-manifest, read back by --from-manifest):
+// objects, arrays, strings, numbers, true, false and null, enough for it without a dependency.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// the value of `key` in an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as u64),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        position: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_space();
+    if parser.position < parser.chars.len() {
+        return Err(parser.error("unexpected content after the value"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("{} at character {}", message, self.position)
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.get(self.position).map_or(false, |c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.position).copied();
+        self.position += 1;
+        c
+    }
+
+    /// skips the spaces and `expected`, fails if it isn't next
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_space();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected {}", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_space();
+        match self.chars.get(self.position) {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-') | Some('0'..='9') => self.number(),
+            Some(_) => self.keyword(),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut members = BTreeMap::new();
+        self.skip_space();
+        if self.chars.get(self.position) == Some(&'}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.insert(key, self.value()?);
+            self.skip_space();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(members)),
+                _ => return Err(self.error("expected , or }")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_space();
+        if self.chars.get(self.position) == Some(&']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_space();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected , or ]")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.next() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => {
+                        let mut code = self.hex()?;
+                        // a character out of the basic plane is written as a surrogate pair
+                        if (0xd800..0xdc00).contains(&code) && self.next() == Some('\\') && self.next() == Some('u') {
+                            code = 0x10000 + ((code - 0xd800) << 10) + (self.hex()? - 0xdc00);
+                        }
+                        value.push(std::char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?);
+                    }
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// the 4 hex digits of a \u escape
+    fn hex(&mut self) -> Result<u32> {
+        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid \\u escape"))
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.position;
+        while self
+            .chars
+            .get(self.position)
+            .map_or(false, |c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map(Value::Number).map_err(|_| self.error("invalid number"))
+    }
+
+    fn keyword(&mut self) -> Result<Value> {
+        for (keyword, value) in [("null", Value::Null), ("true", Value::Bool(true)), ("false", Value::Bool(false))] {
+            if self.chars[self.position..].iter().take(keyword.len()).copied().eq(keyword.chars()) {
+                self.position += keyword.len();
+                return Ok(value);
+            }
+        }
+        Err(self.error("unexpected character"))
+    }
+}
@@ -0,0 +1,4951 @@
+use ansi_term::Colour::{Blue, Green, Red, Yellow};
+use ansi_term::{ANSIString, Style};
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error as AnyError;
+use anyhow::Result;
+use filetime::FileTime;
+use log::Level;
+use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::{DirEntry, File, Metadata, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+pub mod chitin;
+pub mod config;
+mod dedup;
+pub mod defaults;
+mod diff;
+mod dlc;
+mod error;
+mod ignore;
+mod json;
+mod manifest;
+mod mounts;
+mod origin;
+pub mod permissions;
+pub mod plan;
+pub mod profiles;
+mod report;
+pub mod stats;
+mod steam;
+#[cfg(test)]
+mod tests;
+pub mod timestamp;
+pub mod watch;
+use config::{parse_rule_args, ConfigFile, Rule, RuleAction};
+pub use error::IedupError;
+use glob::{MatchOptions, Pattern};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use permissions::Owner;
+use plan::{Action, ActionKind, Shell};
+pub use stats::Stats;
+use std::io::IsTerminal;
+
+/// The options of a run, see the command line help for the details. Built with IedupBuilder, or from the defaults of
+/// Config::new
+#[derive(Clone)]
+pub struct Config {
+    /// the game install to duplicate
+    pub source: PathBuf,
+    /// where to build the copy, an existing dir
+    pub target: PathBuf,
+    pub no_progress: bool,
+    /// the number of -v
+    pub verbose: i32,
+    pub quiet: bool,
+    pub color: ColorChoice,
+    pub merge: bool,
+    pub verify: bool,
+    pub force: bool,
+    /// globs relative to the source
+    pub exclude: Vec<String>,
+    /// globs relative to the source
+    pub include_only: Vec<String>,
+    pub resume: bool,
+    pub preserve: Preserve,
+    pub fail_on_empty: bool,
+    pub dry_run: bool,
+    pub plan_format: Option<PlanFormat>,
+    pub emit_script: Option<Shell>,
+    pub script_out: Option<PathBuf>,
+    pub ignore_case: bool,
+    /// the name of a subdir of the target to build into
+    pub into: Option<String>,
+    /// copy when not set (and not set by the profile)
+    pub override_mode: Option<OverrideMode>,
+    pub expect_version: Option<String>,
+    pub loose_version: bool,
+    /// the TOML file with the rules (--config)
+    pub config_file: Option<PathBuf>,
+    /// rules as PATTERN=ACTION (--rule), before those of the config file
+    pub rules: Vec<String>,
+    pub strict: bool,
+    pub follow_existing: bool,
+    pub check_bifs: bool,
+    pub link_base: Option<PathBuf>,
+    pub copy_all: bool,
+    pub auto_fallback: bool,
+    pub write_manifest: bool,
+    pub dedup_copies: bool,
+    pub max_links: Option<u64>,
+    /// the subdirs of lang/ to duplicate, all when empty
+    pub languages: Vec<String>,
+    pub fail_on_dangling: bool,
+    pub max_depth: usize,
+    pub copy_support: bool,
+    pub profile: Option<String>,
+    /// check every copy against its source, not only those of VERIFIED_COPIES
+    pub verify_copies: bool,
+    /// with verify, remove the links of the target to source files that don't exist anymore
+    pub prune: bool,
+    /// print the time spent in each phase at the end
+    pub timings: bool,
+    /// with merge, copy again the files of the source modified after this time
+    pub since: Option<SystemTime>,
+    /// a target with only JUNK_FILES (.DS_Store...) isn't empty
+    pub strict_empty: bool,
+    /// duplicate only this part of the game dir, in a target where the rest may already be
+    pub only: Option<Section>,
+    /// the mode of the copies (the created dirs get it too, searchable where readable)
+    pub chmod: Option<u32>,
+    /// the owner of the copies and of the created dirs, as user:group, user or :group
+    pub chown: Option<String>,
+    /// go on after the failure of an item, fail at the end with all of them
+    pub continue_on_error: bool,
+    /// print what the run will do and ask for a confirmation on stdin before doing it
+    pub interactive: bool,
+    /// with interactive, don't ask (needed when stdin isn't a terminal)
+    pub yes: bool,
+    /// the names of the dlc archives linked from the root and from dlc/, DEFAULT_DLC_PATTERN when empty
+    pub dlc_patterns: Vec<String>,
+    /// check at the end that the target has what the game needs to start
+    pub sanity_check: bool,
+    /// copy the files smaller than this many bytes (unless a rule links them)
+    pub copy_under: Option<u64>,
+    /// link the files larger than this many bytes (unless a rule or copy_all copies them)
+    pub link_over: Option<u64>,
+    /// keep the copies replaced by a merge (with since) as <name>.iedup.bak
+    pub backup: bool,
+    /// don't check that the copies fit in the free space of the target
+    pub ignore_space: bool,
+    /// put the content of game/ at the root of the target, with start.sh and support/
+    pub flatten: bool,
+    /// name the copies and links of the target in lowercase, whatever the case of the source
+    pub lowercase_names: bool,
+    /// only report what a rebuild would change in the (existing) target, without changing anything
+    pub diff: bool,
+    /// with diff, fail if there are differences
+    pub fail_on_diff: bool,
+    /// write a summary of the run in JSON there, even if it fails at the end
+    pub report_json: Option<PathBuf>,
+    /// the entries of the root of the install left out of the target
+    pub skip: Vec<RootItem>,
+    /// how many times a copy or a link failing with a transient error (network filesystems) is tried again
+    pub retries: u32,
+    /// the wait before the first retry, doubled at each retry
+    pub retry_delay: Duration,
+    /// build the target from the entries of this manifest (written by write_manifest) instead of what the source has
+    pub from_manifest: Option<PathBuf>,
+    /// with from_manifest, leave out the entries whose source is gone or changed instead of failing
+    pub allow_drift: bool,
+    /// reflinks fall back to plain copies when the target can't hold them
+    pub copy_mode: CopyMode,
+    /// create the target (and its parents) when it doesn't exist
+    pub create_target: bool,
+    /// leave out the movies/ and the sounds/ of the languages (their dialog.tlk, data/ and override/ are kept)
+    pub skip_lang_movies: bool,
+    pub skip_lang_sounds: bool,
+    /// only print the sizes a run would copy and link, by category and extension, without planning each file
+    pub stats_only: bool,
+    /// replace the copies of the existing target identical to the source files a run would link by links (iedup dedup)
+    pub relink: bool,
+    /// where the copies go (at their path relative to the target), the target linking to them
+    pub writable_root: Option<PathBuf>,
+    /// extract the dlc archives into the game dir instead of linking them
+    pub extract_dlc: bool,
+    /// asks the question of interactive (Proceed?), without it only yes lets the run go on
+    pub confirm: Option<Confirm>,
+    /// gets the json plan of plan_format and the script of emit_script (without script_out), dropped without it
+    pub plan_output: Option<PlanOutput>,
+}
+
+/// Asks a yes/no question to the user, true for yes
+pub type Confirm = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Takes the text of a plan (json or script)
+pub type PlanOutput = Arc<dyn Fn(&str) + Send + Sync>;
+
+impl Config {
+    /// The options of a plain run, as with no option on the command line
+    pub fn new<S: Into<PathBuf>, T: Into<PathBuf>>(source: S, target: T) -> Config {
+        Config {
+            source: source.into(),
+            target: target.into(),
+            no_progress: false,
+            verbose: 0,
+            quiet: false,
+            color: ColorChoice::Auto,
+            merge: false,
+            verify: false,
+            force: false,
+            exclude: vec![],
+            include_only: vec![],
+            resume: false,
+            preserve: Preserve::All,
+            fail_on_empty: false,
+            dry_run: false,
+            plan_format: None,
+            emit_script: None,
+            script_out: None,
+            ignore_case: false,
+            into: None,
+            override_mode: None,
+            expect_version: None,
+            loose_version: false,
+            config_file: None,
+            rules: vec![],
+            strict: false,
+            follow_existing: false,
+            check_bifs: false,
+            link_base: None,
+            copy_all: false,
+            auto_fallback: false,
+            write_manifest: false,
+            dedup_copies: false,
+            max_links: None,
+            languages: vec![],
+            fail_on_dangling: false,
+            max_depth: 32,
+            copy_support: false,
+            profile: None,
+            verify_copies: false,
+            prune: false,
+            timings: false,
+            since: None,
+            strict_empty: false,
+            only: None,
+            chmod: None,
+            chown: None,
+            continue_on_error: false,
+            interactive: false,
+            yes: false,
+            dlc_patterns: vec![],
+            sanity_check: false,
+            copy_under: None,
+            link_over: None,
+            backup: false,
+            ignore_space: false,
+            flatten: false,
+            lowercase_names: false,
+            diff: false,
+            fail_on_diff: false,
+            report_json: None,
+            skip: vec![],
+            retries: 0,
+            retry_delay: Duration::from_millis(200),
+            from_manifest: None,
+            allow_drift: false,
+            copy_mode: CopyMode::Plain,
+            create_target: false,
+            skip_lang_movies: false,
+            skip_lang_sounds: false,
+            stats_only: false,
+            relink: false,
+            writable_root: None,
+            extract_dlc: false,
+            confirm: None,
+            plan_output: None,
+        }
+    }
+
+    /// Whether the run gives the json plan or the script to plan_output, which iedup writes to stdout: they are then the
+    /// only things written there (the messages should go to stderr)
+    pub fn plan_to_stdout(&self) -> bool {
+        self.plan_format == Some(PlanFormat::Json) || (self.emit_script.is_some() && self.script_out.is_none())
+    }
+}
+
+/// Builds the Config of a run:
+///
+/// ```no_run
+/// let stats = iedup::IedupBuilder::new("/gog/bg2ee", "/games/bg2ee")
+///     .exclude("game/lang/de_DE")
+///     .quiet(true)
+///     .run()?;
+/// println!("{} files copied", stats.copied);
+/// # Ok::<(), iedup::IedupError>(())
+/// ```
+pub struct IedupBuilder {
+    config: Config,
+}
+
+impl IedupBuilder {
+    pub fn new<S: Into<PathBuf>, T: Into<PathBuf>>(source: S, target: T) -> IedupBuilder {
+        IedupBuilder {
+            config: Config::new(source, target),
+        }
+    }
+
+    pub fn no_progress(mut self, no_progress: bool) -> Self {
+        self.config.no_progress = no_progress;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: i32) -> Self {
+        self.config.verbose = verbose;
+        self
+    }
+
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.config.quiet = quiet;
+        self
+    }
+
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.config.color = color;
+        self
+    }
+
+    pub fn merge(mut self, merge: bool) -> Self {
+        self.config.merge = merge;
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.config.verify = verify;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    /// adds an exclude glob, can be called several times
+    pub fn exclude<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.config.exclude.push(pattern.into());
+        self
+    }
+
+    /// adds an include glob, can be called several times
+    pub fn include_only<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.config.include_only.push(pattern.into());
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.config.resume = resume;
+        self
+    }
+
+    pub fn preserve(mut self, preserve: Preserve) -> Self {
+        self.config.preserve = preserve;
+        self
+    }
+
+    pub fn fail_on_empty(mut self, fail_on_empty: bool) -> Self {
+        self.config.fail_on_empty = fail_on_empty;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    pub fn plan_format(mut self, plan_format: PlanFormat) -> Self {
+        self.config.plan_format = Some(plan_format);
+        self
+    }
+
+    pub fn emit_script(mut self, shell: Shell) -> Self {
+        self.config.emit_script = Some(shell);
+        self
+    }
+
+    pub fn script_out<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.script_out = Some(path.into());
+        self
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.config.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn into_subdir<S: Into<String>>(mut self, subdir: S) -> Self {
+        self.config.into = Some(subdir.into());
+        self
+    }
+
+    pub fn override_mode(mut self, override_mode: OverrideMode) -> Self {
+        self.config.override_mode = Some(override_mode);
+        self
+    }
+
+    pub fn expect_version<S: Into<String>>(mut self, version: S, loose: bool) -> Self {
+        self.config.expect_version = Some(version.into());
+        self.config.loose_version = loose;
+        self
+    }
+
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.config_file = Some(path.into());
+        self
+    }
+
+    /// adds a rule as PATTERN=ACTION (*.bif=link), can be called several times
+    pub fn rule<S: Into<String>>(mut self, rule: S) -> Self {
+        self.config.rules.push(rule.into());
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    pub fn follow_existing(mut self, follow_existing: bool) -> Self {
+        self.config.follow_existing = follow_existing;
+        self
+    }
+
+    pub fn check_bifs(mut self, check_bifs: bool) -> Self {
+        self.config.check_bifs = check_bifs;
+        self
+    }
+
+    pub fn link_base<P: Into<PathBuf>>(mut self, link_base: P) -> Self {
+        self.config.link_base = Some(link_base.into());
+        self
+    }
+
+    pub fn copy_all(mut self, copy_all: bool) -> Self {
+        self.config.copy_all = copy_all;
+        self
+    }
+
+    pub fn auto_fallback(mut self, auto_fallback: bool) -> Self {
+        self.config.auto_fallback = auto_fallback;
+        self
+    }
+
+    pub fn write_manifest(mut self, write_manifest: bool) -> Self {
+        self.config.write_manifest = write_manifest;
+        self
+    }
+
+    pub fn dedup_copies(mut self, dedup_copies: bool) -> Self {
+        self.config.dedup_copies = dedup_copies;
+        self
+    }
+
+    pub fn max_links(mut self, max_links: u64) -> Self {
+        self.config.max_links = Some(max_links);
+        self
+    }
+
+    /// adds a language to keep, can be called several times
+    pub fn language<S: Into<String>>(mut self, language: S) -> Self {
+        self.config.languages.push(language.into());
+        self
+    }
+
+    pub fn fail_on_dangling(mut self, fail_on_dangling: bool) -> Self {
+        self.config.fail_on_dangling = fail_on_dangling;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    pub fn copy_support(mut self, copy_support: bool) -> Self {
+        self.config.copy_support = copy_support;
+        self
+    }
+
+    pub fn profile<S: Into<String>>(mut self, profile: S) -> Self {
+        self.config.profile = Some(profile.into());
+        self
+    }
+
+    pub fn verify_copies(mut self, verify_copies: bool) -> Self {
+        self.config.verify_copies = verify_copies;
+        self
+    }
+
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.config.prune = prune;
+        self
+    }
+
+    pub fn timings(mut self, timings: bool) -> Self {
+        self.config.timings = timings;
+        self
+    }
+
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.config.since = Some(since);
+        self
+    }
+
+    pub fn strict_empty(mut self, strict_empty: bool) -> Self {
+        self.config.strict_empty = strict_empty;
+        self
+    }
+
+    pub fn only(mut self, section: Section) -> Self {
+        self.config.only = Some(section);
+        self
+    }
+
+    pub fn chmod(mut self, mode: u32) -> Self {
+        self.config.chmod = Some(mode);
+        self
+    }
+
+    pub fn chown<S: Into<String>>(mut self, owner: S) -> Self {
+        self.config.chown = Some(owner.into());
+        self
+    }
+
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.config.continue_on_error = continue_on_error;
+        self
+    }
+
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.config.interactive = interactive;
+        self
+    }
+
+    pub fn yes(mut self, yes: bool) -> Self {
+        self.config.yes = yes;
+        self
+    }
+
+    pub fn sanity_check(mut self, sanity_check: bool) -> Self {
+        self.config.sanity_check = sanity_check;
+        self
+    }
+
+    pub fn copy_under(mut self, bytes: u64) -> Self {
+        self.config.copy_under = Some(bytes);
+        self
+    }
+
+    pub fn link_over(mut self, bytes: u64) -> Self {
+        self.config.link_over = Some(bytes);
+        self
+    }
+
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.config.backup = backup;
+        self
+    }
+
+    pub fn ignore_space(mut self, ignore_space: bool) -> Self {
+        self.config.ignore_space = ignore_space;
+        self
+    }
+
+    pub fn flatten(mut self, flatten: bool) -> Self {
+        self.config.flatten = flatten;
+        self
+    }
+
+    pub fn lowercase_names(mut self, lowercase_names: bool) -> Self {
+        self.config.lowercase_names = lowercase_names;
+        self
+    }
+
+    pub fn diff(mut self, diff: bool) -> Self {
+        self.config.diff = diff;
+        self
+    }
+
+    pub fn fail_on_diff(mut self, fail_on_diff: bool) -> Self {
+        self.config.fail_on_diff = fail_on_diff;
+        self
+    }
+
+    pub fn report_json<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.report_json = Some(path.into());
+        self
+    }
+
+    /// leaves an entry of the root of the install out of the target, can be called several times
+    pub fn skip(mut self, item: RootItem) -> Self {
+        self.config.skip.push(item);
+        self
+    }
+
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.config.retries = retries;
+        self.config.retry_delay = delay;
+        self
+    }
+
+    pub fn from_manifest<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.from_manifest = Some(path.into());
+        self
+    }
+
+    pub fn allow_drift(mut self, allow_drift: bool) -> Self {
+        self.config.allow_drift = allow_drift;
+        self
+    }
+
+    pub fn copy_mode(mut self, copy_mode: CopyMode) -> Self {
+        self.config.copy_mode = copy_mode;
+        self
+    }
+
+    pub fn create_target(mut self, create_target: bool) -> Self {
+        self.config.create_target = create_target;
+        self
+    }
+
+    pub fn skip_lang_movies(mut self, skip_lang_movies: bool) -> Self {
+        self.config.skip_lang_movies = skip_lang_movies;
+        self
+    }
+
+    pub fn skip_lang_sounds(mut self, skip_lang_sounds: bool) -> Self {
+        self.config.skip_lang_sounds = skip_lang_sounds;
+        self
+    }
+
+    pub fn stats_only(mut self, stats_only: bool) -> Self {
+        self.config.stats_only = stats_only;
+        self
+    }
+
+    pub fn relink(mut self, relink: bool) -> Self {
+        self.config.relink = relink;
+        self
+    }
+
+    pub fn writable_root<P: Into<PathBuf>>(mut self, writable_root: P) -> Self {
+        self.config.writable_root = Some(writable_root.into());
+        self
+    }
+
+    pub fn extract_dlc(mut self, extract_dlc: bool) -> Self {
+        self.config.extract_dlc = extract_dlc;
+        self
+    }
+
+    /// how to ask whether to proceed with interactive
+    pub fn confirm<F: Fn(&str) -> bool + Send + Sync + 'static>(mut self, confirm: F) -> Self {
+        self.config.confirm = Some(Arc::new(confirm));
+        self
+    }
+
+    /// where the json plan and the script go
+    pub fn plan_output<F: Fn(&str) + Send + Sync + 'static>(mut self, plan_output: F) -> Self {
+        self.config.plan_output = Some(Arc::new(plan_output));
+        self
+    }
+
+    /// adds a pattern of the names of the dlc archives, can be called several times
+    pub fn dlc_pattern<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.config.dlc_patterns.push(pattern.into());
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+
+    /// builds the Config and runs it
+    pub fn run(self) -> Result<Stats, IedupError> {
+        run(self.build())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for PlanFormat {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "text" => Ok(PlanFormat::Text),
+            "json" => Ok(PlanFormat::Json),
+            _ => Err(anyhow!("unknown plan format {} (expected text or json)", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Preserve {
+    All,
+    Perms,
+    None,
+}
+
+impl FromStr for Preserve {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "all" => Ok(Preserve::All),
+            "perms" => Ok(Preserve::Perms),
+            "none" => Ok(Preserve::None),
+            _ => Err(anyhow!("unknown preserve mode {} (expected all, perms or none)", value)),
+        }
+    }
+}
+
+/// How the files are copied: plainly, or as reflinks (copy-on-write copies sharing the blocks of their source until
+/// modified, on Btrfs and XFS)
+#[derive(Clone, Copy, PartialEq)]
+pub enum CopyMode {
+    Plain,
+    Reflink,
+}
+
+impl FromStr for CopyMode {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "plain" => Ok(CopyMode::Plain),
+            "reflink" => Ok(CopyMode::Reflink),
+            _ => Err(anyhow!("unknown copy mode {} (expected plain or reflink)", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OverrideMode {
+    Copy,
+    Link,
+    LinkDir,
+}
+
+impl FromStr for OverrideMode {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "copy" => Ok(OverrideMode::Copy),
+            "link" => Ok(OverrideMode::Link),
+            "link-dir" => Ok(OverrideMode::LinkDir),
+            _ => Err(anyhow!("unknown override mode {} (expected copy, link or link-dir)", value)),
+        }
+    }
+}
+
+/// A part of the game dir that can be duplicated alone (--only)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Section {
+    /// the whole game dir
+    Game,
+    Scripts,
+    Data,
+    Lang,
+    Movies,
+    Music,
+    Override,
+}
+
+impl FromStr for Section {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "game" => Ok(Section::Game),
+            "scripts" => Ok(Section::Scripts),
+            "data" => Ok(Section::Data),
+            "lang" => Ok(Section::Lang),
+            "movies" => Ok(Section::Movies),
+            "music" => Ok(Section::Music),
+            "override" => Ok(Section::Override),
+            _ => Err(anyhow!(
+                "unknown section {} (expected game, scripts, data, lang, movies, music or override)",
+                value
+            )),
+        }
+    }
+}
+
+impl Section {
+    /// the name of its dir in the game dir (the game dir itself for Game)
+    fn dir_name(self) -> &'static str {
+        match self {
+            Section::Game => "",
+            Section::Scripts => "scripts",
+            Section::Data => "data",
+            Section::Lang => "lang",
+            Section::Movies => "movies",
+            Section::Music => "music",
+            Section::Override => "override",
+        }
+    }
+}
+
+/// An entry of the root of the GOG installs, around the game dir, that can be left out of the target (--skip)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RootItem {
+    StartSh,
+    Gameinfo,
+    Support,
+    /// the dlc archives, at the root and in dlc/
+    Dlc,
+}
+
+impl FromStr for RootItem {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "start.sh" => Ok(RootItem::StartSh),
+            "gameinfo" => Ok(RootItem::Gameinfo),
+            "support" => Ok(RootItem::Support),
+            "dlc" => Ok(RootItem::Dlc),
+            _ => Err(anyhow!("unknown item {} (expected start.sh, gameinfo, support or dlc)", value)),
+        }
+    }
+}
+
+impl RootItem {
+    pub fn name(self) -> &'static str {
+        match self {
+            RootItem::StartSh => "start.sh",
+            RootItem::Gameinfo => "gameinfo",
+            RootItem::Support => "support",
+            RootItem::Dlc => "dlc",
+        }
+    }
+}
+
+/// how an item is linked when no rule says otherwise
+#[derive(Clone, Copy, PartialEq)]
+enum LinkKind {
+    Symbolic,
+    /// the files only, those of a dir are hard linked one by one
+    Hard,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// build a new target from scratch
+    Create,
+    /// add the missing items to an existing target
+    Merge,
+    /// only check that the target matches the source
+    Verify,
+    /// continue an interrupted run
+    Resume,
+}
+
+#[derive(Clone, Copy)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!("unknown color mode {} (expected auto, always or never)", value)),
+        }
+    }
+}
+
+impl ColorChoice {
+    fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty());
+                !no_color && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+static NO_EXT: Lazy<&OsStr> = Lazy::new(|| &OsStr::new(""));
+
+/// Files of the game dir that can be modified by the user or by mods, they are copied while the others are linked
+static EE_CONFIG_FILES: &[&str] = &["chitin.key", "engine.lua", "baldur.lua", "*.ini"];
+static CLASSIC_CONFIG_FILES: &[&str] = &["chitin.key", "dialog.tlk", "dialogF.tlk", "*.ini"];
+
+/// The config files of the game dir a source must have, by variant (chitin.key is how it's found), and those it should
+/// have, only warned about when missing (older EE builds have no engine.lua)
+static EE_REQUIRED_FILES: &[&str] = &["chitin.key"];
+static EE_EXPECTED_FILES: &[&str] = &["engine.lua"];
+static CLASSIC_REQUIRED_FILES: &[&str] = &["chitin.key", "dialog.tlk"];
+static CLASSIC_EXPECTED_FILES: &[&str] = &[];
+
+/// the files the game can't run without, an empty one is an error with --fail-on-empty
+static CRITICAL_FILES: &[&str] = &["chitin.key", "dialog.tlk", "dialogF.tlk"];
+
+/// how the files of music/ and of its song dirs are duplicated, by extension: the .mus playlists can be modded, the
+/// sounds are large. The [music] table of the config file comes first
+static MUSIC_EXTENSIONS: &[(&str, RuleAction)] = &[
+    ("mus", RuleAction::Copy),
+    ("acm", RuleAction::Link),
+    ("ogg", RuleAction::Link),
+    ("wav", RuleAction::Link),
+];
+
+/// what a link weighs in the progress by bytes, as many bytes copied: a link takes about the same time whatever the
+/// size of what it links to
+static LINK_WEIGHT: u64 = 64 * 1024;
+
+/// the copies always checked against their source (all of them are with --verify-copies)
+static VERIFIED_COPIES: &[&str] = &["chitin.key", "dialog.tlk", "dialogF.tlk", "engine.lua"];
+
+/// the files linked at the root of a windows game dir (the others are copied)
+static WINDOWS_BINARIES: &[&str] = &["exe", "dll"];
+
+/// the dirs process_game_dir knows how to duplicate (the files are linked, or copied if they are config files)
+static KNOWN_GAME_DIRS: &[&str] = &["Manuals", "scripts", "data", "lang", "movies", "music", "override"];
+
+/// the dirs of the game dir filled by process_game_subdir, the classic installs have no lang/ (dialog.tlk is at the
+/// root of their game dir)
+static EE_GAME_SECTIONS: &[Section] = &[Section::Scripts, Section::Data, Section::Lang, Section::Movies, Section::Music];
+static CLASSIC_GAME_SECTIONS: &[Section] = &[Section::Scripts, Section::Data, Section::Movies, Section::Music];
+
+/// the dirs of the classic installs that hold bifs or sounds (in any case), linked (their save, characters, cache...
+/// dirs are left out)
+static CLASSIC_LINKED_DIRS: &[&str] = &["cd1", "cd2", "cd3", "cd4", "cd5", "cd6", "sounds"];
+
+/// the languages of the EE releases, as named in lang/
+static KNOWN_LOCALES: &[&str] = &[
+    "cs_CZ", "de_DE", "en_US", "es_ES", "fr_FR", "hu_HU", "it_IT", "ja_JP", "ko_KR", "pl_PL", "pt_BR", "ru_RU", "tr_TR", "uk_UA", "zh_CN",
+];
+
+/// the subdirs of movies/ found in the releases, by resolution
+static KNOWN_MOVIE_DIRS: &[&str] = &["480", "lo"];
+
+/// the dlc archives of the GOG installs, at the root or in DLC_DIR
+static DEFAULT_DLC_PATTERN: &str = "*-dlc.zip";
+static DLC_DIR: &str = "dlc";
+
+/// added to the name of the files of the target kept by --backup
+static BACKUP_SUFFIX: &str = ".iedup.bak";
+
+/// Lists the items done by a run, so that it can be resumed if interrupted. Removed when the run completes.
+static STATE_FILE: &str = ".iedup-state";
+static STATE_HEADER: &str = "iedup state v1";
+
+/// Left by the file managers and the OS in any dir, a target with only those is still empty
+static JUNK_FILES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini", ".directory", ".Trashes"];
+
+/// Left by the Beamdog client at the root of its installs, with the build of the game
+static BEAMDOG_BUILD_ID: &str = ".build-id";
+
+/// Written at the end of a run with --write-manifest
+static MANIFEST_FILE: &str = "manifest.json";
+
+/// Written at the end of the runs that build a target, with the source it was built from
+static SOURCE_FILE: &str = ".iedup-source";
+
+/// What went wrong in a run, for the callers that react to it (the exit code of iedup)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    /// invalid arguments
+    Usage,
+    /// the source is missing or isn't a game install iedup knows about
+    InvalidSource,
+    /// the target (or the part of it to create) isn't empty
+    TargetNotEmpty,
+    /// some items failed with --continue-on-error, the others were duplicated
+    Partial,
+    /// the target or a copy doesn't match the source
+    Mismatch,
+    /// the source can't be read or the target can't be written by the current user
+    Permission,
+}
+
+/// Duplicates `config.source` into `config.target`, returns what was done
+pub fn run(config: Config) -> Result<Stats, IedupError> {
+    run_session(config).map_err(IedupError::from_any)
+}
+
+fn run_session(config: Config) -> Result<Stats> {
+    let start = Instant::now();
+    let (source, target_root) = (config.source.as_path(), config.target.as_path());
+
+    // ensure source and target are directories
+    if !source.is_dir() {
+        return Err(AnyError::new(IedupError::SourceNotDir { path: source.to_path_buf() }));
+    }
+    // created once the checks are done (verifying needs an existing target)
+    let create_target = config.create_target && !config.verify && !target_root.exists();
+    if !target_root.is_dir() && !create_target {
+        return Err(AnyError::new(IedupError::TargetNotDir {
+            path: target_root.to_path_buf(),
+        }));
+    }
+    check_not_swapped(source, target_root)?;
+    check_no_overlap(source, target_root)?;
+    let target = &match &config.into {
+        Some(subdir) => target_root.join(check_subdir_name(subdir)?),
+        None => target_root.to_path_buf(),
+    };
+
+    // --diff, --stats-only and relinking plan the run as for an empty target
+    let mode = if config.diff || config.stats_only || config.relink {
+        Mode::Create
+    } else if config.verify {
+        Mode::Verify
+    } else if config.merge {
+        Mode::Merge
+    } else if config.resume {
+        Mode::Resume
+    } else {
+        Mode::Create
+    };
+
+    // the access rights are checked before anything is done, a refused copy would only fail deep in the run
+    check_readable(source)?;
+    let writes = mode != Mode::Verify && !(config.dry_run || config.emit_script.is_some() || config.diff || config.stats_only);
+    if writes && target_root.is_dir() {
+        check_writable(target_root)?;
+    }
+    if let Some(writable_root) = &config.writable_root {
+        check_writable_root(source, target_root, writable_root)?;
+        if writes {
+            check_writable(writable_root)?;
+        }
+    }
+
+    // ensure source dir looks like an EE infinity engine directory we know about
+    let hint = guess_structure(source);
+    let description = check_source(&source, &hint).map_err(|error| IedupError::DetectionFailed {
+        path: source.to_path_buf(),
+        source: error.into(),
+    })?;
+    let mut config_file = match &config.config_file {
+        Some(path) => ConfigFile::read(path)?,
+        None => ConfigFile::default(),
+    };
+    let mut rules = parse_rule_args(&config.rules)?;
+    rules.append(&mut config_file.rules);
+    config_file.rules = rules;
+    let profile = config.profile.as_deref().map(profiles::find).transpose()?;
+    let mut exclude = config.exclude.clone();
+    let mut include_only = config.include_only.clone();
+    let mut override_mode = config.override_mode;
+    if let Some(profile) = profile {
+        exclude.extend(profile.exclude);
+        include_only.extend(profile.include_only);
+        override_mode = override_mode.or(profile.override_mode);
+        config_file.rules.extend(profile.rules);
+    }
+    if config.copy_support {
+        config_file.rules.insert(0, Rule::new("support", RuleAction::Copy)?);
+    }
+    if let Some(expected) = &config.expect_version {
+        check_version(&description, expected, config.loose_version)?;
+    }
+    if config.check_bifs {
+        check_bifs(&description)?;
+    }
+    if let Some(link_base) = &config.link_base {
+        check_link_base(source, &description, link_base)?;
+    }
+    if config.flatten
+        && description
+            .game_dir
+            .strip_prefix(source)
+            .map_or(true, |relative| relative.as_os_str().is_empty())
+    {
+        return Err(AnyError::new(IedupError::Usage(format!(
+            "--flatten needs a game/ dir, chitin.key is already at the root of {}",
+            source.to_string_lossy()
+        ))));
+    }
+    if let (Some(copy_under), Some(link_over)) = (config.copy_under, config.link_over) {
+        if link_over < copy_under {
+            return Err(AnyError::new(IedupError::Usage(format!(
+                "--link-over {} is less than --copy-under {}, a file can't be both",
+                link_over, copy_under
+            ))));
+        }
+    }
+
+    if config.relink && (config.verify || config.merge || config.resume || config.diff || config.stats_only || config.from_manifest.is_some()) {
+        return Err(AnyError::new(IedupError::Usage(String::from(
+            "dedup changes an existing target, it can't be used with --verify, --merge, --resume, --diff, --stats-only or --from-manifest",
+        ))));
+    }
+    if config.stats_only && (config.verify || config.merge || config.resume || config.diff) {
+        return Err(AnyError::new(IedupError::Usage(String::from(
+            "--stats-only sizes a new target, it can't be used with --verify, --merge, --resume or --diff",
+        ))));
+    }
+    if config.extract_dlc && mode != Mode::Create {
+        return Err(AnyError::new(IedupError::Usage(String::from(
+            "--extract-dlc builds a new target, it can't be used with --verify, --merge or --resume",
+        ))));
+    }
+    if config.from_manifest.is_some() && (mode != Mode::Create || config.diff || config.only.is_some() || config.writable_root.is_some()) {
+        return Err(AnyError::new(IedupError::Usage(String::from(
+            "--from-manifest builds a new target, it can't be used with --verify, --merge, --resume, --diff, --only or --writable-root",
+        ))));
+    }
+    let (manifest_entries, drift) = match &config.from_manifest {
+        Some(path) => {
+            let (entries, drift) = manifest_entries(path, source)?;
+            (Some(entries), drift)
+        }
+        None => (None, vec![]),
+    };
+
+    // resuming needs the state left by the interrupted run, without it we can only merge
+    let state_path = target.join(STATE_FILE);
+    let (mode, completed, state_error) = match mode {
+        Mode::Resume => match read_state(&state_path) {
+            Ok(completed) => (Mode::Resume, completed, None),
+            Err(error) => (Mode::Merge, HashSet::new(), Some(error)),
+        },
+        _ => (mode, HashSet::new(), None),
+    };
+
+    let plan_format = config.plan_format.unwrap_or(PlanFormat::Text);
+    let dry_run = config.dry_run || config.emit_script.is_some() || config.diff || config.stats_only || config.relink;
+    let plan_to_stdout = config.plan_to_stdout();
+    if create_target && !dry_run {
+        std::fs::create_dir_all(target_root).with_context(|| format!("failed to create the target {}", target_root.to_string_lossy()))?;
+    }
+    // fail fast when the target can't hold links (there is nothing to write in a dry run or when verifying)
+    let makes_links = !dry_run || (config.relink && !config.dry_run);
+    let links_unsupported = !config.copy_all && makes_links && mode != Mode::Verify && !supports_symlinks(target_root)?;
+    if links_unsupported && !config.auto_fallback {
+        return Err(AnyError::new(IedupError::LinksUnsupported {
+            path: target_root.to_path_buf(),
+        }));
+    }
+    // tried once, the reflinks need the source and the target on the same filesystem, one that has them
+    let reflink_error = if config.copy_mode == CopyMode::Reflink && !dry_run && mode != Mode::Verify {
+        supports_reflinks(&description.game_dir, target_root).err()
+    } else {
+        None
+    };
+    let progress = if !config.no_progress && !plan_to_stdout && std::io::stdout().is_terminal() {
+        let operations = match &manifest_entries {
+            Some(entries) => entries.iter().filter(|entry| entry.kind != ActionKind::CreateDir).count() as u64,
+            None => count_operations(source, &description.game_dir, config.max_depth)?,
+        };
+        let bar = ProgressBar::with_draw_target(operations, ProgressDrawTarget::stdout());
+        bar.set_style(ProgressStyle::default_bar().template("[{bar:40}] {pos}/{len} {wide_msg}"));
+        bar
+    } else {
+        ProgressBar::hidden()
+    };
+    let session = &Session {
+        progress,
+        progress_by_bytes: Cell::new(false),
+        color: config.color.use_color(),
+        // a dry run shows each action it would do, as -v does
+        verbosity: if config.quiet {
+            QUIET
+        } else if config.dry_run && plan_format == PlanFormat::Text && !config.relink {
+            config.verbose.max(1)
+        } else {
+            config.verbose
+        },
+        mode,
+        os: description.os,
+        variant: description.variant,
+        config_files: config_files(&description.variant)?,
+        preserve: config.preserve,
+        reflink: config.copy_mode == CopyMode::Reflink && reflink_error.is_none(),
+        override_mode: override_mode.unwrap_or(OverrideMode::Copy),
+        fail_on_empty: config.fail_on_empty,
+        follow_existing: config.follow_existing,
+        copy_all: config.copy_all || links_unsupported,
+        ignore_case: config.ignore_case || cfg!(any(target_os = "macos", target_os = "windows")),
+        languages: config.languages.clone(),
+        dry_run,
+        counting: Cell::new(false),
+        plan: RefCell::new(Vec::new()),
+        tally: if config.stats_only {
+            Some(RefCell::new(stats::Tally::default()))
+        } else {
+            None
+        },
+        exclude: parse_patterns(&exclude)?,
+        include_only: parse_patterns(&include_only)?,
+        include_parents: parse_patterns(&parent_patterns(&include_only))?,
+        dlc_patterns: if config.dlc_patterns.is_empty() {
+            parse_patterns(&[DEFAULT_DLC_PATTERN.to_string()])?
+        } else {
+            parse_patterns(&config.dlc_patterns)?
+        },
+        dlc_patterns_given: !config.dlc_patterns.is_empty(),
+        extract_dlc: config.extract_dlc,
+        dlc_archives: RefCell::new(Vec::new()),
+        skip: config.skip.clone(),
+        skip_lang_movies: config.skip_lang_movies,
+        skip_lang_sounds: config.skip_lang_sounds,
+        retries: config.retries,
+        retry_delay: config.retry_delay,
+        manifest_entries,
+        copy_under: config.copy_under,
+        link_over: config.link_over,
+        backup: config.backup,
+        config: config_file,
+        link_base: config.link_base.clone(),
+        writable_root: config.writable_root.as_deref().map(Path::canonicalize).transpose()?,
+        source_root: source.to_path_buf(),
+        target_root: target.to_path_buf(),
+        max_depth: config.max_depth,
+        verify_copies: config.verify_copies,
+        prune: config.prune && mode == Mode::Verify,
+        pruned: Cell::new(0),
+        only: config.only,
+        flatten: config.flatten,
+        lowercase_names: config.lowercase_names,
+        lowercased: RefCell::new(HashMap::new()),
+        ignore_files: RefCell::new(HashMap::new()),
+        chmod: config.chmod,
+        chown: config.chown.as_deref().map(Owner::parse).transpose()?,
+        chown_refused: Cell::new(false),
+        continue_on_error: config.continue_on_error,
+        errors: RefCell::new(Vec::new()),
+        warnings: RefCell::new(Vec::new()),
+        vanished: RefCell::new(Vec::new()),
+        timings: RefCell::new(Vec::new()),
+        since: config.since.filter(|_| mode == Mode::Merge),
+        refreshed: Cell::new(0),
+        unchanged: Cell::new(0),
+        completed,
+        state_file: OnceCell::new(),
+        problems: Cell::new(0),
+        local_additions: Cell::new(0),
+    };
+    session.info(format!(
+        "detected {} ({} bifs, {} resources)",
+        description.game,
+        description.key.bif_names.len(),
+        description.key.resource_count
+    ));
+    if let Some(profile) = &config.profile {
+        session.info(format!("using profile {}", profile));
+    }
+    if description.vendor == Vendor::Steam {
+        match &description.manifest {
+            Some(manifest) => session.info(format!("using Steam manifest {}", manifest.to_string_lossy())),
+            None => session.info(format!("no Steam manifest found for {}", source.to_string_lossy())),
+        }
+    }
+    if create_target && dry_run {
+        session.info(format!("target {} doesn't exist, it would be created", target_root.to_string_lossy()));
+    } else if create_target {
+        session.info(format!("target {} didn't exist, created", target_root.to_string_lossy()));
+    }
+    if links_unsupported {
+        session.warn(format!(
+            "target {} can't hold symbolic links, copying everything",
+            target_root.to_string_lossy()
+        ));
+    }
+    if let Some(error) = state_error {
+        session.warn(format!("can't resume ({}), merging instead", error));
+    }
+    match reflink_error {
+        Some(error) => session.warn(format!(
+            "can't make reflinks of {} in {} ({}), copying plainly",
+            source.to_string_lossy(),
+            target_root.to_string_lossy(),
+            error
+        )),
+        None if session.reflink => session.info("the copies are reflinks"),
+        None => {}
+    }
+
+    // iedup only reads the source, read-only media are fine
+    if let Some(mount) = source.canonicalize().ok().and_then(|source| mounts::mount_of(&source)) {
+        if mount.read_only {
+            session.info(format!("source {} is read-only, that's fine", source.to_string_lossy()));
+        }
+        if mount.is_removable() && !config.copy_all {
+            session.warn(format!(
+                "source {} is on removable media, the links will break when it's unmounted (use --copy-all to copy everything)",
+                source.to_string_lossy()
+            ));
+        }
+    }
+    check_game_entries(session, &description.game_dir, config.strict)?;
+    if !drift.is_empty() {
+        let manifest_path = config.from_manifest.as_deref().unwrap_or_else(|| Path::new(""));
+        if !config.allow_drift {
+            for change in &drift {
+                session.error(format!("  {}", change));
+            }
+            return Err(AnyError::new(IedupError::Mismatch(format!(
+                "{} item(s) of the manifest {} don't match the source anymore (--allow-drift leaves them out)",
+                drift.len(),
+                manifest_path.to_string_lossy()
+            ))));
+        }
+        for change in &drift {
+            session.warn(format!("leave out {}", change));
+        }
+    }
+    if config.diff {
+        return diff_target(session, &description, source, target, config.fail_on_diff);
+    }
+    if config.stats_only {
+        return print_sizes(session, &description, source, target);
+    }
+    if config.relink {
+        return relink_copies(session, &description, source, target, config.dry_run);
+    }
+    let interactive = config.interactive && !dry_run && mode != Mode::Verify;
+    let check_space = !config.ignore_space && !dry_run && mode != Mode::Verify;
+    if config.max_links.is_some() || interactive || check_space {
+        let planned = count_planned(session, &description, source, target)?;
+        if !session.progress.is_hidden() {
+            // with the bytes to copy, the bar can tell the time left
+            let total = stats::total(&planned);
+            session.progress.set_length(total.bytes + total.linked * LINK_WEIGHT);
+            session
+                .progress
+                .set_style(ProgressStyle::default_bar().template("[{bar:40}] {percent:>3}% {eta} left {wide_msg}"));
+            session.progress_by_bytes.set(true);
+        }
+        if let Some(max_links) = config.max_links {
+            check_link_count(session, &planned, max_links)?;
+        }
+        if check_space {
+            match mounts::available_space(target_root) {
+                Ok(available) => check_free_space(stats::total(&planned).bytes, available)?,
+                Err(error) => session.warn(format!("can't check the free space of the target: {}", error)),
+            }
+        }
+        if interactive {
+            confirm(session, &planned, target, config.yes, config.confirm.as_ref())?;
+        }
+    }
+
+    if config.into.is_some() {
+        session.info(format!("building into {}", target.to_string_lossy()));
+        if target.exists() {
+            if mode == Mode::Create && !config.force {
+                return Err(AnyError::new(IedupError::AlreadyExists { path: target.to_path_buf() }));
+            }
+        } else if mode != Mode::Create {
+            return Err(AnyError::new(IedupError::TargetNotDir { path: target.to_path_buf() }));
+        } else if session.plan(ActionKind::CreateDir, None, target) {
+            std::fs::create_dir(target).with_context(|| format!("failed to create directory {}", target.to_string_lossy()))?;
+        }
+    }
+
+    // with --only, only the dir of the section must be new
+    let section_target = config
+        .only
+        .map(|section| section_path(&description, section, source, target, config.flatten));
+    if let (Some(section_target), Mode::Create) = (&section_target, mode) {
+        if section_target != target {
+            prepare_section_target(session, section_target, config.force)?;
+        }
+    }
+
+    // ensure target is empty (unless we want to complete or check an existing one)
+    // (with --into, a dry run doesn't create it)
+    if mode == Mode::Create && target.is_dir() && section_target.map_or(true, |section_target| section_target == *target) {
+        let mut target_files = read_dir(target)?.filter(|entry| match entry {
+            Ok(entry) => config.strict_empty || !JUNK_FILES.iter().any(|junk| entry.file_name() == *junk),
+            Err(_) => true,
+        });
+        if target_files.next().is_some() {
+            if !config.force {
+                return Err(AnyError::new(IedupError::TargetNotEmpty { path: target.to_path_buf() }));
+            }
+            clean_target(session, target)?;
+        }
+    }
+    if mode != Mode::Verify && !dry_run {
+        let state_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&state_path)
+            .with_context(|| format!("failed to open state file {}", state_path.to_string_lossy()))?;
+        if state_file.metadata()?.len() == 0 {
+            writeln!(&state_file, "{}", STATE_HEADER)?;
+        }
+        let _ = session.state_file.set(state_file);
+    }
+
+    // the report tells how the run ended, whatever it failed at (the error of the run stays the one returned)
+    let mut all_stats = BTreeMap::new();
+    let mut total = Stats::default();
+    let result = (|| -> Result<()> {
+        process_source(session, &description, source, target)?;
+
+        session.progress.finish_and_clear();
+        let deduped = if config.dedup_copies { dedup_copies(session)? } else { 0 };
+        if mode != Mode::Verify && !dry_run {
+            check_dangling_links(session, config.fail_on_dangling)?;
+            check_escaping_links(session, source, config.link_base.as_deref(), config.strict)?;
+        }
+        if config.sanity_check && !dry_run {
+            sanity_check(session, &description, source, target, config.flatten)?;
+        }
+        all_stats = stats::collect(&session.plan.borrow(), target, session.writable_root.as_deref());
+        if mode != Mode::Verify {
+            for line in stats::table(&all_stats) {
+                session.info(line);
+            }
+        }
+        let problems = session.problems.get();
+        total = stats::total(&all_stats);
+        total.deduped_bytes = deduped;
+        let done = if dry_run {
+            format!("{} to copy, {} to link, {} dir(s) to create", total.copied, total.linked, total.dirs)
+        } else {
+            format!("{} copied, {} linked, {} dir(s) created", total.copied, total.linked, total.dirs)
+        };
+        match mode {
+            Mode::Create => session.info_at(QUIET, format!("{} in {}", done, target.to_string_lossy())),
+            Mode::Merge if session.since.is_some() => session.info_at(
+                QUIET,
+                format!(
+                    "{}, {} copies refreshed, {} unchanged, {} local addition(s) kept in {}",
+                    done,
+                    session.refreshed.get(),
+                    session.unchanged.get(),
+                    session.local_additions.get(),
+                    target.to_string_lossy()
+                ),
+            ),
+            Mode::Merge | Mode::Resume => session.info_at(
+                QUIET,
+                format!(
+                    "{}, {} local addition(s) kept in {}",
+                    done,
+                    session.local_additions.get(),
+                    target.to_string_lossy()
+                ),
+            ),
+            Mode::Verify if session.prune => session.info_at(
+                QUIET,
+                format!(
+                    "{} problem(s), {} local addition(s), {} stale link(s) pruned in {}",
+                    problems,
+                    session.local_additions.get(),
+                    session.pruned.get(),
+                    target.to_string_lossy()
+                ),
+            ),
+            Mode::Verify => session.info_at(
+                QUIET,
+                format!(
+                    "{} problem(s), {} local addition(s) in {}",
+                    problems,
+                    session.local_additions.get(),
+                    target.to_string_lossy()
+                ),
+            ),
+        }
+        if !config.skip.is_empty() {
+            let skipped: Vec<&str> = config.skip.iter().map(|item| item.name()).collect();
+            session.info_at(QUIET, format!("skipped {}", skipped.join(", ")));
+        }
+        if !session.vanished.borrow().is_empty() {
+            let vanished = session.vanished.borrow();
+            session.info_at(
+                QUIET,
+                format!(
+                    "{} file(s) vanished from the source during the run, skipped: {}",
+                    vanished.len(),
+                    vanished.join(", ")
+                ),
+            );
+        }
+        let errors = session.errors.borrow();
+        let failed = if !errors.is_empty() {
+            session.error(format!("{} error(s):", errors.len()));
+            for error in errors.iter() {
+                session.error(format!("  {}", error));
+            }
+            Some(AnyError::new(IedupError::Partial {
+                failed: errors.len(),
+                target: target.to_path_buf(),
+            }))
+        } else if problems > 0 {
+            Some(AnyError::new(IedupError::Mismatch(format!(
+                "target {} doesn't match source {}",
+                target.to_string_lossy(),
+                source.to_string_lossy()
+            ))))
+        } else {
+            None
+        };
+        if let Some(error) = failed {
+            return Err(error);
+        }
+        if plan_format == PlanFormat::Json {
+            if let Some(output) = &config.plan_output {
+                output(&format!("{}\n", plan::to_json(&session.plan.borrow())));
+            }
+        }
+        if let Some(shell) = config.emit_script {
+            let copy_command = match config.preserve {
+                Preserve::All => "cp -p",
+                Preserve::Perms => "cp --preserve=mode",
+                Preserve::None => "cp",
+            };
+            // cp makes a plain copy when it can't make a reflink
+            let copy_command = match config.copy_mode {
+                CopyMode::Reflink => format!("{} --reflink=auto", copy_command),
+                CopyMode::Plain => copy_command.to_string(),
+            };
+            let script = plan::to_script(&session.plan.borrow(), shell, &copy_command);
+            match &config.script_out {
+                Some(path) => std::fs::write(path, script).with_context(|| format!("failed to write the script {}", path.to_string_lossy()))?,
+                None => {
+                    if let Some(output) = &config.plan_output {
+                        output(&script);
+                    }
+                }
+            }
+        }
+        if config.write_manifest {
+            let game = manifest::GameInfo {
+                id: description.game.to_string(),
+                name: description.name.as_deref(),
+                version: description.version.as_deref(),
+                build: description.build.as_deref(),
+            };
+            let manifest_path = target.join(MANIFEST_FILE);
+            std::fs::write(&manifest_path, manifest::to_json(&game, source, &session.plan.borrow(), target))
+                .with_context(|| format!("failed to write the manifest {}", manifest_path.to_string_lossy()))?;
+        }
+        if mode != Mode::Verify && !dry_run {
+            let source_path = target.join(SOURCE_FILE);
+            let canonical = source
+                .canonicalize()
+                .with_context(|| format!("failed to resolve {}", source.to_string_lossy()))?;
+            std::fs::write(&source_path, origin::to_toml(&canonical, &description, &config))
+                .with_context(|| format!("failed to write {}", source_path.to_string_lossy()))?;
+            // everything is done, nothing to resume
+            std::fs::remove_file(&state_path)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() && all_stats.is_empty() {
+        // what was done before the failure
+        all_stats = stats::collect(&session.plan.borrow(), target, session.writable_root.as_deref());
+        total = stats::total(&all_stats);
+    }
+    set_timings(session, &mut total, start);
+    if let Some(path) = &config.report_json {
+        let written = write_report(session, path, &description, &all_stats, &total, result.as_ref().err());
+        match (&result, written) {
+            (Err(_), Err(report_error)) => session.error(format!("{:#}", report_error)),
+            (Ok(_), Err(report_error)) => return Err(report_error),
+            _ => {}
+        }
+    }
+    result?;
+    if config.timings {
+        for line in stats::timings(&total) {
+            session.info_at(QUIET, line);
+        }
+    }
+    Ok(total)
+}
+
+/// Sets the time of each phase and of the whole run in `total`
+fn set_timings(session: &Session, total: &mut Stats, start: Instant) {
+    total.phases = session
+        .timings
+        .borrow()
+        .iter()
+        .map(|(name, duration)| (name.to_string(), *duration))
+        .collect();
+    total.elapsed = start.elapsed();
+}
+
+/// Writes the summary of the run (--report-json), with the error it ends with if any
+fn write_report(
+    session: &Session,
+    path: &Path,
+    description: &GameDescription,
+    all_stats: &BTreeMap<String, Stats>,
+    total: &Stats,
+    error: Option<&AnyError>,
+) -> Result<()> {
+    let report = report::Report {
+        error: error.map(|error| format!("{:#}", error)),
+        game: description,
+        categories: all_stats,
+        total,
+        warnings: &session.warnings.borrow(),
+        errors: &session.errors.borrow(),
+        vanished: &session.vanished.borrow(),
+    };
+    std::fs::write(path, report::to_json(&report)).with_context(|| format!("failed to write the report {}", path.to_string_lossy()))
+}
+
+/// Reports the links created by the run that don't lead to an existing file (a wrong source or --link-base)
+fn check_dangling_links(session: &Session, fail_on_dangling: bool) -> Result<()> {
+    let mut dangling = 0;
+    for action in session.plan.borrow().iter().filter(|action| action.kind == ActionKind::Link) {
+        // metadata() follows the link
+        if action.target.metadata().is_err() {
+            dangling += 1;
+            let link = std::fs::read_link(&action.target).unwrap_or_default();
+            session.error(format!("dangling link {} -> {}", action.target.to_string_lossy(), link.to_string_lossy()));
+        }
+    }
+    if dangling > 0 && fail_on_dangling {
+        return Err(anyhow!("{} dangling link(s) in the target", dangling));
+    }
+    Ok(())
+}
+
+/// Reports the links created that resolve out of the source (and of --link-base), through a link of the source to
+/// elsewhere (a bug, or an untrusted install pointing the target to other files). Fails with `strict`
+fn check_escaping_links(session: &Session, source: &Path, link_base: Option<&Path>, strict: bool) -> Result<()> {
+    let mut roots = vec![source
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", source.to_string_lossy()))?];
+    if let Some(link_base) = link_base {
+        roots.push(
+            link_base
+                .canonicalize()
+                .with_context(|| format!("failed to resolve {}", link_base.to_string_lossy()))?,
+        );
+    }
+    // the links to the copies (canonical already)
+    roots.extend(session.writable_root.clone());
+    let mut escaping = 0;
+    for action in session.plan.borrow().iter().filter(|action| action.kind == ActionKind::Link) {
+        // the dangling links were reported already
+        if let Ok(resolved) = action.target.canonicalize() {
+            if !roots.iter().any(|root| resolved.starts_with(root)) {
+                escaping += 1;
+                session.error(format!(
+                    "link {} leads out of the source, to {}",
+                    action.target.to_string_lossy(),
+                    resolved.to_string_lossy()
+                ));
+            }
+        }
+    }
+    if escaping > 0 && strict {
+        return Err(AnyError::new(IedupError::LinksOutOfSource {
+            count: escaping,
+            path: source.to_path_buf(),
+        }));
+    }
+    Ok(())
+}
+
+/// Fails with the list of what's missing if the target lacks what the game needs to start (--sanity-check)
+fn sanity_check(session: &Session, description: &GameDescription, source: &Path, target: &Path, flatten: bool) -> Result<()> {
+    let missing = missing_for_launch(description, source, target, flatten)?;
+    if missing.is_empty() {
+        session.info_at(QUIET, format!("{}", session.paint(Green.normal(), "target looks launch-ready")));
+        return Ok(());
+    }
+    for entry in &missing {
+        session.error(format!("missing or broken: {}", entry));
+    }
+    Err(AnyError::new(IedupError::NotLaunchReady {
+        path: target.to_path_buf(),
+        missing,
+    }))
+}
+
+/// The entries needed to start the game that are missing in the target, or links to nothing
+fn missing_for_launch(description: &GameDescription, source: &Path, target: &Path, flatten: bool) -> Result<Vec<String>> {
+    let game_dir = game_target(description, source, target, flatten);
+    let mut missing = Vec::new();
+    // is_file() follows the links
+    let has_file = |name: &str| find_ignoring_case(&game_dir, name).map_or(false, |path| path.is_file());
+    if game_dir != target || flatten {
+        if !target.join("start.sh").is_file() {
+            missing.push(String::from("start.sh"));
+        }
+    } else {
+        let is_executable = |entry: &DirEntry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_known = GAME_SIGNATURES.iter().any(|(executable, _)| name.eq_ignore_ascii_case(executable));
+            (is_known || (description.os == Os::Win && has_extension(&name, "exe"))) && entry.path().is_file()
+        };
+        if !read_dir(&game_dir)?.flatten().any(|entry| is_executable(&entry)) {
+            missing.push(String::from("the game executable"));
+        }
+    }
+    // the expected files the source doesn't have can't be missing
+    let (required, expected) = game_files(description.variant);
+    let in_source = |name: &&&str| find_ignoring_case(&description.game_dir, name).map_or(false, |path| path.is_file());
+    for name in required.iter().chain(expected.iter().filter(in_source)) {
+        if !has_file(name) {
+            missing.push(relative_name(&game_dir, target, name));
+        }
+    }
+    if description.variant == Variant::Classic {
+        return Ok(missing);
+    }
+    let lang = game_dir.join("lang");
+    let has_dialog = lang.is_dir()
+        && read_dir(&lang)?
+            .flatten()
+            .any(|language| find_ignoring_case(&language.path(), "dialog.tlk").map_or(false, |path| path.is_file()));
+    if !has_dialog {
+        missing.push(relative_name(&game_dir, target, "lang/*/dialog.tlk"));
+    }
+    Ok(missing)
+}
+
+/// `name` in `dir`, relative to `root`
+fn relative_name(dir: &Path, root: &Path, name: &str) -> String {
+    dir.join(name)
+        .strip_prefix(root)
+        .map_or_else(|_| name.to_string(), |path| path.to_string_lossy().into_owned())
+}
+
+/// Replaces the files copied by the run that have the same content by hard links, returns the space saved
+fn dedup_copies(session: &Session) -> Result<u64> {
+    let copies: Vec<PathBuf> = session
+        .plan
+        .borrow()
+        .iter()
+        .filter(|action| action.kind == ActionKind::Copy)
+        .map(|action| action.target.clone())
+        .collect();
+    let duplicates = dedup::find_duplicates(&copies)?;
+    for duplicate in &duplicates {
+        session.info_at(
+            1,
+            format!(
+                "hardlink {} to {}",
+                session.paint(Blue.bold(), duplicate.original.to_string_lossy()),
+                session.paint(Green.normal(), duplicate.path.to_string_lossy())
+            ),
+        );
+        // link beside the duplicate then replace it, so that it's never missing
+        let mut temporary = duplicate.path.clone().into_os_string();
+        temporary.push(".iedup-dedup");
+        std::fs::hard_link(&duplicate.original, &temporary)
+            .and_then(|_| std::fs::rename(&temporary, &duplicate.path))
+            .with_context(|| {
+                format!(
+                    "failed to hardlink {} to {}",
+                    duplicate.original.to_string_lossy(),
+                    duplicate.path.to_string_lossy()
+                )
+            })?;
+    }
+    let saved = duplicates.iter().map(|duplicate| duplicate.size).sum();
+    session.info(format!(
+        "{} duplicate copies replaced by hard links, {} saved",
+        duplicates.len(),
+        stats::human_size(saved)
+    ));
+    Ok(saved)
+}
+
+/// Duplicates the whole source in the target
+fn process_source(session: &Session, description: &GameDescription, source: &Path, target: &Path) -> Result<()> {
+    if let Some(entries) = &session.manifest_entries {
+        return apply_manifest(session, entries, target);
+    }
+    if let Some(section) = session.only {
+        return process_section(session, description, section, source, target);
+    }
+    match (description.os, description.vendor) {
+        (Os::Win, _) | (_, Vendor::Steam) | (_, Vendor::Beamdog) => {
+            // no launcher script around the game, the install dir is the game dir
+            process_game_dir(session, source, target)?;
+        }
+        _ => {
+            // root dir : copy start.sh (allows user modification), link support/ and gameinfo (no changes expected,
+            // unless a rule says otherwise), create game/
+
+            if !session.skip.contains(&RootItem::StartSh) {
+                copy_item(session, source, target, "start.sh")?;
+            }
+            for item in &[RootItem::Gameinfo, RootItem::Support] {
+                if session.skip.contains(item) {
+                    continue;
+                }
+                let action = session.rule_for(&source.join(item.name())).unwrap_or(RuleAction::Link);
+                apply_rule(session, source, target, OsStr::new(item.name()), action)?;
+            }
+            if !session.skip.contains(&RootItem::Dlc) {
+                process_dlc(session, source, target)?;
+            }
+            if session.flatten {
+                // the local additions are found with those of the game dir, in the same dir of the target
+                process_game_dir(session, &source.join("game"), target)?;
+                return extract_dlcs(session, &source.join("game"), target);
+            }
+            if create_dir_str(session, source, target, "game")? {
+                process_game_dir(session, &source.join("game"), &target.join("game"))?;
+                extract_dlcs(session, &source.join("game"), &target.join("game"))?;
+            }
+            check_local_additions(session, source, target, &[STATE_FILE, MANIFEST_FILE, SOURCE_FILE])?;
+        }
+    }
+    Ok(())
+}
+
+/// where the game dir of the source is in the target (its root with --flatten)
+fn game_target(description: &GameDescription, source: &Path, target: &Path, flatten: bool) -> PathBuf {
+    if flatten {
+        return target.to_path_buf();
+    }
+    target.join(description.game_dir.strip_prefix(source).unwrap_or_else(|_| Path::new("")))
+}
+
+/// where `section` is in the target
+fn section_path(description: &GameDescription, section: Section, source: &Path, target: &Path, flatten: bool) -> PathBuf {
+    game_target(description, source, target, flatten).join(section.dir_name())
+}
+
+/// With --only, makes room for the section in an existing target: its dir must be missing or empty (or is removed
+/// with --force)
+fn prepare_section_target(session: &Session, section_target: &Path, force: bool) -> Result<()> {
+    let metadata = match section_target.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    let is_empty_dir = metadata.is_dir() && read_dir(section_target)?.next().is_none();
+    if !is_empty_dir {
+        if !force {
+            return Err(AnyError::new(IedupError::AlreadyExists {
+                path: section_target.to_path_buf(),
+            }));
+        }
+        session.warn(format!("remove {}", section_target.to_string_lossy()));
+    }
+    // an empty dir is removed too, to be created like in a new target
+    if session.plan(ActionKind::Remove, None, section_target) {
+        if metadata.is_dir() {
+            std::fs::remove_dir_all(section_target)
+        } else {
+            std::fs::remove_file(section_target)
+        }
+        .with_context(|| format!("failed to remove {}", section_target.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// Duplicates only `section` of the game dir (--only), the game dir of the target is created if needed
+fn process_section(session: &Session, description: &GameDescription, section: Section, source: &Path, target: &Path) -> Result<()> {
+    let relative = description.game_dir.strip_prefix(source).unwrap_or_else(|_| Path::new(""));
+    let (source_game, target_game) = (source.join(relative), game_target(description, source, target, session.flatten));
+    if section == Section::Lang && description.variant == Variant::Classic {
+        return Err(AnyError::new(IedupError::UnsupportedVariant {
+            path: source.to_path_buf(),
+            missing: "lang/",
+        }));
+    }
+    if section == Section::Game {
+        if target_game == target || create_dir_os(session, source, target, relative.as_os_str())? {
+            process_game_dir(session, &source_game, &target_game)?;
+        }
+        return Ok(());
+    }
+    if !target_game.is_dir() && session.plan(ActionKind::CreateDir, Some(&source_game), &target_game) {
+        std::fs::create_dir_all(&target_game).with_context(|| format!("failed to create directory {}", target_game.to_string_lossy()))?;
+    }
+    match section {
+        Section::Override => session.timed("override", || process_override(session, &source_game, &target_game)),
+        _ => process_game_subdir(session, &source_game, &target_game, section),
+    }
+}
+
+/// The entries of the manifest at `path`, with their sources moved from the source of the manifest to `source`, and
+/// the descriptions of those left out because their source is gone or changed since (with the entries below them)
+fn manifest_entries(path: &Path, source: &Path) -> Result<(Vec<manifest::Entry>, Vec<String>)> {
+    let manifest = manifest::read(path)?;
+    let mut entries = Vec::new();
+    let mut drift = Vec::new();
+    let mut left_out: Vec<PathBuf> = Vec::new();
+    for mut entry in manifest.entries {
+        if left_out.iter().any(|dir| entry.path.starts_with(dir)) {
+            continue;
+        }
+        if let (Some(entry_source), Some(manifest_source)) = (&entry.source, &manifest.source) {
+            // a link to --link-base stays as it is
+            if let Ok(relative) = entry_source.strip_prefix(manifest_source) {
+                entry.source = Some(source.join(relative));
+            }
+        }
+        let change = match &entry.source {
+            // the file of an archive is read when it's extracted
+            Some(entry_source) if entry.kind == ActionKind::Extract => match dlc::split_source(entry_source) {
+                None => Some(format!(
+                    "{}, its source {} is gone",
+                    entry.path.to_string_lossy(),
+                    entry_source.to_string_lossy()
+                )),
+                Some(_) => None,
+            },
+            Some(entry_source) => match entry_source.metadata() {
+                Err(_) => Some(format!(
+                    "{}, its source {} is gone",
+                    entry.path.to_string_lossy(),
+                    entry_source.to_string_lossy()
+                )),
+                Ok(metadata) if entry.kind == ActionKind::Copy && entry.size.map_or(false, |size| size != metadata.len()) => Some(format!(
+                    "{}, its source {} changed size",
+                    entry.path.to_string_lossy(),
+                    entry_source.to_string_lossy()
+                )),
+                Ok(_) => None,
+            },
+            None => None,
+        };
+        match change {
+            Some(change) => {
+                drift.push(change);
+                left_out.push(entry.path);
+            }
+            None => entries.push(entry),
+        }
+    }
+    Ok((entries, drift))
+}
+
+/// Creates the entries of a manifest (--from-manifest) in `target`, in their order
+fn apply_manifest(session: &Session, entries: &[manifest::Entry], target: &Path) -> Result<()> {
+    // the dlc archives opened for their files
+    let mut archives = HashMap::new();
+    for entry in entries {
+        session.tolerate(apply_entry(session, entry, target, &mut archives))?;
+    }
+    Ok(())
+}
+
+fn apply_entry(session: &Session, entry: &manifest::Entry, target: &Path, archives: &mut HashMap<PathBuf, dlc::Archive>) -> Result<()> {
+    let target_item = target.join(&entry.path);
+    match (entry.kind, &entry.source) {
+        (ActionKind::Extract, Some(source_item)) => {
+            session.info_at(1, format!("extract {}", session.paint(Green.normal(), target_item.to_string_lossy())));
+            let (archive_path, path) = dlc::split_source(source_item).ok_or_else(|| anyhow!("no archive for {}", source_item.to_string_lossy()))?;
+            let archive = match archives.entry(archive_path.to_path_buf()) {
+                std::collections::hash_map::Entry::Occupied(occupied) => occupied.into_mut(),
+                std::collections::hash_map::Entry::Vacant(vacant) => vacant.insert(dlc::Archive::open(archive_path)?),
+            };
+            let archive_entry = archive.entry(path)?;
+            if session.plan_extract(source_item, &target_item, archive_entry.size) {
+                archive.extract(&archive_entry, &target_item)?;
+                session.set_permissions(&target_item, false)?;
+            }
+            session.item_done();
+        }
+        (ActionKind::Copy, Some(source_item)) => {
+            session.info_at(
+                1,
+                format!(
+                    "copy {} to {}",
+                    session.paint(Blue.bold(), source_item.to_string_lossy()),
+                    session.paint(Green.normal(), target_item.to_string_lossy())
+                ),
+            );
+            if session.plan(ActionKind::Copy, Some(source_item), &target_item) {
+                let what = || format!("copy {}", target_item.to_string_lossy());
+                session
+                    .retry(what, || copy_file(session.preserve, session.reflink, source_item, &target_item))
+                    .map_err(|error| IedupError::CopyFailed {
+                        path: target_item.clone(),
+                        original: source_item.clone(),
+                        source: error,
+                    })?;
+                session.set_permissions(&target_item, false)?;
+                if session.verify_copies {
+                    check_copy(source_item, &target_item)?;
+                }
+            }
+            session.item_done();
+        }
+        (ActionKind::Link, Some(source_item)) => {
+            session.info_at(
+                1,
+                format!(
+                    "link {} to {}",
+                    session.paint(Blue.bold(), source_item.to_string_lossy()),
+                    session.paint(Green.normal(), target_item.to_string_lossy())
+                ),
+            );
+            if session.plan(ActionKind::Link, Some(source_item), &target_item) {
+                let what = || format!("link {}", target_item.to_string_lossy());
+                session
+                    .retry(what, || std::os::unix::fs::symlink(source_item, &target_item))
+                    .map_err(|error| IedupError::LinkFailed {
+                        path: target_item.clone(),
+                        original: source_item.clone(),
+                        source: error,
+                    })?;
+            }
+            session.item_done();
+        }
+        (ActionKind::Hardlink, Some(source_item)) => {
+            session.info_at(
+                1,
+                format!(
+                    "hardlink {} to {}",
+                    session.paint(Blue.bold(), source_item.to_string_lossy()),
+                    session.paint(Green.normal(), target_item.to_string_lossy())
+                ),
+            );
+            if session.plan(ActionKind::Hardlink, Some(source_item), &target_item) {
+                let what = || format!("hard link {}", target_item.to_string_lossy());
+                session
+                    .retry(what, || std::fs::hard_link(source_item, &target_item))
+                    .map_err(|error| IedupError::LinkFailed {
+                        path: target_item.clone(),
+                        original: source_item.clone(),
+                        source: error,
+                    })?;
+            }
+            session.item_done();
+        }
+        (_, source_dir) => {
+            if session.plan(ActionKind::CreateDir, source_dir.as_deref(), &target_item) {
+                std::fs::create_dir(&target_item).with_context(|| format!("failed to create directory {}", target_item.to_string_lossy()))?;
+                session.set_permissions(&target_item, true)?;
+            }
+        }
+    }
+    session.record_done(&target_item)
+}
+
+/// What the run will do by category, from a silent planning pass
+fn count_planned(session: &Session, description: &GameDescription, source: &Path, target: &Path) -> Result<BTreeMap<String, Stats>> {
+    session.counting.set(true);
+    let result = process_source(session, description, source, target);
+    session.counting.set(false);
+    let actions = session.plan.replace(Vec::new());
+    session.problems.set(0);
+    session.local_additions.set(0);
+    // read again, to report the invalid ones
+    session.ignore_files.borrow_mut().clear();
+    result?;
+    Ok(stats::collect(&actions, target, session.writable_root.as_deref()))
+}
+
+/// Prints the sizes a run would copy and link by category and by extension (--stats-only), from a silent planning pass
+/// that sums them as it goes
+fn print_sizes(session: &Session, description: &GameDescription, source: &Path, target: &Path) -> Result<Stats> {
+    session.counting.set(true);
+    let result = process_source(session, description, source, target);
+    session.counting.set(false);
+    result?;
+    session.progress.finish_and_clear();
+    let tally = match &session.tally {
+        Some(tally) => tally.replace(stats::Tally::default()),
+        None => return Err(anyhow!("no sizes summed")),
+    };
+    for line in stats::size_table("category", &tally.categories) {
+        session.info_at(QUIET, line);
+    }
+    for line in stats::size_table("extension", &tally.extensions) {
+        session.info_at(QUIET, line);
+    }
+    let (total, files) = (stats::total(&tally.categories), stats::total(&tally.extensions));
+    session.info_at(
+        QUIET,
+        format!(
+            "{} in {} file(s) in {}: {} to copy ({} file(s)), {} to link ({} file(s), in {} link(s))",
+            stats::human_size(total.bytes + total.linked_bytes),
+            files.copied + files.linked,
+            source.to_string_lossy(),
+            stats::human_size(total.bytes),
+            files.copied,
+            stats::human_size(total.linked_bytes),
+            files.linked,
+            total.linked
+        ),
+    );
+    Ok(total)
+}
+
+/// Replaces the copies of `target` that a run would link by links to their source (iedup dedup), when they have the
+/// content of their source: the copies modified since and those a run would make too are left alone. The files of the
+/// dirs a run would link as a whole are linked one by one. Returns the links made, with the space reclaimed
+fn relink_copies(session: &Session, description: &GameDescription, source: &Path, target: &Path, dry_run: bool) -> Result<Stats> {
+    session.counting.set(true);
+    let result = process_source(session, description, source, target);
+    session.counting.set(false);
+    result?;
+    session.progress.finish_and_clear();
+    let actions = session.plan.replace(Vec::new());
+    let mut candidates = Vec::new();
+    for action in actions.iter().filter(|action| action.kind == ActionKind::Link) {
+        let source_item = match &action.source {
+            Some(source_item) => source_item,
+            None => continue,
+        };
+        if source_item.is_dir() {
+            if action.target.symlink_metadata().map_or(false, |metadata| metadata.is_dir()) {
+                add_copies_of_dir(&action.target, &action.target, source_item, &mut candidates)?;
+            }
+        } else {
+            candidates.push((action.target.clone(), source_item.clone()));
+        }
+    }
+    let (mut relinked, mut modified, mut reclaimed) = (0, 0, 0);
+    for (copy, original) in &candidates {
+        // missing, or already a link
+        let size = match copy.symlink_metadata() {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => continue,
+        };
+        if !original.is_file() || diff::differs(original, copy)? {
+            session.info_at(
+                1,
+                format!("keep {}, it differs from {}", copy.to_string_lossy(), original.to_string_lossy()),
+            );
+            modified += 1;
+            continue;
+        }
+        session.info_at(
+            1,
+            format!(
+                "link {} to {}",
+                session.paint(Blue.bold(), original.to_string_lossy()),
+                session.paint(Green.normal(), copy.to_string_lossy())
+            ),
+        );
+        if !dry_run {
+            // link beside the copy then replace it, so that it's never missing
+            let mut temporary = copy.clone().into_os_string();
+            temporary.push(".iedup-relink");
+            std::os::unix::fs::symlink(original, &temporary)
+                .and_then(|_| std::fs::rename(&temporary, copy))
+                .with_context(|| format!("failed to replace {} by a link to {}", copy.to_string_lossy(), original.to_string_lossy()))?;
+        }
+        relinked += 1;
+        reclaimed += size;
+    }
+    session.info_at(
+        QUIET,
+        format!(
+            "{} {} replaced by links in {} ({} reclaimed), {} left alone as they differ from their source",
+            relinked,
+            if dry_run { "copies would be" } else { "copies" },
+            target.to_string_lossy(),
+            stats::human_size(reclaimed),
+            modified
+        ),
+    );
+    Ok(Stats {
+        linked: relinked,
+        linked_bytes: reclaimed,
+        ..Stats::default()
+    })
+}
+
+/// Adds to `copies` the files of `dir` (in the copy of `source_dir` at `copy_root`) with the source file they copy
+fn add_copies_of_dir(copy_root: &Path, dir: &Path, source_dir: &Path, copies: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // file_type() doesn't follow the links
+        if entry.file_type()?.is_dir() {
+            add_copies_of_dir(copy_root, &path, source_dir, copies)?;
+        } else {
+            let original = source_dir.join(path.strip_prefix(copy_root).unwrap_or(&path));
+            copies.push((path, original));
+        }
+    }
+    Ok(())
+}
+
+/// Reports what a rebuild would change in `target` (--diff), from a silent planning pass. Fails with `fail_on_diff` if
+/// there are differences
+fn diff_target(session: &Session, description: &GameDescription, source: &Path, target: &Path, fail_on_diff: bool) -> Result<Stats> {
+    session.counting.set(true);
+    let result = process_source(session, description, source, target);
+    session.counting.set(false);
+    result?;
+    session.progress.finish_and_clear();
+    let actions = session.plan.replace(Vec::new());
+    let differences = diff::compare(&actions, target, &[STATE_FILE, MANIFEST_FILE, SOURCE_FILE])?;
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for difference in &differences {
+        match difference {
+            diff::Difference::Added(..) => added += 1,
+            diff::Difference::Removed(_) => removed += 1,
+            diff::Difference::Changed(..) => changed += 1,
+        }
+        session.info(difference.to_string());
+    }
+    session.info_at(
+        QUIET,
+        format!(
+            "{} added, {} removed, {} changed by a rebuild of {}",
+            added,
+            removed,
+            changed,
+            target.to_string_lossy()
+        ),
+    );
+    if fail_on_diff && !differences.is_empty() {
+        return Err(AnyError::new(IedupError::Mismatch(format!(
+            "a rebuild would change {}",
+            target.to_string_lossy()
+        ))));
+    }
+    Ok(stats::total(&stats::collect(&actions, target, session.writable_root.as_deref())))
+}
+
+/// Fails if the run will create more than `max_links` links
+fn check_link_count(session: &Session, planned: &BTreeMap<String, Stats>, max_links: u64) -> Result<()> {
+    let links = stats::total(planned).linked;
+    session.info(format!("{} link(s) to create", links));
+    if links > max_links {
+        let (category, stats) = planned
+            .iter()
+            .max_by_key(|(_, stats)| stats.linked)
+            .ok_or_else(|| anyhow!("no links counted"))?;
+        return Err(AnyError::new(IedupError::TooManyLinks {
+            links,
+            max_links,
+            most: stats.linked,
+            category: category.clone(),
+        }));
+    }
+    Ok(())
+}
+
+/// Fails, before anything is done, if the copies (`needed` bytes) don't fit in the `available` space of the target
+fn check_free_space(needed: u64, available: u64) -> Result<()> {
+    if needed > available {
+        return Err(anyhow!(
+            "the copies need {} but the target has {} free, {} more are needed (use --ignore-space to try anyway)",
+            stats::human_size(needed),
+            stats::human_size(available),
+            stats::human_size(needed - available)
+        ));
+    }
+    Ok(())
+}
+
+/// Prints what the run will do and asks whether to go on (--interactive), fails unless the answer is yes. Without a
+/// way to ask, only `yes` lets the run go on.
+fn confirm(session: &Session, planned: &BTreeMap<String, Stats>, target: &Path, yes: bool, ask: Option<&Confirm>) -> Result<()> {
+    for line in stats::table(planned) {
+        session.info_at(QUIET, line);
+    }
+    let total = stats::total(planned);
+    session.info_at(
+        QUIET,
+        format!(
+            "{} copies ({}), {} links and {} dir(s) to create in {}",
+            total.copied,
+            stats::human_size(total.bytes),
+            total.linked,
+            total.dirs,
+            target.to_string_lossy()
+        ),
+    );
+    if yes {
+        return Ok(());
+    }
+    let ask = match ask {
+        Some(ask) => ask,
+        None => return Err(anyhow!("there is no terminal to confirm the run, use --yes to proceed")),
+    };
+    if !ask("Proceed?") {
+        return Err(anyhow!("cancelled, nothing was changed"));
+    }
+    Ok(())
+}
+
+fn read_state(path: &Path) -> Result<HashSet<String>> {
+    let content = std::fs::read_to_string(path)?;
+    let mut lines = content.lines();
+    if lines.next() != Some(STATE_HEADER) {
+        return Err(anyhow!("{} is not an iedup state file", path.to_string_lossy()));
+    }
+    Ok(lines.map(String::from).collect())
+}
+
+/// --into takes a single directory name, it can't escape the target
+fn check_subdir_name(subdir: &str) -> Result<&str> {
+    let mut components = Path::new(subdir).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(subdir),
+        _ => Err(AnyError::new(IedupError::Usage(format!(
+            "--into expects a directory name, not {}",
+            subdir
+        )))),
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| Pattern::new(pattern).with_context(|| format!("invalid pattern {}", pattern)))
+        .collect()
+}
+
+/// the patterns of the parent dirs of what `patterns` match (game and game/music for game/music/*.mus)
+fn parent_patterns(patterns: &[String]) -> Vec<String> {
+    let mut parents = Vec::new();
+    for pattern in patterns {
+        let components: Vec<&str> = pattern.split('/').collect();
+        for length in 1..components.len() {
+            parents.push(components[..length].join("/"));
+        }
+    }
+    parents
+}
+
+/// <name>.iedup.bak next to `path`, or <name>.iedup.bak.2 (3...) if there is already a backup
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(BACKUP_SUFFIX);
+    let mut backup = path.with_file_name(&name);
+    let mut number = 2;
+    while backup.symlink_metadata().is_ok() {
+        let mut numbered = name.clone();
+        numbered.push(format!(".{}", number));
+        backup = path.with_file_name(numbered);
+        number += 1;
+    }
+    backup
+}
+
+/// Whether the source entry is a dir, or a link to one (game/ or lang/ can be links to another drive)
+fn is_dir_entry(entry: &DirEntry) -> Result<bool> {
+    let file_type = entry.file_type()?;
+    // path().is_dir() follows the link
+    Ok(file_type.is_dir() || (file_type.is_symlink() && entry.path().is_dir()))
+}
+
+/// `dir.read_dir()` sorted by name, with the directory in the errors
+fn read_dir(dir: &Path) -> Result<impl Iterator<Item = Result<DirEntry>>> {
+    let entries = dir.read_dir().with_context(|| match dir.metadata() {
+        Ok(metadata) if metadata.permissions().readonly() => {
+            format!(
+                "failed to read directory {} (it's read-only, but should still be readable)",
+                dir.to_string_lossy()
+            )
+        }
+        _ => format!("failed to read directory {}", dir.to_string_lossy()),
+    })?;
+    let mut entries = entries
+        .map(|entry| entry.with_context(|| format!("failed to read an entry of {}", dir.to_string_lossy())))
+        .collect::<Result<Vec<_>>>()?;
+    // the filesystem order changes from one run (or one copy of the source) to the other
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries.into_iter().map(Ok))
+}
+
+/// Fails if `dir` is more than `max_depth` levels below `root`, to stop on pathological source trees
+fn check_depth(root: &Path, dir: &Path, max_depth: usize) -> Result<()> {
+    let depth = dir.strip_prefix(root).map_or(0, |relative| relative.components().count());
+    if depth > max_depth {
+        return Err(anyhow!(
+            "{} is {} levels deep, more than --max-depth {}",
+            dir.to_string_lossy(),
+            depth,
+            max_depth
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses an empty source with a target that looks like a game, the arguments were probably given in the wrong order
+fn check_not_swapped(source: &Path, target: &Path) -> Result<()> {
+    let looks_like_game = ["chitin.key", "start.sh", "game/chitin.key"]
+        .iter()
+        .any(|entry| target.join(entry).exists());
+    if looks_like_game && read_dir(source)?.next().is_none() {
+        return Err(anyhow!(
+            "source {} is empty but target {} looks like a game install, are the source and the target reversed?",
+            source.to_string_lossy(),
+            target.to_string_lossy()
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses overlapping source and target, where we could create links to themselves or links looping into the target
+fn check_no_overlap(source: &Path, target: &Path) -> Result<()> {
+    let source = source
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", source.to_string_lossy()))?;
+    let target = resolve(target)?;
+    if source == target {
+        return Err(anyhow!("source and target are the same directory {}", source.to_string_lossy()));
+    }
+    if target.starts_with(&source) {
+        return Err(anyhow!(
+            "target {} is inside source {}",
+            target.to_string_lossy(),
+            source.to_string_lossy()
+        ));
+    }
+    if source.starts_with(&target) {
+        return Err(anyhow!(
+            "source {} is inside target {}",
+            source.to_string_lossy(),
+            target.to_string_lossy()
+        ));
+    }
+    Ok(())
+}
+
+/// The writable root must be a dir apart from the source and the target
+fn check_writable_root(source: &Path, target: &Path, writable_root: &Path) -> Result<()> {
+    if !writable_root.is_dir() {
+        return Err(anyhow!("writable root {} is not a directory", writable_root.to_string_lossy()));
+    }
+    let writable_root = writable_root
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", writable_root.to_string_lossy()))?;
+    let source = source
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", source.to_string_lossy()))?;
+    for (name, dir) in [("source", source), ("target", resolve(target)?)] {
+        if writable_root.starts_with(&dir) || dir.starts_with(&writable_root) {
+            return Err(anyhow!(
+                "writable root {} overlaps the {} {}",
+                writable_root.to_string_lossy(),
+                name,
+                dir.to_string_lossy()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `path` canonicalized, with its end that doesn't exist yet (a target to create) appended as it is
+fn resolve(path: &Path) -> Result<PathBuf> {
+    if path.exists() || path.file_name().is_none() {
+        return path
+            .canonicalize()
+            .with_context(|| format!("failed to resolve {}", path.to_string_lossy()));
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(resolve(parent)?.join(path.file_name().unwrap_or_default()))
+}
+
+/// whether `target` looks like the output of a previous run
+fn is_previous_output(target: &Path) -> bool {
+    // we always duplicate support/ (linked unless a rule copies it) and create game/ (or copy chitin.key and create
+    // data/ for flat layouts)
+    (target.join("support").symlink_metadata().is_ok() && target.join("game").is_dir())
+        || (target.join("chitin.key").is_file() && target.join("data").is_dir())
+}
+
+/// Removes the content of `target` (but not `target` itself), which must be the output of a previous run.
+fn clean_target(session: &Session, target: &Path) -> Result<()> {
+    if !is_previous_output(target) {
+        return Err(anyhow!(
+            "target {} doesn't look like it was built by iedup, refusing to clean it",
+            target.to_string_lossy()
+        ));
+    }
+    for entry in read_dir(target)? {
+        let entry = entry?;
+        let path = entry.path();
+        session.warn(format!("remove {}", path.to_string_lossy()));
+        if !session.plan(ActionKind::Remove, None, &path) {
+            continue;
+        }
+        // file_type() doesn't follow links, so linked dirs are removed as links, not recursively
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        }
+        .with_context(|| format!("failed to remove {}", path.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// the verbosity of --quiet, where only the errors and the summary are shown
+const QUIET: i32 = -1;
+
+/// State shared by all the steps of a duplication
+struct Session {
+    progress: ProgressBar,
+    /// the progress is counted in bytes (copied, and LINK_WEIGHT by link) once the planning pass knows the total,
+    /// instead of in items
+    progress_by_bytes: Cell<bool>,
+    color: bool,
+    /// QUIET, 0 by default, or the number of -v
+    verbosity: i32,
+    mode: Mode,
+    /// the OS of the source install
+    os: Os,
+    variant: Variant,
+    config_files: Vec<Pattern>,
+    preserve: Preserve,
+    /// the copies are reflinks (--copy-mode=reflink, when the target can hold them)
+    reflink: bool,
+    override_mode: OverrideMode,
+    fail_on_empty: bool,
+    follow_existing: bool,
+    copy_all: bool,
+    /// compare the names without case, for case-insensitive filesystems
+    ignore_case: bool,
+    /// the subdirs of lang/ to duplicate, all when empty
+    languages: Vec<String>,
+    dry_run: bool,
+    /// in the planning pass of --max-links: the actions are only recorded, nothing is done or shown
+    counting: Cell<bool>,
+    /// what was done (or would be done in a dry run)
+    plan: RefCell<Vec<Action>>,
+    /// with --stats-only, the sizes of the actions, summed instead of kept in the plan
+    tally: Option<RefCell<stats::Tally>>,
+    exclude: Vec<Pattern>,
+    include_only: Vec<Pattern>,
+    /// match the dirs that lead to the include_only entries
+    include_parents: Vec<Pattern>,
+    dlc_patterns: Vec<Pattern>,
+    /// the dlc patterns were given, finding no dlc is worth a warning
+    dlc_patterns_given: bool,
+    extract_dlc: bool,
+    /// the dlc archives found, extracted once the game dir is done
+    dlc_archives: RefCell<Vec<PathBuf>>,
+    /// the entries of the root left out (--skip)
+    skip: Vec<RootItem>,
+    skip_lang_movies: bool,
+    skip_lang_sounds: bool,
+    retries: u32,
+    retry_delay: Duration,
+    /// the entries of --from-manifest, done instead of what is found in the source
+    manifest_entries: Option<Vec<manifest::Entry>>,
+    copy_under: Option<u64>,
+    link_over: Option<u64>,
+    backup: bool,
+    config: ConfigFile,
+    /// where the links point instead of the source root
+    link_base: Option<PathBuf>,
+    /// where the copies go (--writable-root), canonicalized
+    writable_root: Option<PathBuf>,
+    source_root: PathBuf,
+    target_root: PathBuf,
+    /// how deep the recursive walks can go below the source root
+    max_depth: usize,
+    verify_copies: bool,
+    prune: bool,
+    /// links removed by --prune
+    pruned: Cell<u32>,
+    only: Option<Section>,
+    /// the content of game/ goes to the root of the target
+    flatten: bool,
+    lowercase_names: bool,
+    /// the source of each target entry named by lowercase_names, to find the names that collide
+    lowercased: RefCell<HashMap<PathBuf, PathBuf>>,
+    /// the rules of the .iedupignore file of each source dir seen (none for most)
+    ignore_files: RefCell<HashMap<PathBuf, Rc<Vec<ignore::Rule>>>>,
+    chmod: Option<u32>,
+    chown: Option<Owner>,
+    /// chown failed for lack of rights, it's not tried again
+    chown_refused: Cell<bool>,
+    continue_on_error: bool,
+    /// the failures of the items with --continue-on-error
+    errors: RefCell<Vec<String>>,
+    warnings: RefCell<Vec<String>>,
+    /// the source files that disappeared before they were copied or linked, skipped
+    vanished: RefCell<Vec<String>>,
+    /// the time spent in each phase, in order
+    timings: RefCell<Vec<(&'static str, Duration)>>,
+    since: Option<SystemTime>,
+    /// the copies made again with --since, and those left alone
+    refreshed: Cell<u32>,
+    unchanged: Cell<u32>,
+    /// items (relative to the target root) done by the interrupted run we're resuming
+    completed: HashSet<String>,
+    state_file: OnceCell<File>,
+    /// differences with the source found while verifying
+    problems: Cell<u32>,
+    /// target entries that don't exist in the source (added by mods for example)
+    local_additions: Cell<u32>,
+}
+
+impl Session {
+    /// applies `style` to `text`, or leaves it as is when colors are disabled
+    fn paint<'a, I: Into<Cow<'a, str>>>(&self, style: Style, text: I) -> ANSIString<'a> {
+        if self.color {
+            style.paint(text)
+        } else {
+            Style::new().paint(text)
+        }
+    }
+
+    /// logs a line, unless --quiet
+    fn info<S: AsRef<str>>(&self, line: S) {
+        self.info_at(0, line);
+    }
+
+    /// logs a line if the verbosity is at least `level`, the details of -v and more at the debug and trace levels
+    fn info_at<S: AsRef<str>>(&self, level: i32, line: S) {
+        let log_level = match level {
+            level if level <= 0 => Level::Info,
+            1 => Level::Debug,
+            _ => Level::Trace,
+        };
+        self.log(level, log_level, line.as_ref());
+    }
+
+    /// logs a warning (in yellow), unless --quiet
+    fn warn<S: AsRef<str>>(&self, message: S) {
+        self.warn_at(0, message);
+    }
+
+    fn warn_at<S: AsRef<str>>(&self, level: i32, message: S) {
+        // kept for --report-json, whatever the verbosity
+        if !self.counting.get() {
+            self.warnings.borrow_mut().push(message.as_ref().to_string());
+        }
+        self.log(level, Level::Warn, &self.paint(Yellow.normal(), message.as_ref()).to_string());
+    }
+
+    /// logs an error (in red), even with --quiet
+    fn error<S: AsRef<str>>(&self, message: S) {
+        self.log(QUIET, Level::Error, &self.paint(Red.bold(), message.as_ref()).to_string());
+    }
+
+    /// hands a line to the logger (the console one of iedup, or the one of the program using the library) if the
+    /// verbosity is at least `level`, with the progress bar out of the way
+    fn log(&self, level: i32, log_level: Level, line: &str) {
+        if self.verbosity < level || self.counting.get() {
+            return;
+        }
+        if self.progress.is_hidden() {
+            log::log!(log_level, "{}", line);
+        } else {
+            self.progress.suspend(|| log::log!(log_level, "{}", line));
+        }
+    }
+
+    fn enter_dir(&self, dir: &Path) {
+        // nothing is shown in the planning pass (it may end with a question)
+        if !self.counting.get() {
+            self.progress.set_message(dir.to_string_lossy().into_owned());
+        }
+    }
+
+    fn item_done(&self) {
+        if !self.counting.get() && !self.progress_by_bytes.get() {
+            self.progress.inc(1);
+        }
+    }
+
+    /// modifiable config files are copied instead of linked
+    fn is_copy_target(&self, name: &OsStr) -> bool {
+        let name = name.to_string_lossy();
+        let options = MatchOptions {
+            case_sensitive: !self.ignore_case,
+            ..MatchOptions::new()
+        };
+        self.config_files.iter().any(|pattern| pattern.matches_with(&name, options))
+    }
+
+    /// how to duplicate a music file with this extension, None if it's unknown
+    fn music_action(&self, extension: &OsStr) -> Option<RuleAction> {
+        let configured = self.config.music.iter().map(|(extension, action)| (extension.as_str(), *action));
+        configured
+            .chain(MUSIC_EXTENSIONS.iter().copied())
+            .find(|(known, _)| self.name_is(extension, known))
+            .map(|(_, action)| action)
+    }
+
+    /// whether a file name (or extension) is `expected`
+    fn name_is(&self, name: &OsStr, expected: &str) -> bool {
+        if self.ignore_case {
+            name.to_string_lossy().eq_ignore_ascii_case(expected)
+        } else {
+            name == expected
+        }
+    }
+
+    /// whether the source file or dir was left out by --include-only or --exclude (a dir is then skipped with all its
+    /// content). `is_walked_dir` is true for dirs whose content is processed (as opposed to dirs linked as a whole).
+    fn is_skipped(&self, source_item: &Path, is_walked_dir: bool) -> bool {
+        // left out by the source itself, only worth telling with -v
+        if let Some(rule) = self.ignore_rule(source_item) {
+            self.info_at(1, format!("ignore {} ({})", source_item.to_string_lossy(), rule));
+            return true;
+        }
+        match self.skip_reason(source_item, is_walked_dir) {
+            Some(reason) => {
+                self.warn(format!("{} {}", reason, source_item.to_string_lossy()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// why `source_item` is left out (skip when not included, exclude), silently
+    fn skip_reason(&self, source_item: &Path, is_walked_dir: bool) -> Option<&'static str> {
+        let relative = source_item.strip_prefix(&self.source_root).unwrap_or(source_item);
+        let matches = |patterns: &[Pattern], path: &Path| patterns.iter().any(|pattern| pattern.matches(&path.to_string_lossy()));
+        if !self.include_only.is_empty() {
+            // included by itself or by a parent dir, or needed to reach an included entry
+            let included =
+                relative.ancestors().any(|path| matches(&self.include_only, path)) || (is_walked_dir && matches(&self.include_parents, relative));
+            if !included {
+                return Some("skip");
+            }
+        }
+        if matches(&self.exclude, relative) {
+            return Some("exclude");
+        }
+        if self.config.rule_for(relative) == Some(RuleAction::Skip) {
+            return Some("skip");
+        }
+        if self.ignore_rule(source_item).is_some() {
+            return Some("ignore");
+        }
+        None
+    }
+
+    /// the .iedupignore rule that leaves `source_item` out, described, if any
+    fn ignore_rule(&self, source_item: &Path) -> Option<String> {
+        if source_item.file_name().map_or(false, |name| name == ignore::IGNORE_FILE) {
+            return Some(String::from("an ignore file"));
+        }
+        let is_dir = source_item.is_dir();
+        let mut ignored_by = None;
+        // from the source root down, the rules of the deeper files override those of their parents
+        let dirs: Vec<&Path> = source_item
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.source_root))
+            .collect();
+        for dir in dirs.into_iter().rev() {
+            let rules = self.ignore_rules(dir);
+            let relative = source_item.strip_prefix(dir).unwrap_or(source_item);
+            for rule in rules.iter() {
+                match rule.matches(relative, is_dir) {
+                    Some(true) => {
+                        ignored_by = Some(format!("{} in {}", rule.text, dir.join(ignore::IGNORE_FILE).to_string_lossy()));
+                    }
+                    Some(false) => ignored_by = None,
+                    None => {}
+                }
+            }
+        }
+        ignored_by
+    }
+
+    /// the rules of the .iedupignore file of the source dir `dir`, read once (an invalid file is reported and unused)
+    fn ignore_rules(&self, dir: &Path) -> Rc<Vec<ignore::Rule>> {
+        if let Some(rules) = self.ignore_files.borrow().get(dir) {
+            return rules.clone();
+        }
+        let rules = Rc::new(ignore::read(dir).unwrap_or_else(|error| {
+            self.warn(format!("{:#}, it is left unused", error));
+            Vec::new()
+        }));
+        self.ignore_files.borrow_mut().insert(dir.to_path_buf(), rules.clone());
+        rules
+    }
+
+    /// what a link to `source_item` points to: the source item, or the same item under --link-base (which must exist)
+    fn link_path(&self, source_item: &Path) -> Result<PathBuf> {
+        let link_base = match &self.link_base {
+            Some(link_base) => link_base,
+            None => return Ok(source_item.to_path_buf()),
+        };
+        let link_path = link_base.join(source_item.strip_prefix(&self.source_root).unwrap_or(source_item));
+        if link_path.symlink_metadata().is_err() {
+            return Err(anyhow!(
+                "{} isn't in the link base {}",
+                link_path.to_string_lossy(),
+                link_base.to_string_lossy()
+            ));
+        }
+        Ok(link_path)
+    }
+
+    /// where the copy of `target_item` goes with --writable-root, None without it
+    fn writable_item(&self, target_item: &Path) -> Option<PathBuf> {
+        let relative = target_item.strip_prefix(&self.target_root).ok()?;
+        Some(self.writable_root.as_ref()?.join(relative))
+    }
+
+    /// the action of the config rule matching the source file or dir, if any
+    fn rule_for(&self, source_item: &Path) -> Option<RuleAction> {
+        self.config.rule_for(source_item.strip_prefix(&self.source_root).unwrap_or(source_item))
+    }
+
+    /// how the source item is duplicated when it's not the default: by its rule, or else by its size (--copy-under
+    /// and --link-over, only for files)
+    fn forced_action(&self, source_item: &Path, metadata: Option<&Metadata>) -> Option<RuleAction> {
+        self.rule_for(source_item).or_else(|| {
+            let size = metadata.filter(|metadata| metadata.is_file())?.len();
+            if self.copy_under.map_or(false, |copy_under| size < copy_under) {
+                Some(RuleAction::Copy)
+            } else if self.link_over.map_or(false, |link_over| size > link_over) {
+                Some(RuleAction::Link)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// warns about an empty source file, fails for the critical ones if asked to
+    fn check_not_empty(&self, source_item: &Path, metadata: &Metadata) -> Result<()> {
+        if !metadata.is_file() || metadata.len() > 0 {
+            return Ok(());
+        }
+        let name = source_item.file_name().unwrap_or_default();
+        if self.fail_on_empty && CRITICAL_FILES.iter().any(|critical| self.name_is(name, critical)) {
+            return Err(anyhow!("{} is empty", source_item.to_string_lossy()));
+        }
+        self.warn(format!("empty file {}", source_item.to_string_lossy()));
+        Ok(())
+    }
+
+    /// adds an action to the plan, returns whether it should be done (false in a dry run)
+    fn plan(&self, kind: ActionKind, source: Option<&Path>, target: &Path) -> bool {
+        self.plan_action(Action {
+            kind,
+            source: source.map(Path::to_path_buf),
+            target: target.to_path_buf(),
+            size: None,
+        })
+    }
+
+    /// plans the extraction of the file of an archive `source` (<archive>/<path in the archive>) of `size` bytes
+    fn plan_extract(&self, source: &Path, target: &Path, size: u64) -> bool {
+        self.plan_action(Action {
+            kind: ActionKind::Extract,
+            source: Some(source.to_path_buf()),
+            target: target.to_path_buf(),
+            size: Some(size),
+        })
+    }
+
+    fn plan_action(&self, action: Action) -> bool {
+        let kind = action.kind;
+        if self.progress_by_bytes.get() && !self.counting.get() {
+            self.progress.inc(progress_weight(&action));
+        }
+        match &self.tally {
+            Some(tally) => tally.borrow_mut().add(&action, &self.target_root, self.writable_root.as_deref()),
+            None => {
+                // the copies and links are shown where they are decided (with their sizes), the removals by their callers
+                if kind == ActionKind::CreateDir {
+                    self.info_at(
+                        1,
+                        format!("create directory {}", self.paint(Green.normal(), action.target.to_string_lossy())),
+                    );
+                }
+                self.plan.borrow_mut().push(action)
+            }
+        }
+        !self.dry_run && !self.counting.get()
+    }
+
+    /// Makes room for a new copy or link at `target_item`: a copy may have local changes, it's kept as <name>.iedup.bak
+    /// with --backup, a link (or an empty dir) is removed
+    fn replace(&self, target_item: &Path) -> Result<()> {
+        let metadata = match target_item.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(()),
+        };
+        if metadata.is_dir() {
+            if self.plan(ActionKind::Remove, None, target_item) {
+                std::fs::remove_dir(target_item).with_context(|| format!("failed to remove {}", target_item.to_string_lossy()))?;
+            }
+        } else if self.backup && metadata.is_file() {
+            let backup = backup_path(target_item);
+            self.info(format!("backup {} to {}", target_item.to_string_lossy(), backup.to_string_lossy()));
+            if self.plan(ActionKind::Backup, Some(target_item), &backup) {
+                std::fs::rename(target_item, &backup).with_context(|| format!("failed to back up {}", target_item.to_string_lossy()))?;
+            }
+        } else if self.plan(ActionKind::Remove, None, target_item) {
+            std::fs::remove_file(target_item).with_context(|| format!("failed to remove {}", target_item.to_string_lossy()))?;
+        }
+        Ok(())
+    }
+
+    /// Where the copy or link of `source_item` goes in `target`, under a lowercase name with --lowercase-names. Fails if
+    /// another entry of the source already got that name
+    fn target_item(&self, source_item: &Path, target: &Path, item: &OsStr) -> Result<PathBuf> {
+        // a name that isn't valid unicode has no lowercase
+        let name = match item.to_str() {
+            Some(name) if self.lowercase_names => name,
+            _ => return Ok(target.join(item)),
+        };
+        let target_item = target.join(name.to_lowercase());
+        let mut lowercased = self.lowercased.borrow_mut();
+        if let Some(other) = lowercased.get(&target_item).filter(|other| *other != source_item) {
+            return Err(anyhow!(
+                "{} and {} would both be {} with --lowercase-names",
+                other.to_string_lossy(),
+                source_item.to_string_lossy(),
+                target_item.to_string_lossy()
+            ));
+        }
+        lowercased.insert(target_item.clone(), source_item.to_path_buf());
+        Ok(target_item)
+    }
+
+    fn relative_path(&self, target_item: &Path) -> String {
+        target_item
+            .strip_prefix(&self.target_root)
+            .unwrap_or(target_item)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn is_completed(&self, target_item: &Path) -> bool {
+        self.completed.contains(&self.relative_path(target_item))
+    }
+
+    /// records in the state file that `target_item` is done
+    fn record_done(&self, target_item: &Path) -> Result<()> {
+        if let Some(mut state_file) = self.state_file.get() {
+            writeln!(state_file, "{}", self.relative_path(target_item))?;
+        }
+        Ok(())
+    }
+
+    fn problem(&self, message: String) {
+        self.problems.set(self.problems.get() + 1);
+        self.error(message);
+    }
+
+    /// the size of a file for the -vv output (nothing below)
+    fn size_note(&self, metadata: Option<&Metadata>) -> String {
+        match metadata {
+            Some(metadata) if self.verbosity >= 2 && metadata.is_file() => format!(" ({})", stats::human_size(metadata.len())),
+            _ => String::new(),
+        }
+    }
+
+    /// With --prune, removes `path` if it's a link into the source (or the link base) to something that doesn't exist
+    /// anymore, returns whether it was removed. Copies and the links made by hand elsewhere are left alone
+    fn prune_stale_link(&self, path: &Path) -> Result<bool> {
+        if !self.prune || path.metadata().is_ok() {
+            return Ok(false);
+        }
+        let link = match std::fs::read_link(path) {
+            Ok(link) => link,
+            Err(_) => return Ok(false),
+        };
+        let into_source = link.starts_with(&self.source_root) || self.link_base.as_ref().map_or(false, |base| link.starts_with(base));
+        if !into_source {
+            return Ok(false);
+        }
+        self.warn(format!("prune stale link {} (to {})", path.to_string_lossy(), link.to_string_lossy()));
+        if self.plan(ActionKind::Remove, None, path) {
+            std::fs::remove_file(path).with_context(|| format!("failed to remove {}", path.to_string_lossy()))?;
+        }
+        self.pruned.set(self.pruned.get() + 1);
+        Ok(true)
+    }
+
+    /// whether an existing copy of a source item with this metadata must be made again (--since)
+    fn is_refreshed(&self, metadata: Option<&Metadata>) -> bool {
+        let since = match self.since {
+            Some(since) => since,
+            None => return false,
+        };
+        let is_modified = metadata
+            .and_then(|metadata| metadata.modified().ok())
+            .map_or(false, |modified| modified > since);
+        let counter = if is_modified { &self.refreshed } else { &self.unchanged };
+        if !self.counting.get() {
+            counter.set(counter.get() + 1);
+        }
+        is_modified
+    }
+
+    /// With --continue-on-error, records the failure of an item (an I/O error: the checks still stop the run) and goes
+    /// on as if there was nothing to do. The planning pass only goes on, the run records it again
+    fn tolerate<T: Default>(&self, result: Result<T>) -> Result<T> {
+        match result {
+            Err(error) if self.skip_vanished(&error) => Ok(T::default()),
+            Err(error) if self.continue_on_error && error.chain().any(|cause| cause.is::<std::io::Error>()) => {
+                if !self.counting.get() {
+                    let message = format!("{:#}", error);
+                    self.error(format!("error: {}", message));
+                    self.errors.borrow_mut().push(message);
+                }
+                Ok(T::default())
+            }
+            result => result,
+        }
+    }
+
+    /// Whether `error` is the failure of an item whose source file disappeared since it was listed (a live install being
+    /// patched), which is then skipped with a warning. A critical file missing still fails the run
+    fn skip_vanished(&self, error: &AnyError) -> bool {
+        let original = match vanished_source(error) {
+            Some(original) => original,
+            None => return false,
+        };
+        let name = original.file_name().unwrap_or_default();
+        if CRITICAL_FILES.iter().any(|critical| self.name_is(name, critical)) {
+            return false;
+        }
+        if !self.counting.get() {
+            self.warn(format!("{} vanished from the source, skipped", original.to_string_lossy()));
+            self.vanished.borrow_mut().push(original.to_string_lossy().into_owned());
+        }
+        true
+    }
+
+    /// Runs `operation` again (up to --retries times, waiting longer each time) while it fails with a transient error
+    fn retry<T>(&self, what: impl Fn() -> String, mut operation: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut delay = self.retry_delay;
+        for attempt in 1..=self.retries {
+            match operation() {
+                Err(error) if is_transient(&error) => {
+                    self.warn(format!(
+                        "{} failed ({}), retry {}/{} in {} ms",
+                        what(),
+                        error,
+                        attempt,
+                        self.retries,
+                        delay.as_millis()
+                    ));
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        operation()
+    }
+
+    /// gives a created copy or dir the mode and the owner of --chmod and --chown
+    fn set_permissions(&self, path: &Path, is_dir: bool) -> Result<()> {
+        if let Some(mode) = self.chmod {
+            permissions::chmod(path, if is_dir { permissions::dir_mode(mode) } else { mode })?;
+        }
+        match &self.chown {
+            Some(owner) if !self.chown_refused.get() => match owner.apply(path) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::PermissionDenied => {
+                    self.chown_refused.set(true);
+                    self.warn_at(QUIET, "can't change the owners (not running as root?), --chown is ignored");
+                }
+                Err(error) => return Err(error).with_context(|| format!("failed to change the owner of {}", path.to_string_lossy())),
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// runs `phase`, adding its duration to the timings of `name` (the silent planning pass isn't timed)
+    fn timed(&self, name: &'static str, phase: impl FnOnce() -> Result<()>) -> Result<()> {
+        let start = Instant::now();
+        let result = phase();
+        if !self.counting.get() {
+            let mut timings = self.timings.borrow_mut();
+            match timings.iter_mut().find(|(phase_name, _)| *phase_name == name) {
+                Some((_, duration)) => *duration += start.elapsed(),
+                None => timings.push((name, start.elapsed())),
+            }
+        }
+        result
+    }
+
+    fn local_addition(&self, path: &Path) {
+        self.local_additions.set(self.local_additions.get() + 1);
+        let message = match self.mode {
+            Mode::Verify => format!("local (not in source): {}", path.to_string_lossy()),
+            _ => format!("keep local addition {}", path.to_string_lossy()),
+        };
+        self.warn(message);
+    }
+}
+
+/// What `action` weighs in the progress by bytes: the size of a copy, LINK_WEIGHT for a link
+fn progress_weight(action: &Action) -> u64 {
+    match (action.kind, &action.source) {
+        (ActionKind::Copy, _) | (ActionKind::Extract, _) => action.file_size(),
+        (ActionKind::Link, _) | (ActionKind::Hardlink, _) => LINK_WEIGHT,
+        _ => 0,
+    }
+}
+
+/// Counts the copy/link operations a duplication will do, to size the progress bar.
+fn count_operations(source: &Path, game_dir: &Path, max_depth: usize) -> Result<u64> {
+    let mut count = 0;
+    if game_dir != source {
+        // start.sh, gameinfo, support/, dlc zips... (the game dir is counted below)
+        count += read_dir(source)?.count() as u64 - 1;
+    }
+    for entry in read_dir(game_dir)? {
+        let entry = entry?;
+        // Manuals/ is linked as a whole, the other dirs are processed file by file
+        if is_dir_entry(&entry)? && entry.file_name() != "Manuals" {
+            count += count_files(source, &entry.path(), max_depth)?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn count_files(source: &Path, dir: &Path, max_depth: usize) -> Result<u64> {
+    check_depth(source, dir, max_depth)?;
+    let mut count = 0;
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        if is_dir_entry(&entry)? {
+            count += count_files(source, &entry.path(), max_depth)?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn copy_item(session: &Session, source: &Path, target: &Path, item: &str) -> Result<()> {
+    copy_item_os(session, source, target, OsStr::new(item))
+}
+
+fn copy_item_os(session: &Session, source: &Path, target: &Path, item: &OsStr) -> Result<()> {
+    session.tolerate(try_copy_item(session, source, target, item))
+}
+
+fn try_copy_item(session: &Session, source: &Path, target: &Path, item: &OsStr) -> Result<()> {
+    let source_item = source.join(item);
+    if session.is_skipped(&source_item, false) {
+        return Ok(());
+    }
+    let metadata = source_item.metadata().ok();
+    match session.forced_action(&source_item, metadata.as_ref()) {
+        Some(RuleAction::Link) if !session.copy_all => return link_item_os(session, source, target, item, LinkKind::Symbolic),
+        Some(RuleAction::Hardlink) if !session.copy_all => return link_item_os(session, source, target, item, LinkKind::Hard),
+        _ => {}
+    }
+    if let Some(metadata) = &metadata {
+        session.check_not_empty(&source_item, metadata)?;
+    }
+    let target_item = session.target_item(&source_item, target, item)?;
+    // with --writable-root, the copy goes there and the target links to it
+    let writable_item = session.writable_item(&target_item);
+    let copy_item = writable_item.as_ref().unwrap_or(&target_item);
+    // a copy cut short by an interrupted run is done again
+    let is_truncated = session.mode == Mode::Merge && is_truncated_copy(&source_item, copy_item)?;
+    match session.mode {
+        Mode::Verify => match copy_item.symlink_metadata() {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => session.problem(format!(
+                "{} should be a copy of {}",
+                copy_item.to_string_lossy(),
+                source_item.to_string_lossy()
+            )),
+            Err(_) => session.problem(format!("missing copy {}", copy_item.to_string_lossy())),
+        },
+        // the existing file may have been modified, leave it alone (unless the source changed after --since)
+        Mode::Merge if copy_item.symlink_metadata().is_ok() && !is_truncated && !session.is_refreshed(metadata.as_ref()) => {}
+        // an existing file that wasn't recorded may be an interrupted copy
+        Mode::Resume if session.is_completed(&target_item) => {}
+        _ => {
+            if is_truncated {
+                session.warn(format!("copy {} again, it was cut short", copy_item.to_string_lossy()));
+                if session.plan(ActionKind::Remove, None, copy_item) {
+                    std::fs::remove_file(copy_item).with_context(|| format!("failed to remove {}", copy_item.to_string_lossy()))?;
+                }
+            } else if session.mode == Mode::Merge {
+                // a refreshed copy replaces the existing one
+                session.replace(copy_item)?;
+            }
+            session.info_at(
+                1,
+                format!(
+                    "copy {} to {}{}",
+                    session.paint(Blue.bold(), source_item.to_string_lossy()),
+                    session.paint(Green.normal(), copy_item.to_string_lossy()),
+                    session.size_note(metadata.as_ref())
+                ),
+            );
+            if session.plan(ActionKind::Copy, Some(&source_item), copy_item) {
+                if let Some(parent) = writable_item.as_deref().and_then(Path::parent) {
+                    std::fs::create_dir_all(parent).with_context(|| format!("failed to create directory {}", parent.to_string_lossy()))?;
+                }
+                let what = || format!("copy {}", copy_item.to_string_lossy());
+                session
+                    .retry(what, || copy_file(session.preserve, session.reflink, &source_item, copy_item))
+                    .map_err(|error| IedupError::CopyFailed {
+                        path: copy_item.clone(),
+                        original: source_item.clone(),
+                        source: error,
+                    })?;
+                session.set_permissions(copy_item, false)?;
+                if session.verify_copies || VERIFIED_COPIES.iter().any(|verified| session.name_is(item, verified)) {
+                    check_copy(&source_item, copy_item)?;
+                }
+            }
+        }
+    }
+    if let Some(writable_item) = &writable_item {
+        link_to_writable(session, writable_item, &target_item)?;
+    }
+    session.record_done(&target_item)?;
+    session.item_done();
+    Ok(())
+}
+
+/// The source file whose copy or link failed because it's no longer there, if that's what `error` is
+fn vanished_source(error: &AnyError) -> Option<&Path> {
+    match error.downcast_ref::<IedupError>()? {
+        IedupError::CopyFailed { original, source, .. } | IedupError::LinkFailed { original, source, .. }
+            if source.kind() == std::io::ErrorKind::NotFound && original.symlink_metadata().is_err() =>
+        {
+            Some(original)
+        }
+        _ => None,
+    }
+}
+
+/// Links `target_item` to its copy `writable_item` in the writable root
+fn link_to_writable(session: &Session, writable_item: &Path, target_item: &Path) -> Result<()> {
+    if std::fs::read_link(target_item).map_or(false, |link| link == writable_item) {
+        return Ok(());
+    }
+    // a copy made without --writable-root (maybe modified since) is left alone
+    if session.mode == Mode::Verify || (session.mode != Mode::Create && target_item.symlink_metadata().is_ok()) {
+        session.problem(format!(
+            "{} should be a link to {}",
+            target_item.to_string_lossy(),
+            writable_item.to_string_lossy()
+        ));
+        return Ok(());
+    }
+    session.info_at(
+        2,
+        format!(
+            "link {} to {}",
+            session.paint(Blue.bold(), writable_item.to_string_lossy()),
+            session.paint(Green.normal(), target_item.to_string_lossy())
+        ),
+    );
+    if session.plan(ActionKind::Link, Some(writable_item), target_item) {
+        let what = || format!("link {}", target_item.to_string_lossy());
+        session
+            .retry(what, || std::os::unix::fs::symlink(writable_item, target_item))
+            .map_err(|error| IedupError::LinkFailed {
+                path: target_item.to_path_buf(),
+                original: writable_item.to_path_buf(),
+                source: error,
+            })?;
+    }
+    Ok(())
+}
+
+fn copy_file(preserve: Preserve, reflink: bool, source: &Path, target: &Path) -> std::io::Result<()> {
+    // a file that can't be reflinked (on another filesystem than chitin.key) is copied plainly
+    if reflink && mounts::reflink(source, target).is_ok() {
+        if preserve != Preserve::None {
+            std::fs::set_permissions(target, source.metadata()?.permissions())?;
+        }
+    } else if preserve == Preserve::None {
+        // a new file gets the default permissions
+        let mut reader = File::open(source)?;
+        let mut writer = File::create(target)?;
+        std::io::copy(&mut reader, &mut writer)?;
+    } else {
+        // std::fs::copy keeps the permissions (and so the executable bit of start.sh)
+        std::fs::copy(source, target)?;
+    }
+    if preserve == Preserve::All {
+        let metadata = source.metadata()?;
+        filetime::set_file_times(
+            target,
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_last_modification_time(&metadata),
+        )?;
+    }
+    Ok(())
+}
+
+/// Whether `error` comes from a failure of the filesystem that may go away by itself (seen on NFS and SMB mounts), not
+/// from a missing file or a lack of rights
+fn is_transient(error: &std::io::Error) -> bool {
+    const EINTR: i32 = 4;
+    const EAGAIN: i32 = 11;
+    const EBUSY: i32 = 16;
+    const ETIMEDOUT: i32 = 110;
+    matches!(error.raw_os_error(), Some(code) if [EINTR, EAGAIN, EBUSY, ETIMEDOUT].contains(&code))
+}
+
+/// Fails if the copy `target` doesn't have the content of `source` (a failing disk can leave it short or corrupt)
+fn check_copy(source: &Path, target: &Path) -> Result<()> {
+    if dedup::hash_file(source)? != dedup::hash_file(target)? {
+        return Err(AnyError::new(IedupError::Mismatch(format!(
+            "the copy {} differs from {}, the disk may be failing",
+            target.to_string_lossy(),
+            source.to_string_lossy()
+        ))));
+    }
+    Ok(())
+}
+
+/// Whether `target` is shorter than `source` and has the start of its content, a copy cut short by an interrupted run
+/// (a copy changed locally is left alone)
+fn is_truncated_copy(source: &Path, target: &Path) -> Result<bool> {
+    let (source_len, target_len) = match (source.metadata(), target.symlink_metadata()) {
+        (Ok(source_metadata), Ok(target_metadata)) if target_metadata.is_file() => (source_metadata.len(), target_metadata.len()),
+        _ => return Ok(false),
+    };
+    if target_len >= source_len {
+        return Ok(false);
+    }
+    let mut start = Vec::new();
+    File::open(source)
+        .and_then(|file| file.take(target_len).read_to_end(&mut start))
+        .with_context(|| format!("failed to read {}", source.to_string_lossy()))?;
+    let content = std::fs::read(target).with_context(|| format!("failed to read {}", target.to_string_lossy()))?;
+    Ok(content == start)
+}
+
+fn link_item(session: &Session, source: &Path, target: &Path, item: &str) -> Result<()> {
+    link_item_os(session, source, target, OsStr::new(item), LinkKind::Symbolic)
+}
+
+fn link_item_os(session: &Session, source: &Path, target: &Path, item: &OsStr, kind: LinkKind) -> Result<()> {
+    session.tolerate(try_link_item(session, source, target, item, kind))
+}
+
+fn try_link_item(session: &Session, source: &Path, target: &Path, item: &OsStr, kind: LinkKind) -> Result<()> {
+    if session.copy_all {
+        return copy_any(session, source, target, item);
+    }
+    let source_item = source.join(item);
+    if session.is_skipped(&source_item, false) {
+        return Ok(());
+    }
+    let metadata = source_item.metadata().ok();
+    let kind = match session.forced_action(&source_item, metadata.as_ref()) {
+        Some(RuleAction::Copy) => return copy_any(session, source, target, item),
+        Some(RuleAction::Link) => LinkKind::Symbolic,
+        Some(RuleAction::Hardlink) => LinkKind::Hard,
+        _ => kind,
+    };
+    if let Some(metadata) = &metadata {
+        session.check_not_empty(&source_item, metadata)?;
+    }
+    if kind == LinkKind::Hard {
+        return hardlink_item(session, source, target, item, metadata.as_ref());
+    }
+    let link_path = session.link_path(&source_item)?;
+    let target_item = session.target_item(&source_item, target, item)?;
+    let mut create_link = false;
+    if session.mode != Mode::Create && target_item.symlink_metadata().is_ok() {
+        match std::fs::read_link(&target_item) {
+            Ok(link) if link == link_path => {}
+            // a link made by hand (relative, through another path...) to the same file
+            Ok(link) if is_same_file(&target_item, &link_path) => session.warn(format!(
+                "keep existing link {} -> {}",
+                target_item.to_string_lossy(),
+                link.to_string_lossy()
+            )),
+            Ok(link) if session.follow_existing && session.mode != Mode::Verify => {
+                session.warn(format!(
+                    "replace link {} (was to {})",
+                    target_item.to_string_lossy(),
+                    link.to_string_lossy()
+                ));
+                create_link = true;
+            }
+            Ok(link) => session.problem(format!(
+                "{} links to {} instead of {}",
+                target_item.to_string_lossy(),
+                link.to_string_lossy(),
+                link_path.to_string_lossy()
+            )),
+            // left by an interrupted run, which created the dir of a copied dir before its content
+            Err(_) if session.mode != Mode::Verify && is_empty_dir(&target_item) => {
+                session.warn(format!("replace empty dir {} by a link", target_item.to_string_lossy()));
+                create_link = true;
+            }
+            Err(_) => session.problem(format!(
+                "{} should be a link to {}",
+                target_item.to_string_lossy(),
+                link_path.to_string_lossy()
+            )),
+        }
+    } else if session.mode == Mode::Verify {
+        session.problem(format!("missing link {}", target_item.to_string_lossy()));
+    } else {
+        create_link = true;
+    }
+    if create_link {
+        // -vvv shows where the link really goes when the source path has links itself
+        let resolved = match link_path.canonicalize() {
+            Ok(resolved) if session.verbosity >= 3 && resolved != link_path => format!(" (-> {})", resolved.to_string_lossy()),
+            _ => String::new(),
+        };
+        session.info_at(
+            1,
+            format!(
+                "link {} to {}{}{}",
+                session.paint(Blue.bold(), link_path.to_string_lossy()),
+                session.paint(Green.normal(), target_item.to_string_lossy()),
+                session.size_note(metadata.as_ref()),
+                resolved
+            ),
+        );
+        session.replace(&target_item)?;
+        if session.plan(ActionKind::Link, Some(&link_path), &target_item) {
+            if let Ok(resolved) = link_path.canonicalize() {
+                if resolved == target.canonicalize()?.join(target_item.file_name().unwrap_or(item)) {
+                    return Err(AnyError::new(IedupError::LinkToItself { path: target_item.clone() }));
+                }
+            }
+            let what = || format!("link {}", target_item.to_string_lossy());
+            session
+                .retry(what, || std::os::unix::fs::symlink(&link_path, &target_item))
+                .map_err(|error| IedupError::LinkFailed {
+                    path: target_item.clone(),
+                    original: link_path.clone(),
+                    source: error,
+                })?;
+        }
+    }
+    session.record_done(&target_item)?;
+    session.item_done();
+    Ok(())
+}
+
+/// Hard links the file `source/item`, or each file of the dir since a dir can't be hard linked. Unlike the symlinks,
+/// the hard links go to the source itself whatever --link-base says
+fn hardlink_item(session: &Session, source: &Path, target: &Path, item: &OsStr, metadata: Option<&Metadata>) -> Result<()> {
+    let source_item = source.join(item);
+    if metadata.map_or(false, Metadata::is_dir) {
+        if create_dir_os(session, source, target, item)? {
+            let target_dir = target.join(item);
+            session.enter_dir(&source_item);
+            for entry in read_dir(&source_item)? {
+                link_item_os(session, &source_item, &target_dir, &entry?.file_name(), LinkKind::Hard)?;
+            }
+            check_local_additions(session, &source_item, &target_dir, &[])?;
+        }
+        return Ok(());
+    }
+    let target_item = session.target_item(&source_item, target, item)?;
+    let exists = target_item.symlink_metadata().is_ok();
+    let create_link = if session.mode == Mode::Create || (!exists && session.mode != Mode::Verify) {
+        true
+    } else if !exists {
+        session.problem(format!("missing hard link {}", target_item.to_string_lossy()));
+        false
+    } else if diff::is_hard_link(&source_item, &target_item) {
+        false
+    } else if session.mode != Mode::Verify && (session.follow_existing || is_empty_dir(&target_item)) {
+        session.warn(format!("replace {} by a hard link", target_item.to_string_lossy()));
+        true
+    } else {
+        session.problem(format!(
+            "{} should be a hard link to {}",
+            target_item.to_string_lossy(),
+            source_item.to_string_lossy()
+        ));
+        false
+    };
+    if create_link {
+        session.info_at(
+            1,
+            format!(
+                "hardlink {} to {}{}",
+                session.paint(Blue.bold(), source_item.to_string_lossy()),
+                session.paint(Green.normal(), target_item.to_string_lossy()),
+                session.size_note(metadata)
+            ),
+        );
+        session.replace(&target_item)?;
+        if session.plan(ActionKind::Hardlink, Some(&source_item), &target_item) {
+            let what = || format!("hard link {}", target_item.to_string_lossy());
+            session
+                .retry(what, || std::fs::hard_link(&source_item, &target_item))
+                .map_err(|error| IedupError::LinkFailed {
+                    path: target_item.clone(),
+                    original: source_item.clone(),
+                    source: error,
+                })?;
+        }
+    }
+    session.record_done(&target_item)?;
+    session.item_done();
+    Ok(())
+}
+
+/// Fails when the current user can't list `source`
+fn check_readable(source: &Path) -> Result<()> {
+    match std::fs::read_dir(source) {
+        Ok(_) => Ok(()),
+        Err(error) => Err(AnyError::new(IedupError::SourceNotReadable {
+            path: source.to_path_buf(),
+            source: error,
+        })),
+    }
+}
+
+/// Fails when the current user can't write in `target`, by creating and removing a file in it
+fn check_writable(target: &Path) -> Result<()> {
+    let probe = target.join(format!(".iedup-probe-{}", std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => std::fs::remove_file(&probe).with_context(|| format!("failed to remove {}", probe.to_string_lossy())),
+        Err(error) => Err(AnyError::new(IedupError::TargetNotWritable {
+            path: target.to_path_buf(),
+            source: error,
+        })),
+    }
+}
+
+/// whether both paths resolve to the same file
+fn is_same_file(first: &Path, second: &Path) -> bool {
+    match (first.canonicalize(), second.canonicalize()) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+/// whether `path` is a dir (not a link to one) with nothing in it
+fn is_empty_dir(path: &Path) -> bool {
+    path.symlink_metadata().map_or(false, |metadata| metadata.is_dir())
+        && std::fs::read_dir(path).map_or(false, |mut entries| entries.next().is_none())
+}
+
+fn create_dir_str(session: &Session, source: &Path, target: &Path, item: &str) -> Result<bool> {
+    create_dir_os(session, source, target, OsStr::new(item))
+}
+
+/// Creates `target/item` for the dir `source/item`, returns whether its content should be duplicated
+fn create_dir_os(session: &Session, source: &Path, target: &Path, item: &OsStr) -> Result<bool> {
+    session.tolerate(try_create_dir(session, source, target, item))
+}
+
+/// Creates the target dir matching the source dir `item`, returns false (without creating anything) if it is excluded
+/// and its content should be skipped
+fn try_create_dir(session: &Session, source: &Path, target: &Path, item: &OsStr) -> Result<bool> {
+    let source_dir = source.join(item);
+    if session.is_skipped(&source_dir, true) {
+        return Ok(false);
+    }
+    let target_dir = target.join(item);
+    let create_dir = || -> Result<()> {
+        if session.plan(ActionKind::CreateDir, Some(&source_dir), &target_dir) {
+            std::fs::create_dir(&target_dir).with_context(|| format!("failed to create directory {}", target_dir.to_string_lossy()))?;
+            session.set_permissions(&target_dir, true)?;
+        }
+        Ok(())
+    };
+    match session.mode {
+        Mode::Create => create_dir()?,
+        Mode::Merge | Mode::Resume if target_dir.is_dir() => {}
+        Mode::Merge | Mode::Resume => create_dir()?,
+        Mode::Verify if target_dir.is_dir() => {}
+        Mode::Verify => session.problem(format!("missing directory {}", target_dir.to_string_lossy())),
+    }
+    session.record_done(&target_dir)?;
+    Ok(true)
+}
+
+/// Reports the entries of `target` that don't come from `source` (for example files added by mods).
+/// Those are left untouched. `generated` lists the entries created by iedup itself.
+fn check_local_additions(session: &Session, source: &Path, target: &Path, generated: &[&str]) -> Result<()> {
+    check_local_additions_of(session, &[source], target, generated)
+}
+
+/// Like check_local_additions, for a target dir filled from several source dirs (the root and game/ with --flatten)
+fn check_local_additions_of(session: &Session, sources: &[&Path], target: &Path, generated: &[&str]) -> Result<()> {
+    if session.mode == Mode::Create || !target.is_dir() {
+        return Ok(());
+    }
+    for entry in read_dir(target)? {
+        let name = entry?.file_name();
+        if generated.iter().any(|generated_name| name == *generated_name)
+            || sources.iter().any(|source| source.join(&name).symlink_metadata().is_ok())
+            || session.lowercased.borrow().contains_key(&target.join(&name))
+        {
+            continue;
+        }
+        if session.prune_stale_link(&target.join(&name))? {
+            continue;
+        }
+        session.local_addition(&target.join(&name));
+    }
+    Ok(())
+}
+
+/// Links the dlc archives at the root and in dlc/ (if there is one), or keeps them to be extracted with --extract-dlc
+fn process_dlc(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    let dlc_dir = source.join(DLC_DIR);
+    let found = if session.extract_dlc {
+        let mut archives: Vec<PathBuf> = pattern_files(session, source, &session.dlc_patterns)?
+            .iter()
+            .map(|name| source.join(name))
+            .collect();
+        if dlc_dir.is_dir() {
+            archives.extend(
+                pattern_files(session, &dlc_dir, &session.dlc_patterns)?
+                    .iter()
+                    .map(|name| dlc_dir.join(name)),
+            );
+        }
+        let found = archives.len();
+        session.dlc_archives.borrow_mut().extend(archives);
+        found
+    } else {
+        let mut found = link_pattern_files(session, source, target, &session.dlc_patterns)?;
+        if dlc_dir.is_dir() && create_dir_str(session, source, target, DLC_DIR)? {
+            found += link_pattern_files(session, &dlc_dir, &target.join(DLC_DIR), &session.dlc_patterns)?;
+        }
+        found
+    };
+    if found == 0 && session.dlc_patterns_given {
+        session.warn(format!("no dlc archive matches --dlc-pattern in {}", source.to_string_lossy()));
+    }
+    Ok(())
+}
+
+/// Links the files of `source` whose name matches one of `patterns`, returns how many matched
+fn link_pattern_files(session: &Session, source: &Path, target: &Path, patterns: &[Pattern]) -> Result<usize> {
+    let names = pattern_files(session, source, patterns)?;
+    for name in &names {
+        link_item_os(session, source, target, name, LinkKind::Symbolic)?;
+    }
+    Ok(names.len())
+}
+
+/// The names of the files of `source` that match one of `patterns`
+fn pattern_files(session: &Session, source: &Path, patterns: &[Pattern]) -> Result<Vec<OsString>> {
+    let options = MatchOptions {
+        case_sensitive: !session.ignore_case,
+        ..MatchOptions::new()
+    };
+    let mut names = Vec::new();
+    // the names are matched lossily (the patterns are UTF-8), but the files are found by their real names
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_match = patterns.iter().any(|pattern| pattern.matches_with(&name.to_string_lossy(), options));
+        if is_match && !entry.path().is_dir() {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Extracts the dlc archives kept by process_dlc (--extract-dlc) into the game dir of the target, their files replacing
+/// those of the game. The paths of the files of the archives are those of the game dir of the source for the rules,
+/// --exclude...: a skip rule leaves a file out, a link rule can't link it
+fn extract_dlcs(session: &Session, source_game: &Path, target_game: &Path) -> Result<()> {
+    for archive_path in session.dlc_archives.take() {
+        session.info(format!(
+            "extract {} to {}",
+            session.paint(Blue.bold(), archive_path.to_string_lossy()),
+            session.paint(Green.normal(), target_game.to_string_lossy())
+        ));
+        let mut archive = dlc::Archive::open(&archive_path)?;
+        // nothing is extracted by a dry run (or the planning pass), the files are only listed
+        let writes = !session.dry_run && !session.counting.get();
+        let entries = if writes { archive.validate() } else { archive.entries() }
+            .with_context(|| format!("invalid dlc archive {}", archive_path.to_string_lossy()))?;
+        let mut planned = if writes { None } else { Some(planned_entries(session)) };
+        let (mut extracted, mut bytes, mut skipped) = (0, 0, 0);
+        for entry in &entries {
+            // left out by itself or by a dir, as the walk of the source would
+            let source_item = source_game.join(&entry.path);
+            let mut dirs = source_item.ancestors().take_while(|path| *path != source_game);
+            if dirs.any(|path| session.is_skipped(path, false) || session.rule_for(path) == Some(RuleAction::Skip)) {
+                skipped += 1;
+                continue;
+            }
+            if let Some(RuleAction::Link) | Some(RuleAction::Hardlink) = session.rule_for(&source_item) {
+                session.warn(format!(
+                    "{} is in {}, it can't be linked, extracted",
+                    source_item.to_string_lossy(),
+                    archive_path.to_string_lossy()
+                ));
+            }
+            let result = extract_entry(session, &archive_path, &mut archive, entry, target_game, &mut planned);
+            if session.tolerate(result.map(|()| true))? {
+                extracted += 1;
+                bytes += entry.size;
+            }
+        }
+        session.info(format!(
+            "extracted {} file(s) ({}) from {}, {} left out",
+            extracted,
+            stats::human_size(bytes),
+            archive_path.to_string_lossy(),
+            skipped
+        ));
+    }
+    Ok(())
+}
+
+/// The entries of the target as planned, for a run that doesn't write them: the kind and the source of the last action
+/// on each path
+type PlannedEntries = HashMap<PathBuf, (ActionKind, Option<PathBuf>)>;
+
+fn planned_entries(session: &Session) -> PlannedEntries {
+    let plan = session.plan.borrow();
+    plan.iter()
+        .map(|action| (action.target.clone(), (action.kind, action.source.clone())))
+        .collect()
+}
+
+/// Extracts a file of a dlc archive over what the target has at its path, or what is `planned` there in a dry run
+fn extract_entry(
+    session: &Session,
+    archive_path: &Path,
+    archive: &mut dlc::Archive,
+    entry: &dlc::Entry,
+    target_game: &Path,
+    planned: &mut Option<PlannedEntries>,
+) -> Result<()> {
+    let target_item = target_game.join(&entry.path);
+    // the dirs linked as a whole become dirs of links, not to write into the source
+    let mut dir = target_game.to_path_buf();
+    for component in entry.path.parent().map_or_else(Vec::new, |parent| parent.components().collect()) {
+        dir.push(component);
+        let found = match planned {
+            Some(planned) => planned.get(&dir).cloned(),
+            None => match dir.symlink_metadata() {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    let linked = std::fs::read_link(&dir).with_context(|| format!("failed to read the link {}", dir.to_string_lossy()))?;
+                    Some((ActionKind::Link, Some(linked)))
+                }
+                Ok(metadata) if metadata.is_dir() => Some((ActionKind::CreateDir, None)),
+                Ok(_) => Some((ActionKind::Copy, None)),
+                Err(_) => None,
+            },
+        };
+        match found {
+            Some((ActionKind::Link, Some(linked))) => split_linked_dir(session, &dir, &linked, planned)?,
+            Some((ActionKind::CreateDir, _)) => {}
+            Some(_) => return Err(anyhow!("{} is in the way of {}", dir.to_string_lossy(), target_item.to_string_lossy())),
+            None => {
+                if session.plan(ActionKind::CreateDir, None, &dir) {
+                    session
+                        .retry(|| format!("create directory {}", dir.to_string_lossy()), || std::fs::create_dir(&dir))
+                        .with_context(|| format!("failed to create directory {}", dir.to_string_lossy()))?;
+                    session.set_permissions(&dir, true)?;
+                }
+                if let Some(planned) = planned {
+                    planned.insert(dir.clone(), (ActionKind::CreateDir, None));
+                }
+            }
+        }
+    }
+    session.info_at(1, format!("extract {}", session.paint(Green.normal(), target_item.to_string_lossy())));
+    session.replace(&target_item)?;
+    if session.plan_extract(&archive_path.join(&entry.path), &target_item, entry.size) {
+        archive.extract(entry, &target_item)?;
+        session.set_permissions(&target_item, false)?;
+    }
+    Ok(())
+}
+
+/// Replaces the link `dir` to the dir `linked` by a dir of links to the entries of the linked dir, to add files into it
+fn split_linked_dir(session: &Session, dir: &Path, linked: &Path, planned: &mut Option<PlannedEntries>) -> Result<()> {
+    session.info_at(1, format!("split the link {} to extract into it", dir.to_string_lossy()));
+    if session.plan(ActionKind::Remove, None, dir) {
+        std::fs::remove_file(dir).with_context(|| format!("failed to remove {}", dir.to_string_lossy()))?;
+    }
+    if session.plan(ActionKind::CreateDir, Some(linked), dir) {
+        session
+            .retry(|| format!("create directory {}", dir.to_string_lossy()), || std::fs::create_dir(dir))
+            .with_context(|| format!("failed to create directory {}", dir.to_string_lossy()))?;
+        session.set_permissions(dir, true)?;
+    }
+    if let Some(planned) = planned {
+        planned.insert(dir.to_path_buf(), (ActionKind::CreateDir, Some(linked.to_path_buf())));
+    }
+    for entry in read_dir(linked)? {
+        let name = entry?.file_name();
+        let (source_item, target_item) = (linked.join(&name), dir.join(&name));
+        if session.plan(ActionKind::Link, Some(&source_item), &target_item) {
+            let what = || format!("link {}", target_item.to_string_lossy());
+            let result = session
+                .retry(what, || std::os::unix::fs::symlink(&source_item, &target_item))
+                .map_err(|error| IedupError::LinkFailed {
+                    path: target_item.clone(),
+                    original: source_item.clone(),
+                    source: error,
+                });
+            session.tolerate(result.map_err(AnyError::new))?;
+        }
+        if let Some(planned) = planned {
+            planned.insert(target_item, (ActionKind::Link, Some(source_item)));
+        }
+    }
+    Ok(())
+}
+
+/// Warns about (or refuses with `strict`) the entries of the game dir that process_game_dir doesn't know and no config
+/// rule classifies: the unknown files are linked, the unknown dirs are left out.
+fn check_game_entries(session: &Session, game_dir: &Path, strict: bool) -> Result<()> {
+    let mut unknown = Vec::new();
+    for entry in read_dir(game_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_dir = is_dir_entry(&entry)?;
+        if is_dir && (KNOWN_GAME_DIRS.iter().any(|known| name == *known) || (session.variant == Variant::Classic && is_classic_linked_dir(&name))) {
+            continue;
+        }
+        let is_known_file = !is_dir && (session.is_copy_target(&name) || GAME_SIGNATURES.iter().any(|(executable, _)| name == *executable));
+        if is_known_file || session.rule_for(&entry.path()).is_some() || session.skip_reason(&entry.path(), is_dir).is_some() {
+            continue;
+        }
+        let message = if is_dir {
+            format!(
+                "unexpected dir {}, it will be left out (classify it with a rule in --config)",
+                entry.path().to_string_lossy()
+            )
+        } else {
+            format!(
+                "unexpected file {}, it will be linked (classify it with a rule in --config)",
+                entry.path().to_string_lossy()
+            )
+        };
+        session.warn(message);
+        unknown.push(name.to_string_lossy().into_owned());
+    }
+    if strict && !unknown.is_empty() {
+        return Err(anyhow!("unexpected entries in {}: {}", game_dir.to_string_lossy(), unknown.join(", ")));
+    }
+    Ok(())
+}
+
+/// Duplicates `source/item` as a config rule says
+fn apply_rule(session: &Session, source: &Path, target: &Path, item: &OsStr, action: RuleAction) -> Result<()> {
+    let source_item = source.join(item);
+    match action {
+        RuleAction::Copy => copy_any(session, source, target, item),
+        RuleAction::Link => link_item_os(session, source, target, item, LinkKind::Symbolic),
+        RuleAction::Hardlink => link_item_os(session, source, target, item, LinkKind::Hard),
+        RuleAction::Skip => {
+            session.warn(format!("skip {}", source_item.to_string_lossy()));
+            Ok(())
+        }
+    }
+}
+
+/// Copies the file `source/item`, or the dir with all its content
+fn copy_any(session: &Session, source: &Path, target: &Path, item: &OsStr) -> Result<()> {
+    let source_item = source.join(item);
+    if source_item.is_dir() {
+        if create_dir_os(session, source, target, item)? {
+            copy_tree(session, &source_item, &target.join(item))?;
+        }
+        Ok(())
+    } else {
+        copy_item_os(session, source, target, item)
+    }
+}
+
+/// Copies the content of `source`, recursively
+fn copy_tree(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    check_depth(&session.source_root, source, session.max_depth)?;
+    session.enter_dir(source);
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        if is_dir_entry(&entry)? {
+            if create_dir_os(session, source, target, &entry.file_name())? {
+                copy_tree(session, &entry.path(), &target.join(entry.file_name()))?;
+            }
+        } else {
+            copy_item_os(session, source, target, &entry.file_name())?;
+        }
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+fn process_game_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    session.info(format!(
+        "{} to {}",
+        session.paint(Blue.bold(), source.to_string_lossy()),
+        session.paint(Green.normal(), target.to_string_lossy())
+    ));
+    let (required, expected) = game_files(session.variant);
+    for name in required {
+        if !find_ignoring_case(source, name)?.is_file() {
+            return Err(anyhow!("no {} in {}", name, source.to_string_lossy()));
+        }
+    }
+    for name in expected {
+        if !find_ignoring_case(source, name)?.is_file() {
+            session.warn(format!("no {} in {}, the game may not start", name, source.to_string_lossy()));
+        }
+    }
+    // copy chitin.key, engine.lua... which can be modded
+    // the other non-dirs are supposed to be game exe's and will be linked (on windows, only the .exe and .dll are,
+    // the other files at the root are small and copied)
+    let source_files = read_dir(source)?;
+    for file in source_files {
+        let file = file?;
+        let is_dir = is_dir_entry(&file)?;
+        // the known dirs are processed below
+        if is_dir && KNOWN_GAME_DIRS.iter().any(|known| file.file_name() == *known) {
+            continue;
+        }
+        if let Some(action) = session.rule_for(&file.path()) {
+            apply_rule(session, source, target, &file.file_name(), action)?;
+            continue;
+        }
+        if is_dir && session.variant == Variant::Classic && is_classic_linked_dir(&file.file_name()) {
+            link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+            continue;
+        }
+        // an unexpected dir, left out
+        if is_dir {
+            continue;
+        }
+        let name = file.file_name();
+        let is_binary = WINDOWS_BINARIES.iter().any(|extension| has_extension(&name.to_string_lossy(), extension));
+        if session.is_copy_target(&name) || (session.os == Os::Win && !is_binary) {
+            copy_item_os(session, source, target, &file.file_name())?;
+        } else {
+            link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+        }
+    }
+    // link the dir: Manual
+    if source.join("Manuals").exists() {
+        session.info(format!("{}", session.paint(Blue.bold(), " => Manuals/")));
+        link_item(session, source, target, "Manuals")?;
+    }
+    // create the dir, copy the content: scripts (scripts can be customized, added)
+    // continue with the other dirs
+    // create the dirs: data, lang (EE only), movies, music
+    for section in game_sections(session.variant) {
+        process_game_subdir(session, source, target, *section)?;
+    }
+    session.info(format!("{}", session.paint(Blue.bold(), " <= done")));
+
+    let root_override_dir = source.join("override");
+    if root_override_dir.exists() {
+        session.info(format!("{}", session.paint(Blue.bold(), " => override")));
+        session.timed("override", || process_override(session, source, target))?;
+    } else if create_dir_str(session, source, target, "override")? {
+        //create override/ dir anyway (unless excluded)
+        session.warn(format!("no {}", root_override_dir.to_string_lossy()));
+    }
+
+    // anything else (mod folders, WeiDU.log...) was added locally
+    if session.flatten && source != session.source_root {
+        check_local_additions_of(
+            session,
+            &[source, &session.source_root],
+            target,
+            &["override", STATE_FILE, MANIFEST_FILE, SOURCE_FILE],
+        )?;
+    } else {
+        check_local_additions(session, source, target, &["override", STATE_FILE, MANIFEST_FILE, SOURCE_FILE])?;
+    }
+
+    // done
+    Ok(())
+}
+
+/// Creates the dir of `section` (scripts, data, lang, movies or music) in the game dir `target` and fills it
+fn process_game_subdir(session: &Session, source: &Path, target: &Path, section: Section) -> Result<()> {
+    let name = section.dir_name();
+    session.info(format!("{}", session.paint(Blue.bold(), format!(" => {}/", name))));
+    if !create_dir_str(session, source, target, name)? {
+        return Ok(());
+    }
+    let (source, target) = (source.join(name), target.join(name));
+    session.timed(name, || match section {
+        Section::Scripts => process_scripts_dir(session, &source, &target),
+        Section::Data => process_data_dir(session, &source, &target),
+        Section::Lang => process_lang_dir(session, &source, &target),
+        Section::Movies => process_movies_dir(session, &source, &target),
+        Section::Music => process_music_dir(session, &source, &target),
+        Section::Game | Section::Override => Err(anyhow!("{:?} is not a plain dir of the game dir", section)),
+    })
+}
+
+/// Duplicates `source/override` as `target/override`, as chosen with --override-mode
+fn process_override(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    match session.override_mode {
+        OverrideMode::LinkDir => link_item(session, source, target, "override"),
+        OverrideMode::Copy | OverrideMode::Link => {
+            if create_dir_str(session, source, target, "override")? {
+                process_override_dir(session, &source.join("override"), &target.join("override"))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Copies or links (with --override-mode=link) the files of an override dir. The subdirs some mods create are
+/// recreated and processed the same way.
+fn process_override_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    check_depth(&session.source_root, source, session.max_depth)?;
+    session.enter_dir(source);
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if is_dir_entry(&entry)? {
+            if create_dir_os(session, source, target, &name)? {
+                process_override_dir(session, &entry.path(), &target.join(&name))?;
+            }
+        } else if session.override_mode == OverrideMode::Link {
+            link_item_os(session, source, target, &name, LinkKind::Symbolic)?;
+        } else {
+            copy_item_os(session, source, target, &name)?;
+        }
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+fn copy_content(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    let scripts = read_dir(source)?;
+    for file in scripts {
+        let file = file?;
+        copy_item_os(session, source, target, &file.file_name())?;
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+fn process_scripts_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    //copy content
+    copy_content(session, source, target)
+}
+
+fn process_data_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    // link all files inside(should all be .bif)
+    link_all_inside(session, source, target)?;
+    Ok(())
+}
+
+/// The .bif files of the data/ dir of a language with the same path (in any case, the game ignores it) in the base
+/// data/ dir, sorted
+fn shadowed_bifs(language_data_dir: &Path, base_data_dir: &Path) -> Result<Vec<String>> {
+    if !base_data_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let (mut language_bifs, mut base_bifs) = (BTreeMap::new(), BTreeMap::new());
+    bif_paths(language_data_dir, "", &mut language_bifs)?;
+    bif_paths(base_data_dir, "", &mut base_bifs)?;
+    Ok(language_bifs
+        .into_iter()
+        .filter(|(lowercase, _)| base_bifs.contains_key(lowercase))
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Adds the .bif files of `dir` (and its subdirs) to `bifs`, by their lowercase path relative to the data/ dir
+fn bif_paths(dir: &Path, prefix: &str, bifs: &mut BTreeMap<String, String>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if is_dir_entry(&entry)? {
+            bif_paths(&entry.path(), &format!("{}/", path), bifs)?;
+        } else if has_extension(&path, "bif") {
+            bifs.insert(path.to_lowercase(), path);
+        }
+    }
+    Ok(())
+}
+
+fn process_lang_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    // each language in a subdir (for ex. en_US)
+    let languages = read_dir(source)?;
+    let mut found = Vec::new();
+    for language in languages {
+        let language = language?;
+        let is_dir = is_dir_entry(&language)?;
+        let language = language.file_name();
+        if !is_dir || !is_locale(&language.to_string_lossy()) {
+            session.warn(format!("skip {}, not a language", source.join(&language).to_string_lossy()));
+            continue;
+        }
+        if !KNOWN_LOCALES.iter().any(|known| session.name_is(&language, known)) {
+            session.info(format!("unknown language {}, processed like the others", language.to_string_lossy()));
+        }
+        if !session.languages.is_empty() {
+            if !session.languages.iter().any(|kept| session.name_is(&language, kept)) {
+                session.warn(format!("skip language {}", language.to_string_lossy()));
+                continue;
+            }
+            found.push(language.to_string_lossy().into_owned());
+        }
+        if create_dir_os(session, source, target, &language)? {
+            process_language(session, &source.join(&language), &target.join(&language), &language.to_string_lossy())?;
+        }
+    }
+    for missing in session
+        .languages
+        .iter()
+        .filter(|kept| !found.iter().any(|language| session.name_is(OsStr::new(language), kept)))
+    {
+        session.warn(format!("no language {} in {}", missing, source.to_string_lossy()));
+    }
+
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+/// whether `name` looks like a locale (xx_XX)
+fn is_locale(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() == 5 && bytes[..2].iter().all(u8::is_ascii_alphabetic) && bytes[2] == b'_' && bytes[3..].iter().all(u8::is_ascii_alphabetic)
+}
+
+fn process_language(session: &Session, source: &Path, target: &Path, language_mark: &str) -> Result<()> {
+    session.enter_dir(source);
+    // in each language subdir,
+    // - one dialog.tlk OR dialog.tlk+dialogF.tlk -> copy because those are modifiable
+    // - [maybe]one movies subdir with root wbm and lo/ and 480/ -> like movies at root
+    // - [maybe]one sounds/ subdir
+    // - [maybe]one data/ subdir (ex: de_DE)
+    // - [maybe]one override/ subdir (ex: de_DE)
+
+    session.info(format!(
+        "{} to {}",
+        session.paint(Blue.bold(), source.to_string_lossy()),
+        session.paint(Green.normal(), target.to_string_lossy())
+    ));
+
+    copy_tlk_files(session, source, target, language_mark)?;
+    let source_movies_dir = source.join("movies");
+    if source_movies_dir.exists() && session.skip_lang_movies {
+        session.info(format!("skip the movies/ of {} (--skip-lang-movies)", language_mark));
+    } else if source_movies_dir.exists() {
+        let target_movies_dir = target.join("movies");
+        session.info(format!(
+            "{} to {}",
+            session.paint(Blue.bold(), source_movies_dir.to_string_lossy()),
+            session.paint(Green.normal(), target_movies_dir.to_string_lossy())
+        ));
+        if create_dir_str(session, source, target, "movies")? {
+            process_movies_dir(session, &source_movies_dir, &target_movies_dir)?;
+        }
+    } else {
+        session.warn(format!("no movies/ for {}", language_mark));
+    }
+    let source_sounds_dir = source.join("sounds");
+    if source_sounds_dir.exists() && session.skip_lang_sounds {
+        session.info(format!("skip the sounds/ of {} (--skip-lang-sounds)", language_mark));
+    } else if source_sounds_dir.exists() {
+        let target_sounds_dir = target.join("sounds");
+        session.info(format!(
+            "{} to {}",
+            session.paint(Blue.bold(), source_sounds_dir.to_string_lossy()),
+            session.paint(Green.normal(), target_sounds_dir.to_string_lossy())
+        ));
+        if create_dir_str(session, source, target, "sounds")? {
+            process_sound_dir(session, &source_sounds_dir, &target_sounds_dir)?;
+        }
+    } else {
+        session.warn(format!("no sounds/ for {}", language_mark));
+    }
+    let source_override_dir = source.join("override");
+    if source_override_dir.exists() {
+        let target_override_dir = target.join("override");
+        session.info(format!(
+            "{} to {}",
+            session.paint(Blue.bold(), source_override_dir.to_string_lossy()),
+            session.paint(Green.normal(), target_override_dir.to_string_lossy())
+        ));
+        process_override(session, source, target)?;
+    } else {
+        session.warn(format!("no override/ for {}", language_mark));
+    }
+    let source_data_dir = source.join("data");
+    if source_data_dir.exists() {
+        let target_data_dir = target.join("data");
+        session.info(format!(
+            "{} to {}",
+            session.paint(Blue.bold(), source_data_dir.to_string_lossy()),
+            session.paint(Green.normal(), target_data_dir.to_string_lossy())
+        ));
+        if create_dir_str(session, source, target, "data")? {
+            process_data_dir(session, &source_data_dir, &target_data_dir)?;
+        }
+        // the game dir is above lang/
+        if let Some(base_data_dir) = source.parent().and_then(Path::parent).map(|game_dir| game_dir.join("data")) {
+            let shadowed = shadowed_bifs(&source_data_dir, &base_data_dir)?;
+            if !shadowed.is_empty() {
+                session.info(format!(
+                    "{} bif(s) of {} have the same name as those of {}, the game uses the localized ones: {}",
+                    shadowed.len(),
+                    source_data_dir.to_string_lossy(),
+                    base_data_dir.to_string_lossy(),
+                    shadowed.join(", ")
+                ));
+            }
+        }
+    } else {
+        session.warn(format!("no data/ for {}", language_mark));
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+/// Copies dialog.tlk, which every language has, and dialogF.tlk (female variants of the texts) when there is one.
+/// Any other file at the root of the language dir is copied too.
+fn copy_tlk_files(session: &Session, source: &Path, target: &Path, language_mark: &str) -> Result<()> {
+    // some classic and mod-built installs have a dialogf.tlk or a DIALOG.TLK, whatever the filesystem
+    let dialog_f = find_ignoring_case(source, "dialogF.tlk")?;
+    let dialog_f_name = dialog_f.file_name().unwrap_or_default();
+    let has_dialog_f = dialog_f.is_file();
+    let dialog = find_ignoring_case(source, "dialog.tlk")?;
+    let dialog_name = dialog.file_name().unwrap_or_default();
+    if !dialog.is_file() {
+        return Err(if has_dialog_f {
+            anyhow!("{} has a dialogF.tlk but no dialog.tlk", source.to_string_lossy())
+        } else {
+            anyhow!(
+                "neither dialog.tlk nor dialogF.tlk in {}, the {} language is broken",
+                source.to_string_lossy(),
+                language_mark
+            )
+        });
+    }
+    copy_item_os(session, source, target, dialog_name)?;
+    if has_dialog_f {
+        copy_item_os(session, source, target, dialog_f_name)?;
+    }
+    for file in read_dir(source)? {
+        let file = file?;
+        if !is_dir_entry(&file)? && file.file_name() != dialog_name && file.file_name() != dialog_f_name {
+            copy_item_os(session, source, target, &file.file_name())?;
+        }
+    }
+    Ok(())
+}
+
+fn process_sound_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    // *.wav files and one sndlist.txt -> create dir, link *.wav, copy sndlist.txt
+    let files = read_dir(source)?;
+    for file in files {
+        let file = file?;
+        let file_path = file.path();
+        let ext = file_path.extension().unwrap_or(&*NO_EXT);
+        if session.name_is(ext, "wav") {
+            link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+        } else {
+            copy_item_os(session, source, target, &file.file_name())?;
+        }
+    }
+
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+fn link_non_dirs(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    let files = read_dir(source)?;
+    for file in files {
+        let file = file?;
+        if !is_dir_entry(&file)? {
+            link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+        }
+    }
+    Ok(())
+}
+
+fn process_movies_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    // one set of movies at the root, others in subdirs by resolution (480, lo)
+    // link all root movies (non-dir files)
+    link_non_dirs(session, source, target)?;
+    for known in KNOWN_MOVIE_DIRS {
+        if !source.join(known).exists() {
+            session.info(format!("no {} (not in every release)", source.join(known).to_string_lossy()));
+        }
+    }
+    // every subdir is linked file by file, the unknown ones too
+    for dir in read_dir(source)? {
+        let dir = dir?;
+        if !is_dir_entry(&dir)? {
+            continue;
+        }
+        let name = dir.file_name();
+        if !KNOWN_MOVIE_DIRS.iter().any(|known| name == *known) {
+            session.warn(format!("unknown movies dir {}, linked too", dir.path().to_string_lossy()));
+        }
+        if create_dir_os(session, source, target, &name)? {
+            link_all_inside(session, &dir.path(), &target.join(&name))?;
+        }
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+fn process_music_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    // some .mus file at the root (couple dozen bytes each, 40 files or so)
+    // one lone .acm file
+    // around 40 directories with  some .acm inside
+    // create the directories, the files at the root and in the directories are copied or linked by extension
+    let music_files = read_dir(source)?;
+    for file in music_files {
+        let file = file?;
+        if is_dir_entry(&file)? {
+            if create_dir_os(session, source, target, &file.file_name())? {
+                process_song_dir(session, &source.join(&file.file_name()), &target.join(&file.file_name()))?;
+            }
+        } else {
+            process_music_file(session, source, target, &file.file_name())?;
+        }
+    }
+
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+/// The files of a song dir are duplicated like those of the music root, its dirs are linked
+fn process_song_dir(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    for file in read_dir(source)? {
+        let file = file?;
+        if is_dir_entry(&file)? {
+            link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+        } else {
+            process_music_file(session, source, target, &file.file_name())?;
+        }
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+/// Copies or links a music file, by its extension
+fn process_music_file(session: &Session, source: &Path, target: &Path, name: &OsStr) -> Result<()> {
+    let file_path = source.join(name);
+    let extension = file_path.extension().unwrap_or(&*NO_EXT);
+    match session.music_action(extension) {
+        Some(action) => apply_rule(session, source, target, name, action),
+        None => {
+            session.warn(format!("unexpected file {} in music/, it will be linked", file_path.to_string_lossy()));
+            link_item_os(session, source, target, name, LinkKind::Symbolic)
+        }
+    }
+}
+
+fn link_all_inside(session: &Session, source: &Path, target: &Path) -> Result<()> {
+    session.enter_dir(source);
+    let files = read_dir(source)?;
+    for file in files {
+        let file = file?;
+        link_item_os(session, source, target, &file.file_name(), LinkKind::Symbolic)?;
+    }
+    check_local_additions(session, source, target, &[])?;
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Os {
+    Linux,
+    Win,
+    Mac,
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Vendor {
+    Gog,
+    Steam,
+    Beamdog,
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Variant {
+    Classic,
+    Ee,
+}
+struct HintStructure {
+    os: Os,
+    vendor: Vendor,
+    variant: Variant,
+}
+
+/// What was found in a source dir
+pub struct GameDescription {
+    pub os: Os,
+    pub vendor: Vendor,
+    pub variant: Variant,
+    pub game: Game,
+    /// where chitin.key is
+    pub game_dir: PathBuf,
+    pub key: chitin::Key,
+    /// the Steam app manifest, if found
+    pub manifest: Option<PathBuf>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub build: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Game {
+    Bg1Ee,
+    Bg2Ee,
+    IwdEe,
+    PstEe,
+    Unknown,
+}
+
+impl std::fmt::Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Game::Bg1Ee => "BG1EE",
+            Game::Bg2Ee => "BG2EE",
+            Game::IwdEe => "IWDEE",
+            Game::PstEe => "PSTEE",
+            Game::Unknown => "unknown game",
+        };
+        f.write_str(name)
+    }
+}
+
+/// game executables (linux and windows names), a file that's present in only one of the games
+static GAME_SIGNATURES: &[(&str, Game)] = &[
+    ("Torment", Game::PstEe),
+    ("Torment.exe", Game::PstEe),
+    ("IcewindDale", Game::IwdEe),
+    ("Icewind.exe", Game::IwdEe),
+    ("BaldursGateII", Game::Bg2Ee),
+    ("BaldursGate", Game::Bg1Ee),
+];
+
+/// Finds which game is installed in `game_dir` (the directory containing chitin.key)
+fn detect_game(game_dir: &Path) -> Result<(Game, chitin::Key)> {
+    let key_path = find_ignoring_case(game_dir, "chitin.key")?;
+    if key_path.metadata()?.len() == 0 {
+        // nothing to detect from, the copy will report it
+        return Ok((
+            Game::Unknown,
+            chitin::Key {
+                resource_count: 0,
+                bif_names: vec![],
+            },
+        ));
+    }
+    let key = chitin::read_key(&key_path)?;
+    // Throne of Bhaal bifs are the only ones prefixed with 25 (25AmbSnd.bif, 25Areas.bif...)
+    if key.bif_names.iter().any(|bif| chitin::bif_file_name(bif).starts_with("25")) {
+        return Ok((Game::Bg2Ee, key));
+    }
+    for (signature, game) in GAME_SIGNATURES {
+        if game_dir.join(signature).exists() {
+            return Ok((*game, key));
+        }
+    }
+    Ok((Game::Unknown, key))
+}
+
+/// Whether the files of `game_dir` can be reflinked in `dir`, found by reflinking chitin.key
+fn supports_reflinks(game_dir: &Path, dir: &Path) -> Result<()> {
+    let key = find_ignoring_case(game_dir, "chitin.key")?;
+    let probe = dir.join(".iedup-reflink-probe");
+    mounts::reflink(&key, &probe)?;
+    std::fs::remove_file(&probe).with_context(|| format!("failed to remove {}", probe.to_string_lossy()))
+}
+
+/// Whether `dir` can hold symbolic links, found by creating one (failures that are not about links are errors)
+fn supports_symlinks(dir: &Path) -> Result<bool> {
+    // what FAT/exFAT and some network filesystems answer
+    const EPERM: i32 = 1;
+    let probe = dir.join(".iedup-link-probe");
+    match std::os::unix::fs::symlink(".", &probe) {
+        Ok(()) => {
+            std::fs::remove_file(&probe).with_context(|| format!("failed to remove {}", probe.to_string_lossy()))?;
+            Ok(true)
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::Unsupported || error.raw_os_error() == Some(EPERM) => Ok(false),
+        Err(error) => Err(error).with_context(|| format!("failed to create a test link in {}", dir.to_string_lossy())),
+    }
+}
+
+/// Fails if --link-base doesn't have the key of the source
+fn check_link_base(source: &Path, description: &GameDescription, link_base: &Path) -> Result<()> {
+    if !link_base.is_dir() {
+        return Err(anyhow!("link base {} is not a directory", link_base.to_string_lossy()));
+    }
+    let key = link_base
+        .join(description.game_dir.strip_prefix(source).unwrap_or(Path::new("")))
+        .join("chitin.key");
+    if !key.is_file() {
+        return Err(anyhow!(
+            "link base {} doesn't look like the source, there is no {}",
+            link_base.to_string_lossy(),
+            key.to_string_lossy()
+        ));
+    }
+    Ok(())
+}
+
+/// Fails with the list of the bifs referenced by chitin.key that are neither in the game dir (usually data/) nor in the
+/// data/ dir of a language
+fn check_bifs(description: &GameDescription) -> Result<()> {
+    let game_dir = &description.game_dir;
+    let lang_dir = game_dir.join("lang");
+    let mut missing = Vec::new();
+    for bif_name in &description.key.bif_names {
+        let relative = bif_name.replace('\\', "/");
+        let relative = Path::new(&relative);
+        let exists = |dir: &Path| match (relative.parent(), relative.file_name()) {
+            (Some(parent), Some(name)) => {
+                dir.join(parent).is_dir() && find_ignoring_case(&dir.join(parent), &name.to_string_lossy()).map_or(false, |path| path.is_file())
+            }
+            _ => false,
+        };
+        let mut found = exists(game_dir);
+        if !found && lang_dir.is_dir() {
+            for language in read_dir(&lang_dir)? {
+                if exists(&language?.path()) {
+                    found = true;
+                    break;
+                }
+            }
+        }
+        if !found {
+            missing.push(bif_name.as_str());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(AnyError::new(IedupError::MissingBifs {
+            path: game_dir.clone(),
+            missing: missing.iter().map(|name| name.to_string()).collect(),
+        }));
+    }
+    Ok(())
+}
+
+/// `dir/name`, or the entry of `dir` with the same name in another case (CHITIN.KEY...) if there is one
+fn find_ignoring_case(dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(path);
+    }
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().eq_ignore_ascii_case(name) {
+            return Ok(entry.path());
+        }
+    }
+    Ok(path)
+}
+
+fn config_files(variant: &Variant) -> Result<Vec<Pattern>> {
+    let names = match variant {
+        Variant::Ee => EE_CONFIG_FILES,
+        Variant::Classic => CLASSIC_CONFIG_FILES,
+    };
+    Ok(names.iter().map(|name| Pattern::new(name)).collect::<Result<_, _>>()?)
+}
+
+/// the required and the expected config files of the variant
+fn game_files(variant: Variant) -> (&'static [&'static str], &'static [&'static str]) {
+    match variant {
+        Variant::Ee => (EE_REQUIRED_FILES, EE_EXPECTED_FILES),
+        Variant::Classic => (CLASSIC_REQUIRED_FILES, CLASSIC_EXPECTED_FILES),
+    }
+}
+
+fn game_sections(variant: Variant) -> &'static [Section] {
+    match variant {
+        Variant::Ee => EE_GAME_SECTIONS,
+        Variant::Classic => CLASSIC_GAME_SECTIONS,
+    }
+}
+
+/// whether `name` is one of CLASSIC_LINKED_DIRS
+fn is_classic_linked_dir(name: &OsStr) -> bool {
+    CLASSIC_LINKED_DIRS.iter().any(|dir| name.to_string_lossy().eq_ignore_ascii_case(dir))
+}
+
+/// Guesses the kind of install from its layout
+fn guess_structure(dir: &Path) -> HintStructure {
+    // GOG wraps the linux game dir with a launcher script, Steam and the windows installs have the game dir at the root
+    let is_flat = dir.join("chitin.key").is_file() && !dir.join("start.sh").exists();
+    let has_root_file = |is_wanted: &dyn Fn(&str) -> bool| {
+        dir.read_dir()
+            .map(|entries| entries.flatten().any(|entry| is_wanted(&entry.file_name().to_string_lossy())))
+            .unwrap_or(false)
+    };
+    let os = if is_flat && has_root_file(&|name| has_extension(name, "exe")) {
+        Os::Win
+    } else {
+        Os::Linux
+    };
+    // the windows GOG installs have their goggame-<id>.info, the Beamdog client leaves its build id
+    let vendor = if is_flat && dir.join(BEAMDOG_BUILD_ID).is_file() {
+        Vendor::Beamdog
+    } else if is_flat && !(os == Os::Win && has_root_file(&|name| name.starts_with("goggame-"))) {
+        Vendor::Steam
+    } else {
+        Vendor::Gog
+    };
+    // the classic installs have dialog.tlk next to chitin.key, the EE have it in lang/
+    let variant = if is_flat && !dir.join("lang").is_dir() && find_ignoring_case(dir, "dialog.tlk").map_or(false, |path| path.is_file()) {
+        Variant::Classic
+    } else {
+        Variant::Ee
+    };
+    HintStructure { os, vendor, variant }
+}
+
+/// whether `name` ends with the extension `extension`, in any case
+fn has_extension(name: &str, extension: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .map_or(false, |name_extension| name_extension.to_string_lossy().eq_ignore_ascii_case(extension))
+}
+
+/// Detects the game installed in `source`, fails if it's not an install iedup knows about
+pub fn detect(source: &Path) -> Result<GameDescription, IedupError> {
+    check_source(source, &guess_structure(source)).map_err(|error| IedupError::DetectionFailed {
+        path: source.to_path_buf(),
+        source: error.into(),
+    })
+}
+
+/// The source `target` was built from, as recorded in it by the run that built it. Fails if that source isn't there
+/// anymore or doesn't hold the same game
+pub fn recorded_source(target: &Path) -> Result<PathBuf, IedupError> {
+    let path = target.join(SOURCE_FILE);
+    if !path.is_file() {
+        return Err(IedupError::Usage(format!(
+            "no source given and none recorded in {}",
+            target.to_string_lossy()
+        )));
+    }
+    let origin = origin::read(&path).map_err(IedupError::from_any)?;
+    let description = detect(&origin.source)?;
+    if description.game.to_string() != origin.game {
+        return Err(IedupError::GameChanged {
+            path,
+            found: description.game.to_string(),
+            expected: origin.game,
+        });
+    }
+    Ok(origin.source)
+}
+
+/// What a target holds (iedup list)
+pub struct Listing {
+    /// the source it was built from and the id of its game, as recorded in it
+    pub source: Option<(PathBuf, String)>,
+    /// its copies, links and dirs by category
+    pub categories: BTreeMap<String, Stats>,
+}
+
+/// The source recorded in `target` and what it holds, by category
+pub fn list(target: &Path) -> Result<Listing, IedupError> {
+    if !target.is_dir() {
+        return Err(IedupError::TargetNotDir { path: target.to_path_buf() });
+    }
+    let path = target.join(SOURCE_FILE);
+    let source = if path.is_file() {
+        let origin = origin::read(&path).map_err(IedupError::from_any)?;
+        Some((origin.source, origin.game))
+    } else {
+        None
+    };
+    let categories = stats::inventory(target, &[STATE_FILE, MANIFEST_FILE, SOURCE_FILE])
+        .with_context(|| format!("failed to read {}", target.to_string_lossy()))
+        .map_err(IedupError::from_any)?;
+    Ok(Listing { source, categories })
+}
+
+/// Removes `target`, which must have been built by iedup (with its source recorded): its links are removed, never what
+/// they lead to (the copies of --writable-root stay). Unless `force`, refuses when it has files that aren't copies of
+/// the source (added or changed since the build: mods, saves...), found with its manifest when it has one, or else at
+/// the same paths in the source (refused for the layouts of --flatten and --lowercase-names). Returns the files (not
+/// the links) removed, or that would be with `dry_run`
+pub fn remove(target: &Path, force: bool, dry_run: bool) -> Result<Vec<PathBuf>, IedupError> {
+    try_remove(target, force, dry_run).map_err(IedupError::from_any)
+}
+
+fn try_remove(target: &Path, force: bool, dry_run: bool) -> Result<Vec<PathBuf>> {
+    if !target.is_dir() {
+        return Err(AnyError::new(IedupError::TargetNotDir { path: target.to_path_buf() }));
+    }
+    let origin_path = target.join(SOURCE_FILE);
+    if !origin_path.is_file() {
+        return Err(AnyError::new(IedupError::Usage(format!(
+            "{} has no {}, it wasn't built by iedup, refusing to remove it",
+            target.to_string_lossy(),
+            SOURCE_FILE
+        ))));
+    }
+    let origin = origin::read(&origin_path)?;
+    let source = origin.source;
+    let manifest_path = target.join(MANIFEST_FILE);
+    let manifest = if manifest_path.is_file() {
+        Some(manifest::read(&manifest_path)?)
+    } else {
+        None
+    };
+    if manifest.is_none() && origin.moved && !force {
+        return Err(AnyError::new(IedupError::Usage(format!(
+            "{} was built with --flatten or --lowercase-names and has no {}, its files can't be matched with those of {}, \
+             use --force to remove it anyway",
+            target.to_string_lossy(),
+            MANIFEST_FILE,
+            source.to_string_lossy()
+        ))));
+    }
+    let entries: HashMap<&Path, &manifest::Entry> = manifest
+        .iter()
+        .flat_map(|manifest| &manifest.entries)
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    let mut files = Vec::new();
+    list_files(target, target, &[STATE_FILE, MANIFEST_FILE, SOURCE_FILE], &mut files)?;
+    let mut local = Vec::new();
+    for file in &files {
+        let relative = file.strip_prefix(target)?;
+        let is_copy = match &manifest {
+            Some(_) => match entries.get(relative).map(|entry| (entry.kind, &entry.source, entry.size)) {
+                // the file of an archive isn't read, only its size compared
+                Some((ActionKind::Extract, _, size)) => size == Some(file.metadata()?.len()),
+                Some((ActionKind::Copy, Some(original), _)) | Some((ActionKind::Hardlink, Some(original), _)) => {
+                    original.is_file() && !diff::differs(original, file)?
+                }
+                _ => false,
+            },
+            None => {
+                let original = source.join(relative);
+                original.is_file() && !diff::differs(&original, file)?
+            }
+        };
+        if !is_copy {
+            local.push(file.to_string_lossy().into_owned());
+        }
+    }
+    if !local.is_empty() && !force {
+        return Err(AnyError::new(IedupError::Usage(format!(
+            "{} has {} file(s) that aren't copies of {} ({}), use --force to remove them too",
+            target.to_string_lossy(),
+            local.len(),
+            source.to_string_lossy(),
+            local.join(", ")
+        ))));
+    }
+    if !dry_run {
+        // remove_dir_all doesn't follow the links
+        std::fs::remove_dir_all(target).with_context(|| format!("failed to remove {}", target.to_string_lossy()))?;
+    }
+    Ok(files)
+}
+
+/// Adds to `files` the files of `dir` and of its subdirs, without following the links, except the `generated` ones at
+/// the root of `target`
+fn list_files(target: &Path, dir: &Path, generated: &[&str], files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if dir == target && generated.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        if file_type.is_dir() {
+            list_files(target, &entry.path(), generated, files)?;
+        } else if !file_type.is_symlink() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn check_source(dir: &Path, hint: &HintStructure) -> Result<GameDescription, AnyError> {
+    // should have a start.sh script, a game and support
+    match hint {
+        HintStructure {
+            os: Os::Linux,
+            vendor: Vendor::Gog,
+            variant: Variant::Ee,
+        } => check_source_linux_gog_ee(dir, hint),
+        HintStructure {
+            vendor: Vendor::Beamdog,
+            variant: Variant::Ee,
+            ..
+        } => check_source_beamdog_ee(dir, hint),
+        HintStructure {
+            os: Os::Win,
+            variant: Variant::Ee,
+            ..
+        } => check_source_windows_ee(dir, hint),
+        HintStructure {
+            vendor: Vendor::Steam,
+            variant: Variant::Ee,
+            ..
+        } => check_source_steam_ee(dir),
+        HintStructure {
+            variant: Variant::Classic, ..
+        } => check_source_classic(dir, hint),
+        _ => Err(anyhow!("don't know yet how to process this variant")),
+    }
+}
+
+fn check_source_linux_gog_ee(dir: &Path, hint: &HintStructure) -> Result<GameDescription> {
+    let start_sh = dir.join("start.sh");
+    let game_dir = dir.join("game");
+    let support_dir = dir.join("support");
+    if !(start_sh.exists() && game_dir.is_dir() && support_dir.is_dir()) {
+        return Err(anyhow!("Nope, not a game dir"));
+    }
+    let (game, key) = detect_game(&game_dir)?;
+    let (name, version, build) = read_gameinfo(&dir.join("gameinfo"))?;
+
+    return Ok(GameDescription {
+        os: Os::Linux,
+        vendor: Vendor::Gog,
+        variant: Variant::Ee,
+        game,
+        game_dir,
+        key,
+        manifest: None,
+        name,
+        version,
+        build,
+    });
+}
+
+/// Reads the name, version and build of the game from the first lines of the GOG gameinfo file, if there is one
+fn read_gameinfo(path: &Path) -> Result<(Option<String>, Option<String>, Option<String>)> {
+    if !path.is_file() {
+        return Ok((None, None, None));
+    }
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .map(|line| Some(line.to_string()).filter(|line| !line.is_empty()));
+    Ok((lines.next().flatten(), lines.next().flatten(), lines.next().flatten()))
+}
+
+/// Fails if the version of the source isn't `expected` (or doesn't start with the same major.minor in loose mode)
+fn check_version(description: &GameDescription, expected: &str, loose: bool) -> Result<()> {
+    let version = description.version.as_deref().ok_or_else(|| {
+        AnyError::new(IedupError::WrongVersion {
+            found: None,
+            expected: expected.to_string(),
+        })
+    })?;
+    let major_minor = |version: &str| version.split('.').take(2).collect::<Vec<_>>().join(".");
+    let matches = if loose {
+        major_minor(version) == major_minor(expected)
+    } else {
+        version == expected
+    };
+    if !matches {
+        return Err(AnyError::new(IedupError::WrongVersion {
+            found: Some(version.to_string()),
+            expected: expected.to_string(),
+        }));
+    }
+    Ok(())
+}
+
+/// A windows install (GOG or Steam, in a wine prefix or on a windows partition): the game dir is at the root, with
+/// the .exe and .dll
+fn check_source_windows_ee(dir: &Path, hint: &HintStructure) -> Result<GameDescription> {
+    // the same layout as the linux Steam installs
+    let description = check_source_steam_ee(dir)?;
+    Ok(GameDescription {
+        os: Os::Win,
+        vendor: hint.vendor,
+        manifest: description.manifest.filter(|_| hint.vendor == Vendor::Steam),
+        ..description
+    })
+}
+
+fn check_source_beamdog_ee(dir: &Path, hint: &HintStructure) -> Result<GameDescription> {
+    // the same layout as the Steam installs, with the build id of the client instead of a manifest
+    if !(dir.join("chitin.key").is_file() && dir.join("lang").is_dir()) {
+        return Err(anyhow!("Nope, not a game dir"));
+    }
+    let (game, key) = detect_game(dir)?;
+    let build_id_path = dir.join(BEAMDOG_BUILD_ID);
+    let build_id = std::fs::read_to_string(&build_id_path).with_context(|| format!("failed to read {}", build_id_path.to_string_lossy()))?;
+    Ok(GameDescription {
+        os: hint.os,
+        vendor: Vendor::Beamdog,
+        variant: Variant::Ee,
+        game,
+        game_dir: dir.to_path_buf(),
+        key,
+        manifest: None,
+        name: None,
+        version: None,
+        build: Some(build_id.trim().to_string()).filter(|build_id| !build_id.is_empty()),
+    })
+}
+
+/// A classic install (on a windows partition or in a wine prefix): chitin.key, dialog.tlk and the .ini are at the root
+/// with the exe, there is no lang/
+fn check_source_classic(dir: &Path, hint: &HintStructure) -> Result<GameDescription> {
+    if !(dir.join("chitin.key").is_file() && find_ignoring_case(dir, "dialog.tlk")?.is_file()) {
+        return Err(anyhow!("Nope, not a game dir"));
+    }
+    // the games are told apart by the layout of the EE
+    let (_, key) = detect_game(dir)?;
+    Ok(GameDescription {
+        os: hint.os,
+        vendor: hint.vendor,
+        variant: Variant::Classic,
+        game: Game::Unknown,
+        game_dir: dir.to_path_buf(),
+        key,
+        manifest: None,
+        name: None,
+        version: None,
+        build: None,
+    })
+}
+
+fn check_source_steam_ee(dir: &Path) -> Result<GameDescription> {
+    // chitin.key is at the root of the install dir
+    if !(dir.join("chitin.key").is_file() && dir.join("lang").is_dir()) {
+        return Err(anyhow!("Nope, not a game dir"));
+    }
+    let (game, key) = detect_game(dir)?;
+
+    let (manifest, name, build) = match steam::find_app_manifest(dir)? {
+        Some((manifest, mut values)) => (Some(manifest), values.remove("name"), values.remove("buildid")),
+        None => (None, None, None),
+    };
+
+    Ok(GameDescription {
+        os: Os::Linux,
+        vendor: Vendor::Steam,
+        variant: Variant::Ee,
+        game,
+        game_dir: dir.to_path_buf(),
+        key,
+        manifest,
+        name,
+        version: None,
+        build,
+    })
+}
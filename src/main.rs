@@ -1,446 +1,678 @@
-use ansi_term::Colour::{Blue, Green, Red, Yellow};
-use anyhow::anyhow;
-use anyhow::Error as AnyError;
-use anyhow::Result;
-use clap::Clap;
-use once_cell::sync::Lazy;
-use std::ffi::OsStr;
-use std::path::Path;
-use glob::glob;
+use anyhow::{anyhow, Error as AnyError, Result};
+use clap::{AppSettings, Clap, FromArgMatches, IntoApp};
+use clap_generate::generators::{Bash, Fish, PowerShell, Zsh};
+use iedup::plan::Shell;
+use iedup::{
+    defaults, permissions, profiles, stats, timestamp, ColorChoice, Config, CopyMode, FailureKind, IedupError, OverrideMode, PlanFormat, Preserve,
+    RootItem, Section,
+};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clap)]
-#[clap(version = "1.0", author = "Mickaël Leduque <mleduque@gmail.com>")]
+#[clap(
+    version = "1.0",
+    author = "Mickaël Leduque <mleduque@gmail.com>",
+    setting = AppSettings::SubcommandsNegateReqs
+)]
 struct Opts {
-    source: String,
-    target: String,
+    /// The game install to duplicate (the same as iedup create). With only the target, --verify, --merge and --resume
+    /// use the source recorded in it by the run that built it
+    #[clap(required_unless_present = "list-profiles")]
+    source: Option<String>,
+    #[clap(required_unless_present_any = &["list-profiles", "verify", "merge", "resume", "diff"])]
+    target: Option<String>,
+    /// Don't show the progress bar (it's never shown when the output is not a terminal)
+    #[clap(long)]
+    no_progress: bool,
+    /// Show each copy, link and created dir (-v), with the file sizes (-vv) and where the links resolve to (-vvv)
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: i32,
+    /// Only show the errors and a one line summary
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// When to use colors: auto (if the output is a terminal and NO_COLOR isn't set), always or never
+    #[clap(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+    color: ColorChoice,
+    /// Complete an existing target: create what's missing and keep what is already there
+    #[clap(long, conflicts_with = "verify")]
+    merge: bool,
+    /// With --merge, copy again the files of the source modified after this time (2021-03-01T12:00:00Z, or
+    /// @<seconds since the epoch>), to refresh a target after a game update. The links always lead to the current files
+    #[clap(long, requires = "merge")]
+    since: Option<String>,
+    /// Check an existing target against the source, without changing anything
+    #[clap(long)]
+    verify: bool,
+    /// With --verify, remove the links of the target to source files that don't exist anymore (after an update of the
+    /// game). Only those links are removed, never the copies or the local additions
+    #[clap(long, requires = "verify")]
+    prune: bool,
+    /// Remove the content of a non-empty target before building (only if it was built by iedup). With rm, remove the
+    /// files that aren't copies of the source too. Can also go after rm
+    #[clap(long, global = true, conflicts_with_all = &["merge", "verify"])]
+    force: bool,
+    /// Refuse a target with only the files the OS and the file managers leave around (.DS_Store, Thumbs.db,
+    /// .directory...), which are ignored otherwise
+    #[clap(long)]
+    strict_empty: bool,
+    /// Give this mode (octal, for ex. 644) to the copies, and to the created dirs with the x bits where it's readable, so
+    /// that another user can run the game
+    #[clap(long)]
+    chmod: Option<String>,
+    /// Give this owner (user:group, user or :group) to the copies and the created dirs. Needs to run as root, it's
+    /// ignored with a warning otherwise
+    #[clap(long)]
+    chown: Option<String>,
+    /// Go on when an entry can't be copied, linked or created, and list all the failures at the end (the checks of the
+    /// source and of the target still stop the run)
+    #[clap(long)]
+    continue_on_error: bool,
+    /// Show what the run will do (the counts and the size of the copies) and ask before doing it. When the input isn't
+    /// a terminal, the run is refused unless --yes is given
+    #[clap(long, conflicts_with_all = &["verify", "dry-run", "emit-script"])]
+    interactive: bool,
+    /// With --interactive, proceed without asking
+    #[clap(long, requires = "interactive")]
+    yes: bool,
+    /// Check at the end that the target has what the game needs to start (the launcher, chitin.key, engine.lua and a
+    /// dialog.tlk), and list what's missing or broken
+    #[clap(long, conflicts_with_all = &["dry-run", "emit-script"])]
+    sanity_check: bool,
+    /// Copy the files smaller than this many bytes instead of linking them. A --rule still wins over it
+    #[clap(long)]
+    copy_under: Option<u64>,
+    /// Link the files larger than this many bytes, even those copied by default (chitin.key, dialog.tlk...). A --rule
+    /// and --copy-all still win over it
+    #[clap(long)]
+    link_over: Option<u64>,
+    /// With --merge, keep the copies replaced by newer files of the source (--since) as <name>.iedup.bak, they may have
+    /// local changes
+    #[clap(long, requires = "merge")]
+    backup: bool,
+    /// Don't check before starting that the copies fit in the free space of the target
+    #[clap(long)]
+    ignore_space: bool,
+    /// Put the content of game/ at the root of the target, next to start.sh and support/, for the launchers that look
+    /// for chitin.key there. Not for the installs that already have it at their root (Windows, Steam, Beamdog)
+    #[clap(long)]
+    flatten: bool,
+    /// Show what a rebuild from the source would change in this existing target (entries added, removed, links
+    /// pointing elsewhere, copies with another content), without changing anything. Replaces the target argument
+    #[clap(long, value_name = "existing-target", conflicts_with_all = &["target", "verify", "merge", "resume", "dry-run", "emit-script", "interactive"])]
+    diff: Option<String>,
+    /// With --diff, exit with an error (6) if a rebuild would change the target
+    #[clap(long, requires = "diff")]
+    fail_on_diff: bool,
+    /// Leave these entries of the root of the install out of the target: start.sh, gameinfo, support or dlc (the dlc
+    /// archives), for ex. gameinfo,support for a headless server. Can be repeated
+    #[clap(long, use_delimiter = true)]
+    skip: Vec<RootItem>,
+    /// Build the target from the entries of a manifest.json written by --write-manifest (its dirs, copies and links,
+    /// with their sources moved to this source) instead of what is found in the source, for reproducible layouts
+    #[clap(
+        long,
+        value_name = "json",
+        conflicts_with_all = &["verify", "merge", "resume", "diff", "only", "exclude", "include-only", "skip", "rule"]
+    )]
+    from_manifest: Option<String>,
+    /// With --from-manifest, leave out the entries whose source is gone or changed size instead of stopping
+    #[clap(long, requires = "from-manifest")]
+    allow_drift: bool,
+    /// Try a copy or a link again up to this many times when it fails with a transient error of the filesystem
+    /// (EAGAIN, EBUSY, EINTR, ETIMEDOUT), for the games on a network filesystem (NFS, SMB)
+    #[clap(long, value_name = "N", default_value = "0")]
+    retries: u32,
+    /// The wait before the first retry of --retries in milliseconds, doubled at each retry
+    #[clap(long, value_name = "ms", default_value = "200")]
+    retry_delay: u64,
+    /// Write a summary of the run in JSON to this file (the counts and sizes by category, the time, the game, the
+    /// warnings and the errors), even when the run fails at the end
+    #[clap(long, value_name = "path")]
+    report_json: Option<String>,
+    /// Name the copies and links of the target in lowercase (the links still point to the source names), for the
+    /// modding tools that expect lowercase names. Two source entries that differ only by case are an error
+    #[clap(long)]
+    lowercase_names: bool,
+    /// The names of the dlc archives to link, at the root of the install and in its dlc/ dir (*-dlc.zip by default).
+    /// Can be repeated
+    #[clap(long, number_of_values = 1)]
+    dlc_pattern: Vec<String>,
+    /// Extract the dlc archives into the game dir of the target (their files replacing those of the game) instead of
+    /// linking them, after checking them whole. The rules and --exclude apply to their files as if in the game dir
+    #[clap(long, conflicts_with_all = &["verify", "merge", "resume", "from-manifest"])]
+    extract_dlc: bool,
+    /// Only duplicate this part of the game dir (game, scripts, data, lang, movies, music or override), in a target
+    /// where the rest may already be. Its dir must be missing or empty in the target (--force replaces it)
+    #[clap(
+        long,
+        conflicts_with = "write-manifest",
+        possible_values = &["game", "scripts", "data", "lang", "movies", "music", "override"]
+    )]
+    only: Option<Section>,
+    /// Skip the source files and dirs matching this glob, relative to the source (for ex. game/lang/de_DE or
+    /// game/movies/*.wbm). Can be repeated
+    #[clap(long, number_of_values = 1)]
+    exclude: Vec<String>,
+    /// Only process the source files and dirs matching this glob (and the dirs leading to them), relative to the
+    /// source like --exclude. Can be repeated. It is applied before --exclude, which can still skip included entries
+    #[clap(long, number_of_values = 1)]
+    include_only: Vec<String>,
+    /// Continue an interrupted run, using the state file it left in the target
+    #[clap(long, conflicts_with_all = &["merge", "verify", "force"])]
+    resume: bool,
+    /// What to keep from the source files when copying: all (permissions and timestamps), perms or none
+    #[clap(long, default_value = "all", possible_values = &["all", "perms", "none"])]
+    preserve: Preserve,
+    /// How the files are copied: plain, or reflink (copy-on-write copies sharing their blocks with the source until
+    /// modified, on Btrfs or XFS with the source on the same filesystem; plain copies when the target can't hold them)
+    #[clap(long, default_value = "plain", possible_values = &["plain", "reflink"])]
+    copy_mode: CopyMode,
+    /// Stop on an empty chitin.key or dialog.tlk instead of only warning (empty files are often broken downloads)
+    #[clap(long)]
+    fail_on_empty: bool,
+    /// Show each copy, link and dir that would be created (as with -v), without changing anything in the target. Can
+    /// also go after create
+    #[clap(long, global = true, conflicts_with = "verify")]
+    dry_run: bool,
+    /// How --dry-run shows its plan: text (the usual output) or json (an array of {action, source, target}, the
+    /// messages then go to stderr)
+    #[clap(long, global = true, requires = "dry-run", possible_values = &["text", "json"])]
+    plan_format: Option<PlanFormat>,
+    /// Write a bash or powershell script of what would be done instead of doing it (like --dry-run). The messages then
+    /// go to stderr
+    #[clap(long, conflicts_with_all = &["verify", "plan-format"], possible_values = &["bash", "powershell"])]
+    emit_script: Option<Shell>,
+    /// Write the --emit-script script in this file instead of stdout
+    #[clap(long, requires = "emit-script")]
+    script_out: Option<String>,
+    /// Ignore the case when deciding what to copy (CHITIN.KEY, Baldur.lua...). Always on for macOS and Windows, where the
+    /// filesystems are usually case-insensitive
+    #[clap(long)]
+    ignore_case: bool,
+    /// Build in a new subdir of the target (which doesn't have to be empty), to keep several copies side by side
+    #[clap(long)]
+    into: Option<String>,
+    /// Create the target (and its parents) when it doesn't exist, instead of stopping. With --into, the subdir is then
+    /// created in it
+    #[clap(long, conflicts_with = "verify")]
+    create_target: bool,
+    /// How to duplicate the override dirs (of the game and of the languages): copy (each file is copied), link (each
+    /// file is linked, new files can still be added) or link-dir (the whole dir is linked: nothing is copied, but mods
+    /// can't be installed in the target without writing in the source). copy by default
+    #[clap(long, possible_values = &["copy", "link", "link-dir"])]
+    override_mode: Option<OverrideMode>,
+    /// Stop if the source isn't this version of the game (as found in the GOG gameinfo file)
+    #[clap(long)]
+    expect_version: Option<String>,
+    /// Only compare the major.minor part of --expect-version (2.6 accepts 2.6.6.0)
+    #[clap(long, requires = "expect-version")]
+    loose_version: bool,
+    /// Read the rules for the entries iedup doesn't know (mod frameworks, tools...), support/ and gameinfo from this TOML
+    /// file
+    #[clap(long)]
+    config: Option<String>,
+    /// How to duplicate the files and dirs matching a pattern (by name, or by path relative to the source when it has a
+    /// /), as PATTERN=ACTION with copy, link (or symlink), hardlink or skip (for ex. '*.bif=hardlink' or
+    /// 'game/music/*.mus=copy'). Can be repeated, the first matching rule wins and they come before those of --config
+    #[clap(long, number_of_values = 1)]
+    rule: Vec<String>,
+    /// Stop when the game dir has entries iedup doesn't know and no rule classifies, or when a link created leads out
+    /// of the source (or of --link-base) through a link in it, instead of only reporting it
+    #[clap(long)]
+    strict: bool,
+    /// With --merge or --resume, replace the links of the target that point somewhere else (they are reported as
+    /// problems otherwise)
+    #[clap(long)]
+    follow_existing: bool,
+    /// Check that all the bif files listed in chitin.key are in the source before building
+    #[clap(long)]
+    check_bifs: bool,
+    /// Make the links point into this dir instead of the source, which should have the same content (for ex. the
+    /// canonical location of a source mounted elsewhere for now)
+    #[clap(long)]
+    link_base: Option<String>,
+    /// Put the copies (override, dialog.tlk, scripts...) under this dir, at their path in the target, and link the
+    /// target to them: the target holds only dirs and links, the files to modify are apart (on another disk...)
+    #[clap(long, conflicts_with = "from-manifest")]
+    writable_root: Option<String>,
+    /// Copy everything instead of linking, for sources on removable media (CD images...) where the links would break
+    /// once it's unmounted
+    #[clap(long)]
+    copy_all: bool,
+    /// Copy everything (like --copy-all) when the target can't hold links (FAT, exFAT...) instead of stopping
+    #[clap(long)]
+    auto_fallback: bool,
+    /// Write a manifest.json at the root of the target, listing every entry (dir, copy or link) with its source, to
+    /// compare builds later
+    #[clap(long, conflicts_with_all = &["merge", "verify", "resume", "dry-run", "emit-script"])]
+    write_manifest: bool,
+    /// Replace the copies with the same content by hard links to one of them, to save space. Modifying one of them in
+    /// place then modifies all (the links are left alone)
+    #[clap(long, conflicts_with_all = &["verify", "dry-run", "emit-script"])]
+    dedup_copies: bool,
+    /// Stop before changing anything if the run would create more links than this (some filesystems and backup tools
+    /// have trouble with many links)
+    #[clap(long, conflicts_with = "verify")]
+    max_links: Option<u64>,
+    /// Only duplicate these languages of lang/ (for ex. en_US,fr_FR), all of them by default
+    #[clap(long, use_delimiter = true)]
+    languages: Vec<String>,
+    /// Leave out the movies/ of the languages (the localized cutscenes), their dialog.tlk, data/ and override/ are kept
+    #[clap(long)]
+    skip_lang_movies: bool,
+    /// Leave out the sounds/ of the languages (the localized voices), their dialog.tlk, data/ and override/ are kept
+    #[clap(long)]
+    skip_lang_sounds: bool,
+    /// Only print how big the source is and what a run would copy and link (sizes and file counts by category and by
+    /// extension), quickly and without changing anything: the files are summed as they are walked, not planned one by
+    /// one
+    #[clap(long, conflicts_with_all = &["verify", "merge", "resume", "diff", "dry-run", "emit-script", "interactive", "from-manifest"])]
+    stats_only: bool,
+    /// Fail when some of the links created don't lead to an existing file (they are always reported)
+    #[clap(long)]
+    fail_on_dangling: bool,
+    /// Stop when a dir to copy is more than this many levels below the source (a malformed source tree)
+    #[clap(long, default_value = "32")]
+    max_depth: usize,
+    /// Copy support/ instead of linking it, for the launchers that write in it (the same as a "support" = "copy" rule)
+    #[clap(long)]
+    copy_support: bool,
+    /// Use the options of this profile (see --list-profiles): its excludes and includes are added to the others, its
+    /// rules come after those of --config and its override mode is used unless --override-mode is given
+    #[clap(long)]
+    profile: Option<String>,
+    /// List the built-in profiles and those of profiles.toml in the config dir (~/.config/iedup on Linux)
+    #[clap(long)]
+    list_profiles: bool,
+    /// Check every copy against its source by reading both again (chitin.key, the dialog.tlk files and engine.lua are
+    /// always checked)
+    #[clap(long)]
+    verify_copies: bool,
+    /// Print the time spent in each phase (scripts, data, lang, movies, music, override) and in the whole run at the end
+    #[clap(long)]
+    timings: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-static MUS_EXT: Lazy<&OsStr> = Lazy::new(|| &OsStr::new("mus"));
-static WAV_EXT: Lazy<&OsStr> = Lazy::new(|| &OsStr::new("wav"));
-static NO_EXT: Lazy<&OsStr> = Lazy::new(|| &OsStr::new(""));
-
-fn main() -> Result<()> {
-    let opts: Opts = Opts::parse();
+#[derive(Clap)]
+enum Command {
+    /// Print the completion script of a shell (bash, zsh, fish or powershell)
+    #[clap(setting = AppSettings::Hidden)]
+    Completions {
+        #[clap(possible_values = &["bash", "zsh", "fish", "powershell"])]
+        shell: CompletionShell,
+    },
+    /// Merge the source into the target (which may be empty), then again each time the source changes, until
+    /// interrupted. Implies --merge, only the copies whose source changed are made again. The other options go before
+    /// watch
+    Watch {
+        source: String,
+        target: String,
+        /// How long the source must stay unchanged before a sync, in milliseconds
+        #[clap(long, value_name = "ms", default_value = "500")]
+        debounce: u64,
+    },
+    /// Duplicate the source into the target, like `iedup <source> <target>`. The other options go before create
+    Create { source: String, target: String },
+    /// Check an existing target against the source (the one recorded in it by default) without changing anything,
+    /// like --verify. The other options go before verify
+    Verify { target: String, source: Option<String> },
+    /// Show the source recorded in a target and what it holds (copies, links and dirs by category)
+    List { target: String },
+    /// Remove a target built by iedup (its links, never what they lead to). It's refused when the target has files that
+    /// aren't copies of its source (mods, saves...) unless --force is given. With --dry-run, only list the files that
+    /// would be removed
+    Rm { target: String },
+    /// Replace the copies of an existing target (built by copying everything) that have the content of their source
+    /// file by links to it, where a run would link them, to reclaim their space. The modified copies and those a run
+    /// would copy too are left alone. The other options go before dedup (--dry-run only shows what would be linked)
+    Dedup { installed: String, source: String },
+}
 
-    let source = Path::new(&opts.source);
-    let target = Path::new(&opts.target);
+#[derive(Clone, Copy)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
 
-    // ensure source and target are directories
-    if !source.is_dir() {
-        return Err(anyhow!("source {} is not a directory", opts.source));
-    }
-    if !target.is_dir() {
-        return Err(anyhow!("target {} is not a directory", opts.target));
-    }
+impl FromStr for CompletionShell {
+    type Err = AnyError;
 
-    // ensure target is empty
-    let mut target_files = target.read_dir()?;
-    if target_files.next().is_some() {
-        return Err(anyhow!("target dir {} is not empty", opts.target));
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "bash" => Ok(CompletionShell::Bash),
+            "zsh" => Ok(CompletionShell::Zsh),
+            "fish" => Ok(CompletionShell::Fish),
+            "powershell" => Ok(CompletionShell::PowerShell),
+            _ => Err(anyhow!("unknown shell {} (expected bash, zsh, fish or powershell)", value)),
+        }
     }
-
-    // ensure source dir looks like a gog EE infinity engine directory
-    let hint = HintStructure {
-        os: Os::Linux,
-        vendor: Vendor::Gog,
-        variant: Variant::Ee,
-    };
-    check_source(&source, &hint)?;
-
-    // root dir : copy start.sh (allows user modification), link support/ (no changes expected), create game/
-
-    copy_item(source, target, "start.sh")?;
-    link_item(source, target, "gameinfo")?;
-    link_item(source, target, "support")?;
-    process_dlc_zips(source, target)?;
-    create_dir_str(target, "game")?;
-
-    process_game_dir(&source.join("game"), &target.join("game"))?;
-
-    Ok(())
 }
 
-fn copy_item(source: &Path, target: &Path, item: &str) -> Result<()> {
-    copy_item_os(source, target, OsStr::new(item))
+/// Prints the messages of the library as they come (colored by the library), on stdout, or on stderr when stdout has
+/// the json plan or the script
+struct ConsoleLogger {
+    to_stderr: bool,
 }
 
-fn copy_item_os(source: &Path, target: &Path, item: &OsStr) -> Result<()> {
-    let source_item = source.join(item);
-    let target_item = target.join(item);
-    println!(
-        "copy {} to {}",
-        Blue.bold().paint(source_item.to_string_lossy()),
-        Green.paint(target_item.to_string_lossy())
-    );
-    std::fs::copy(source_item, target_item)?;
-    Ok(())
-}
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with("iedup")
+    }
 
-fn link_item(source: &Path, target: &Path, item: &str) -> Result<()> {
-    link_item_os(source, target, OsStr::new(item))
-}
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if self.to_stderr {
+            eprintln!("{}", record.args());
+        } else {
+            println!("{}", record.args());
+        }
+    }
 
-fn link_item_os(source: &Path, target: &Path, item: &OsStr) -> Result<()> {
-    let source_item = source.join(item);
-    let target_item = target.join(item);
-    println!(
-        "link {} to {}",
-        Blue.bold().paint(source_item.to_string_lossy()),
-        Green.paint(target_item.to_string_lossy())
-    );
-    Ok(std::os::unix::fs::symlink(source.join(item), target.join(item))?)
+    fn flush(&self) {}
 }
 
-fn create_dir_str(target: &Path, item: &str) -> Result<()> {
-    std::fs::create_dir(target.join(item))?;
-    Ok(())
+/// The exit codes of iedup, for the scripts (the invalid arguments found by clap exit with 2 too)
+#[derive(Clone, Copy)]
+enum Exit {
+    Success = 0,
+    /// any other error
+    Error = 1,
+    Usage = 2,
+    InvalidSource = 3,
+    TargetNotEmpty = 4,
+    /// some items failed with --continue-on-error
+    Partial = 5,
+    /// --verify or a check of the copies found differences
+    Mismatch = 6,
+    /// the source can't be read or the target written
+    Permission = 7,
 }
 
-fn create_dir_os(target: &Path, item: &OsStr) -> Result<()> {
-    std::fs::create_dir(target.join(item))?;
-    Ok(())
+impl Exit {
+    fn of(error: &AnyError) -> Exit {
+        match error.downcast_ref::<IedupError>().and_then(IedupError::kind) {
+            Some(FailureKind::Usage) => Exit::Usage,
+            Some(FailureKind::InvalidSource) => Exit::InvalidSource,
+            Some(FailureKind::TargetNotEmpty) => Exit::TargetNotEmpty,
+            Some(FailureKind::Partial) => Exit::Partial,
+            Some(FailureKind::Mismatch) => Exit::Mismatch,
+            Some(FailureKind::Permission) => Exit::Permission,
+            None => Exit::Error,
+        }
+    }
 }
 
-fn process_dlc_zips(source: &Path, target: &Path) -> Result<()> {
-    link_pattern_files(source, target, "*-dlc.zip")
+fn main() {
+    let exit = match try_main() {
+        Ok(()) => Exit::Success,
+        Err(error) => {
+            // like the Result returned by main
+            eprintln!("Error: {:?}", error);
+            Exit::of(&error)
+        }
+    };
+    std::process::exit(exit as i32);
 }
 
-fn link_pattern_files(source: &Path, target: &Path, pattern: &str) -> Result<()> {
-    for entry in glob(source.join(pattern).to_str().unwrap())? {
-        match entry {
-            Ok(path) => {
-                if !path.is_dir() {
-                    if let Some(name) = path.file_name() {
-                        link_item_os(source, target, name)?;
-                    }
-                }
-            }
-            Err(err) => {
-                println!("{}", Red.bold().paint(format!("{}", err)));
-                return Err(err)?;
-            }
+fn try_main() -> Result<()> {
+    let matches = Opts::into_app().get_matches();
+    let opts = Opts::from_arg_matches(&matches);
+    if let Some(Command::Completions { shell }) = opts.command {
+        print_completions(shell);
+        return Ok(());
+    }
+    if opts.list_profiles {
+        return list_profiles();
+    }
+    match &opts.command {
+        Some(Command::List { target }) => return list(target),
+        Some(Command::Rm { target }) => return remove(target, opts.force, opts.dry_run),
+        Some(Command::Create { .. }) if opts.merge || opts.verify || opts.resume => {
+            return Err(AnyError::new(IedupError::Usage(
+                "create builds a new target, it can't be used with --merge, --verify or --resume".to_string(),
+            )))
+        }
+        Some(Command::Verify { .. }) if opts.merge || opts.resume => {
+            return Err(AnyError::new(IedupError::Usage(
+                "verify can't be used with --merge or --resume".to_string(),
+            )))
         }
+        _ => {}
     }
-    Ok(())
-}
-
-fn process_game_dir(source: &Path, target: &Path) -> Result<()> {
-    println!(
-        "{} to {}",
-        Blue.bold().paint(source.to_string_lossy()),
-        Green.paint(target.to_string_lossy())
-    );
-    // copy chitin.key and engine.lua which can be modded
-    copy_item(source, target, "chitin.key")?;
-    copy_item(source, target, "engine.lua")?;
-    // the other non-dirs are supposed to be game exe's and will be linked
-    let source_files = source.read_dir()?;
-    for file in source_files {
-        let file = file?;
-        if !file.file_type()?.is_dir() && file.file_name() != "chitin.key" && file.file_name() != "engine.lua" {
-            link_item_os(source, target, &file.file_name())?;
+    let watch = match &opts.command {
+        Some(Command::Watch { source, target, debounce }) => Some((PathBuf::from(source), PathBuf::from(target), Duration::from_millis(*debounce))),
+        _ => None,
+    };
+    let (source, target) = match (&watch, &opts.command) {
+        (Some((source, target, _)), _) => (source.clone(), target.clone()),
+        (None, Some(Command::Dedup { installed, source })) => (PathBuf::from(source), PathBuf::from(installed)),
+        (None, Some(Command::Create { source, target })) => (PathBuf::from(source), PathBuf::from(target)),
+        (
+            None,
+            Some(Command::Verify {
+                target,
+                source: Some(source),
+            }),
+        ) => (PathBuf::from(source), PathBuf::from(target)),
+        (None, Some(Command::Verify { target, source: None })) => (recorded_source(&opts, target)?, PathBuf::from(target)),
+        (None, _) => source_and_target(&opts)?,
+    };
+    // the values clap can't check are usage errors too
+    let mut config = to_config(opts, source, target).map_err(|error| error.context(IedupError::Usage("invalid arguments".to_string())))?;
+    if let Some(path) = defaults::file().filter(|path| path.exists()) {
+        let applied = defaults::read(&path, &mut config, |name| matches.occurrences_of(name) > 0)?;
+        // on stderr, stdout may be a json plan or a script
+        if !applied.is_empty() && !config.quiet {
+            eprintln!("defaults from {}: {}", path.to_string_lossy(), applied.join(", "));
         }
     }
-    // link the dir: Manual
-    println!("{}", Blue.bold().paint(" => Manuals/"));
-    link_item(source, target, "Manuals")?;
-    // create the dir, copy the content: scripts (scripts can be customized, added)
-    println!("{}", Blue.bold().paint(" => scripts/"));
-    create_dir_str(target, "scripts")?;
-    process_scripts_dir(&source.join("scripts"), &target.join("scripts"))?;
-
-    // continue with the other dirs
-    // create the dirs: data, lang, movies, music
-    println!("{}", Blue.bold().paint(" => data/"));
-    create_dir_str(target, "data")?;
-    process_data_dir(&source.join("data"), &target.join("data"))?;
-    println!("{}", Blue.bold().paint(" => lang/"));
-    create_dir_str(target, "lang")?;
-    process_lang_dir(&source.join("lang"), &target.join("lang"))?;
-    println!("{}", Blue.bold().paint(" => movies/"));
-    create_dir_str(target, "movies")?;
-    process_movies_dir(&source.join("movies"), &target.join("movies"))?;
-    println!("{}", Blue.bold().paint(" => music/"));
-    create_dir_str(target, "music")?;
-    process_music_dir(&source.join("music"), &target.join("music"))?;
-    println!("{}", Blue.bold().paint(" <= done"));
-
-    //create override/ dir anyway
-    create_dir_str(target, "override")?;
-    //copy content if exists
-    let root_override_dir = source.join("override");
-    if root_override_dir.exists() {
-        println!("{}", Blue.bold().paint(" => override"));
-        process_override_dir(&root_override_dir, &target.join("override"))?;
-    } else {
-        println!("{}", Yellow.paint(format!("no {}", root_override_dir.to_string_lossy())));
+    // the library filters the messages by verbosity itself
+    log::set_boxed_logger(Box::new(ConsoleLogger {
+        to_stderr: config.plan_to_stdout(),
+    }))?;
+    log::set_max_level(LevelFilter::Trace);
+    match watch {
+        Some((_, _, debounce)) => iedup::watch::run(config, debounce)?,
+        None => {
+            iedup::run(config)?;
+        }
     }
-
-    // done
     Ok(())
 }
 
-fn process_override_dir(source: &Path, target: &Path) -> Result<()> {
-    //copy content
-    copy_content(source, target)
-}
-
-fn copy_content(source: &Path, target: &Path) -> Result<()> {
-    let scripts = source.read_dir()?;
-    for file in scripts {
-        let file = file?;
-        if let Err(error) = copy_item_os(source, target, &file.file_name()) {
-            return Err(anyhow!("Error copying file {:?} from {:?} to {:?}\n  ->{:?}", file.file_name(), source, target, error));
-        }
+/// The source and the target (the existing one of --diff), or with a single path the target and the source recorded
+/// in it
+fn source_and_target(opts: &Opts) -> Result<(PathBuf, PathBuf)> {
+    match (&opts.source, &opts.target, &opts.diff) {
+        (Some(source), Some(target), _) | (Some(source), None, Some(target)) => Ok((PathBuf::from(source), PathBuf::from(target))),
+        (Some(target), None, None) if opts.verify || opts.merge || opts.resume => Ok((recorded_source(opts, target)?, PathBuf::from(target))),
+        _ => Err(AnyError::new(IedupError::Usage("the source and the target are required".to_string()))),
     }
-    Ok(())
 }
 
-fn process_scripts_dir(source: &Path, target: &Path) -> Result<()> {
-    //copy content
-    copy_content(source, target)
+/// The source recorded in `target` (in its --into subdir) by the run that built it
+fn recorded_source(opts: &Opts, target: &str) -> Result<PathBuf> {
+    let target = PathBuf::from(target);
+    let built = match &opts.into {
+        Some(subdir) => target.join(subdir),
+        None => target,
+    };
+    Ok(iedup::recorded_source(&built)?)
 }
 
-fn process_data_dir(source: &Path, target: &Path) -> Result<()> {
-    // link all files inside(should all be .bif)
-    link_all_inside(source, target)?;
-    Ok(())
+/// The options of the library from the command line ones
+fn to_config(opts: Opts, source: PathBuf, target: PathBuf) -> Result<Config> {
+    Ok(Config {
+        source,
+        target,
+        no_progress: opts.no_progress,
+        verbose: opts.verbose,
+        quiet: opts.quiet,
+        color: opts.color,
+        merge: opts.merge,
+        verify: opts.verify || matches!(opts.command, Some(Command::Verify { .. })),
+        force: opts.force,
+        exclude: opts.exclude,
+        include_only: opts.include_only,
+        resume: opts.resume,
+        preserve: opts.preserve,
+        fail_on_empty: opts.fail_on_empty,
+        dry_run: opts.dry_run,
+        plan_format: opts.plan_format,
+        emit_script: opts.emit_script,
+        script_out: opts.script_out.map(PathBuf::from),
+        ignore_case: opts.ignore_case,
+        into: opts.into,
+        override_mode: opts.override_mode,
+        expect_version: opts.expect_version,
+        loose_version: opts.loose_version,
+        config_file: opts.config.map(PathBuf::from),
+        rules: opts.rule,
+        strict: opts.strict,
+        follow_existing: opts.follow_existing,
+        check_bifs: opts.check_bifs,
+        link_base: opts.link_base.map(PathBuf::from),
+        copy_all: opts.copy_all,
+        auto_fallback: opts.auto_fallback,
+        write_manifest: opts.write_manifest,
+        dedup_copies: opts.dedup_copies,
+        max_links: opts.max_links,
+        languages: opts.languages,
+        fail_on_dangling: opts.fail_on_dangling,
+        max_depth: opts.max_depth,
+        copy_support: opts.copy_support,
+        profile: opts.profile,
+        verify_copies: opts.verify_copies,
+        prune: opts.prune,
+        timings: opts.timings,
+        strict_empty: opts.strict_empty,
+        only: opts.only,
+        chmod: opts.chmod.as_deref().map(permissions::parse_mode).transpose()?,
+        chown: opts.chown,
+        continue_on_error: opts.continue_on_error,
+        interactive: opts.interactive,
+        yes: opts.yes,
+        dlc_patterns: opts.dlc_pattern,
+        sanity_check: opts.sanity_check,
+        copy_under: opts.copy_under,
+        link_over: opts.link_over,
+        backup: opts.backup,
+        ignore_space: opts.ignore_space,
+        flatten: opts.flatten,
+        lowercase_names: opts.lowercase_names,
+        diff: opts.diff.is_some(),
+        fail_on_diff: opts.fail_on_diff,
+        report_json: opts.report_json.map(PathBuf::from),
+        skip: opts.skip,
+        retries: opts.retries,
+        retry_delay: Duration::from_millis(opts.retry_delay),
+        from_manifest: opts.from_manifest.map(PathBuf::from),
+        allow_drift: opts.allow_drift,
+        copy_mode: opts.copy_mode,
+        create_target: opts.create_target,
+        skip_lang_movies: opts.skip_lang_movies,
+        skip_lang_sounds: opts.skip_lang_sounds,
+        stats_only: opts.stats_only,
+        relink: matches!(opts.command, Some(Command::Dedup { .. })),
+        writable_root: opts.writable_root.map(PathBuf::from),
+        extract_dlc: opts.extract_dlc,
+        plan_output: Some(Arc::new(|plan: &str| print!("{}", plan))),
+        confirm: if std::io::stdin().is_terminal() { Some(Arc::new(ask)) } else { None },
+        since: opts.since.as_deref().map(timestamp::parse).transpose()?,
+    })
 }
 
-fn process_lang_dir(source: &Path, target: &Path) -> Result<()> {
-    // each language in a subdir (for ex. en_US)
-    let languages = source.read_dir()?;
-    for language in languages {
-        let language = language?.file_name();
-        create_dir_os(target, &language)?;
-        process_language(&source.join(&language), &target.join(&language), &language.to_string_lossy())?;
-    }
-
-    Ok(())
+/// Asks `question` on the terminal, true when the answer is yes
+fn ask(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdout().flush().is_ok() && std::io::stdin().read_line(&mut answer).is_ok() && is_yes(&answer)
 }
 
-fn process_language(source: &Path, target: &Path, language_mark: &str) -> Result<()> {
-    // in each language subdir,
-    // - one dialog.tlk OR dialog.tlk+dialogF.tlk -> copy because those are modifiable
-    // - [maybe]one movies subdir with root wbm and lo/ and 480/ -> like movies at root
-    // - [maybe]one sounds/ subdir
-    // - [maybe]one data/ subdir (ex: de_DE)
-    // - [maybe]one override/ subdir (ex: de_DE)
-
-    println!(
-        "{} to {}",
-        Blue.bold().paint(source.to_string_lossy()),
-        Green.paint(target.to_string_lossy())
-    );
-
-    copy_non_dirs(source, target)?; // tlk
-    let source_movies_dir = source.join("movies");
-    if source_movies_dir.exists() {
-        let target_movies_dir = target.join("movies");
-        println!(
-            "{} to {}",
-            Blue.bold().paint(source_movies_dir.to_string_lossy()),
-            Green.paint(target_movies_dir.to_string_lossy())
-        );
-        create_dir_str(target, "movies")?;
-        process_movies_dir(&source_movies_dir, &target_movies_dir)?;
-    } else {
-        println!("{}", Yellow.paint(format!("no movies/ for {}", language_mark)));
-    }
-    let source_sounds_dir = source.join("sounds");
-    if source_sounds_dir.exists() {
-        let target_sounds_dir = target.join("sounds");
-        println!(
-            "{} to {}",
-            Blue.bold().paint(source_sounds_dir.to_string_lossy()),
-            Green.paint(target_sounds_dir.to_string_lossy())
-        );
-        create_dir_str(target, "sounds")?;
-        process_sound_dir(&source_sounds_dir, &target_sounds_dir)?;
-    } else {
-        println!("{}", Yellow.paint(format!("no sounds/ for {}", language_mark)));
-    }
-    let source_override_dir = source.join("override");
-    if source_override_dir.exists() {
-        let target_override_dir = target.join("override");
-        println!(
-            "{} to {}",
-            Blue.bold().paint(source_override_dir.to_string_lossy()),
-            Green.paint(target_override_dir.to_string_lossy())
-        );
-        create_dir_str(target, "override")?;
-        process_override_dir(&source_override_dir, &target_override_dir)?;
-    } else {
-        println!("{}", Yellow.paint(format!("no override/ for {}", language_mark)));
-    }
-    let source_data_dir = source.join("data");
-    if source_data_dir.exists() {
-        let target_data_dir = target.join("data");
-        println!(
-            "{} to {}",
-            Blue.bold().paint(source_data_dir.to_string_lossy()),
-            Green.paint(target_data_dir.to_string_lossy())
-        );
-        create_dir_str(target, "data")?;
-        process_data_dir(&source_data_dir, &target_data_dir)?;
-    } else {
-        println!("{}", Yellow.paint(format!("no data/ for {}", language_mark)));
-    }
-    Ok(())
+fn is_yes(answer: &str) -> bool {
+    let answer = answer.trim();
+    answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
 }
 
-fn process_sound_dir(source: &Path, target: &Path) -> Result<()> {
-    // *.wav files and one sndlist.txt -> create dir, link *.wav, copy sndlist.txt
-    let files = source.read_dir()?;
-    for file in files {
-        let file = file?;
-        let file_path = file.path();
-        let ext = file_path.extension().unwrap_or(&*NO_EXT);
-        if ext == *WAV_EXT {
-            link_item_os(source, target, &file.file_name())?;
-        } else {
-            copy_item_os(source, target, &file.file_name())?;
-        }
+fn print_completions(shell: CompletionShell) {
+    let mut app = Opts::into_app();
+    let stdout = &mut std::io::stdout();
+    match shell {
+        CompletionShell::Bash => clap_generate::generate::<Bash, _>(&mut app, "iedup", stdout),
+        CompletionShell::Zsh => clap_generate::generate::<Zsh, _>(&mut app, "iedup", stdout),
+        CompletionShell::Fish => clap_generate::generate::<Fish, _>(&mut app, "iedup", stdout),
+        CompletionShell::PowerShell => clap_generate::generate::<PowerShell, _>(&mut app, "iedup", stdout),
     }
-
-    Ok(())
-}
-fn link_non_dirs(source: &Path, target: &Path) -> Result<()> {
-    let files = source.read_dir()?;
-    for file in files {
-        let file = file?;
-        if !file.file_type()?.is_dir() {
-            link_item_os(source, target, &file.file_name())?;
-        }
-    }
-    Ok(())
-}
-fn copy_non_dirs(source: &Path, target: &Path) -> Result<()> {
-    let files = source.read_dir()?;
-    for file in files {
-        let file = file?;
-        if !file.file_type()?.is_dir() {
-            copy_item_os(source, target, &file.file_name())?;
-        }
-    }
-    Ok(())
 }
 
-fn process_movies_dir(source: &Path, target: &Path) -> Result<()> {
-    // on set of movies at the root, one in 480, one in lo
-    // link all root movies (non-dir files)
-    link_non_dirs(source, target)?;
-    let source_480 = source.join("480");
-    if source_480.exists() {
-        create_dir_str(target, "480")?;
-        let target_480 = target.join("480");
-        link_all_inside(&source_480, &target_480)?;
-    } else {
-        println!("{}", Yellow.bold().paint(format!("no {}", source_480.to_string_lossy())));
+fn list(target: &str) -> Result<()> {
+    let listing = iedup::list(&PathBuf::from(target))?;
+    match &listing.source {
+        Some((source, game)) => println!("{}: {} built from {}", target, game, source.to_string_lossy()),
+        None => println!("{}: no source recorded", target),
     }
-    let source_lo = source.join("lo");
-    if source_lo.exists() {
-        let target_lo = target.join("lo");
-        create_dir_str(target, "lo")?;
-        link_all_inside(&source_lo, &target_lo)?;
-    } else {
-        println!("{}", Yellow.bold().paint(format!("no {}", source_lo.to_string_lossy())));
+    for line in stats::table(&listing.categories) {
+        println!("{}", line);
     }
     Ok(())
 }
 
-fn process_music_dir(source: &Path, target: &Path) -> Result<()> {
-    // some .mus file at the root (couple dozen bytes each, 40 files or so)
-    // one lone .acm file
-    // around 40 directories with  some .acm inside
-    // create the directories, link the .acm inside
-    // copy all the .mus files and link the single .acm in the root
-    let music_files = source.read_dir()?;
-    for file in music_files {
-        let file = file?;
-        if file.file_type()?.is_dir() {
-            create_dir_os(target, &file.file_name())?;
-            link_all_inside(&source.join(&file.file_name()), &target.join(&file.file_name()))?;
-        } else {
-            let file_path = file.path();
-            let extension = file_path.extension().unwrap_or(&*NO_EXT);
-            if extension == *MUS_EXT {
-                // copy *.mus
-                copy_item_os(source, target, &file.file_name())?;
-            } else {
-                // link the non-dir, non-mus file(s)
-                link_item_os(source, target, &file.file_name())?;
-            }
+fn remove(target: &str, force: bool, dry_run: bool) -> Result<()> {
+    let files = iedup::remove(&PathBuf::from(target), force, dry_run)?;
+    if dry_run {
+        println!("would remove {} with its links and {} file(s):", target, files.len());
+        for file in files {
+            println!("  {}", file.to_string_lossy());
         }
+    } else {
+        println!("removed {} with its links and {} file(s)", target, files.len());
     }
-
     Ok(())
 }
 
-fn link_all_inside(source: &Path, target: &Path) -> Result<()> {
-    let files = source.read_dir()?;
-    for file in files {
-        let file = file?;
-        link_item_os(source, target, &file.file_name())?;
+fn list_profiles() -> Result<()> {
+    for profile in profiles::all()? {
+        println!("{:<16} {}", profile.name, profile.description);
+    }
+    if let Some(path) = profiles::user_file() {
+        println!("(more profiles can be added in {})", path.to_string_lossy());
     }
     Ok(())
 }
 
-enum Os {
-    Linux,
-    Win,
-    Mac,
-}
-enum Vendor {
-    Gog,
-    Steam,
-    Beamdog,
-}
-enum Variant {
-    Classic,
-    Ee,
-}
-struct HintStructure {
-    os: Os,
-    vendor: Vendor,
-    variant: Variant,
-}
-
-struct GameDescription {
-    os: Os,
-    vendor: Vendor,
-    variant: Variant,
-    name: Option<String>,
-    version: Option<String>,
-    build: Option<String>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn check_source(dir: &Path, hint: &HintStructure) -> Result<GameDescription, AnyError> {
-    // should have a start.sh script, a game and support
-    match hint {
-        HintStructure {
-            os: Os::Linux,
-            vendor: Vendor::Gog,
-            variant: Variant::Ee,
-        } => check_source_linux_gog_ee(dir, hint),
-        _ => Err(anyhow!("don't know yet how to process this variant")),
+    #[test]
+    fn takes_the_dry_run_options_after_create() {
+        let opts = Opts::try_parse_from(["iedup", "create", "--dry-run", "--plan-format", "json", "/gog/bg2ee", "/games/bg2ee"]).unwrap();
+        assert!(opts.dry_run);
+        assert!(matches!(opts.plan_format, Some(PlanFormat::Json)));
+        assert!(matches!(opts.command, Some(Command::Create { .. })));
     }
-}
 
-fn check_source_linux_gog_ee(dir: &Path, hint: &HintStructure) -> Result<GameDescription> {
-    let start_sh = dir.join("start.sh");
-    let game_dir = dir.join("game");
-    let support_dir = dir.join("support");
-    if !(start_sh.exists() && game_dir.is_dir() && support_dir.is_dir()) {
-        return Err(anyhow!("Nope, not a game dir"));
+    #[test]
+    fn takes_force_after_rm() {
+        let opts = Opts::try_parse_from(["iedup", "rm", "--force", "/games/bg2ee"]).unwrap();
+        assert!(opts.force);
+        assert!(matches!(opts.command, Some(Command::Rm { .. })));
     }
 
-    return Ok(GameDescription {
-        os: Os::Linux,
-        vendor: Vendor::Gog,
-        variant: Variant::Ee,
-        name: None,
-        version: None,
-        build: None,
-    });
+    #[test]
+    fn reads_yes() {
+        assert!(is_yes("y\n") && is_yes(" YES\n"));
+        assert!(!is_yes("\n") && !is_yes("no\n"));
+    }
 }
@@ -0,0 +1,153 @@
+// Description of a built target (--write-manifest), written as manifest.json at its root so that two builds (of two
+// versions of the game for example) can be diffed:
+//
+// {
+//   "game": {"id": "BG2EE", "name": "Baldur's Gate II: Enhanced Edition", "version": "2.6.6.0", "build": "27023"},
+//   "source": "/gog/bg2ee",
+//   "entries": [
+//     {"path": "game", "type": "dir", "source": "/gog/bg2ee/game", "size": null},
+//     {"path": "game/chitin.key", "type": "copy", "source": "/gog/bg2ee/game/chitin.key", "size": 12345},
+//     {"path": "support", "type": "link", "source": "/gog/bg2ee/support", "size": null},
+//     ...
+//   ]
+// }
+//
+// The paths are relative to the target and sorted, the size is the size of the copied files, the files of the hardlink
+// rules are of type "hardlink", the files extracted from the dlc archives (--extract-dlc) of type "extract", their source
+// being <archive>/<path in the archive>.
+// --from-manifest builds a target from those entries, with their sources moved from "source" to the source of the run.
+
+use crate::json;
+use crate::plan::{json_string, Action, ActionKind};
+use anyhow::{anyhow, Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// What the manifest says about the game, from its gameinfo file
+pub struct GameInfo<'a> {
+    pub id: String,
+    pub name: Option<&'a str>,
+    pub version: Option<&'a str>,
+    pub build: Option<&'a str>,
+}
+
+/// An entry of a manifest read back
+pub struct Entry {
+    /// relative to the target
+    pub path: PathBuf,
+    /// CreateDir, Copy, Link, Hardlink or Extract
+    pub kind: ActionKind,
+    pub source: Option<PathBuf>,
+    /// the size of a copy or of an extracted file
+    pub size: Option<u64>,
+}
+
+pub struct Manifest {
+    /// the source of the build, the manifests written before it was recorded don't have it
+    pub source: Option<PathBuf>,
+    /// in the order of the manifest, the dirs before their content
+    pub entries: Vec<Entry>,
+}
+
+/// The manifest of the target built from `source_root` by `actions`
+pub fn to_json(game: &GameInfo, source_root: &Path, actions: &[Action], target_root: &Path) -> String {
+    let mut entries: Vec<(String, &Action)> = actions
+        .iter()
+        .filter(|action| !matches!(action.kind, ActionKind::Remove | ActionKind::Backup))
+        .filter_map(|action| {
+            let relative = action.target.strip_prefix(target_root).ok()?;
+            // the target itself (created by --into) isn't an entry
+            if relative.as_os_str().is_empty() {
+                None
+            } else {
+                Some((relative.to_string_lossy().into_owned(), action))
+            }
+        })
+        .collect();
+    entries.sort_by(|(first, _), (second, _)| first.cmp(second));
+
+    let mut json = format!(
+        "{{\n  \"game\": {{\"id\": {}, \"name\": {}, \"version\": {}, \"build\": {}}},\n  \"source\": {},\n  \"entries\": [",
+        json_string(&game.id),
+        optional_string(game.name),
+        optional_string(game.version),
+        optional_string(game.build),
+        json_string(&source_root.to_string_lossy())
+    );
+    for (index, (path, action)) in entries.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let entry_type = match action.kind {
+            ActionKind::CreateDir => "dir",
+            ActionKind::Copy => "copy",
+            ActionKind::Hardlink => "hardlink",
+            ActionKind::Extract => "extract",
+            _ => "link",
+        };
+        let source = action.source.as_ref().map(|source| source.to_string_lossy());
+        let size = match action.kind {
+            ActionKind::Copy | ActionKind::Extract => action.target.metadata().ok().map(|metadata| metadata.len().to_string()),
+            _ => None,
+        };
+        json.push_str(&format!(
+            "\n    {{\"path\": {}, \"type\": {}, \"source\": {}, \"size\": {}}}",
+            json_string(path),
+            json_string(entry_type),
+            optional_string(source.as_deref()),
+            size.as_deref().unwrap_or("null")
+        ));
+    }
+    json.push_str("\n  ]\n}\n");
+    json
+}
+
+pub fn read(path: &Path) -> Result<Manifest> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    parse(&content).with_context(|| format!("invalid manifest {}", path.to_string_lossy()))
+}
+
+pub fn parse(content: &str) -> Result<Manifest> {
+    let value = json::parse(content)?;
+    let entries = value
+        .get("entries")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| anyhow!("no entries"))?;
+    Ok(Manifest {
+        source: value.get("source").and_then(json::Value::as_str).map(PathBuf::from),
+        entries: entries.iter().map(parse_entry).collect::<Result<_>>()?,
+    })
+}
+
+fn parse_entry(value: &json::Value) -> Result<Entry> {
+    let path = value
+        .get("path")
+        .and_then(json::Value::as_str)
+        .ok_or_else(|| anyhow!("an entry has no path"))?;
+    // the entries stay in the target
+    if !Path::new(path).components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(anyhow!("the path {} of an entry isn't relative to the target", path));
+    }
+    let kind = match value.get("type").and_then(json::Value::as_str) {
+        Some("dir") => ActionKind::CreateDir,
+        Some("copy") => ActionKind::Copy,
+        Some("link") => ActionKind::Link,
+        Some("hardlink") => ActionKind::Hardlink,
+        Some("extract") => ActionKind::Extract,
+        Some(other) => return Err(anyhow!("unknown type {} for {}", other, path)),
+        None => return Err(anyhow!("no type for {}", path)),
+    };
+    let source = value.get("source").and_then(json::Value::as_str).map(PathBuf::from);
+    if source.is_none() && kind != ActionKind::CreateDir {
+        return Err(anyhow!("no source for {}", path));
+    }
+    Ok(Entry {
+        path: PathBuf::from(path),
+        kind,
+        source,
+        size: value.get("size").and_then(json::Value::as_u64),
+    })
+}
+
+fn optional_string(value: Option<&str>) -> String {
+    value.map_or_else(|| String::from("null"), json_string)
+}
@@ -0,0 +1,119 @@
+// Where a path is mounted, from /proc/self/mounts (Linux only), one mount per line:
+//
+// /dev/sr0 /run/media/user/BG2_CD1 iso9660 ro,nosuid,nodev,relatime 0 0
+//
+// the spaces in the paths are written \040
+//
+// The free space of a filesystem comes from statvfs, the reflinks (copy-on-write copies, on Btrfs and XFS) are made
+// with the FICLONE ioctl.
+
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub read_only: bool,
+}
+
+impl Mount {
+    /// CD/DVD images and the media mounted by the desktop, which can be unmounted anytime
+    pub fn is_removable(&self) -> bool {
+        self.fs_type == "iso9660" || self.fs_type == "udf" || self.mount_point.starts_with("/media") || self.mount_point.starts_with("/run/media")
+    }
+}
+
+/// The mount holding `path` (which should be canonical), if it can be found
+pub fn mount_of(path: &Path) -> Option<Mount> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    find_mount(&mounts, path)
+}
+
+/// The mount of `mounts` (in the /proc/self/mounts format) holding `path`: the last one with the longest mount point
+pub fn find_mount(mounts: &str, path: &Path) -> Option<Mount> {
+    let mut found: Option<Mount> = None;
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        let mount_point = PathBuf::from(unescape(fields[1]));
+        if !path.starts_with(&mount_point) {
+            continue;
+        }
+        let is_longer = found
+            .as_ref()
+            .map_or(true, |found| mount_point.components().count() >= found.mount_point.components().count());
+        if is_longer {
+            found = Some(Mount {
+                mount_point,
+                fs_type: fields[2].to_string(),
+                read_only: fields[3].split(',').any(|option| option == "ro"),
+            });
+        }
+    }
+    found
+}
+
+/// decodes the octal escapes (\040 for a space...)
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let raw = field.as_bytes();
+    let mut index = 0;
+    while index < raw.len() {
+        let octal = raw.get(index + 1..index + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+        match octal.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) if raw[index] == b'\\' => {
+                bytes.push(byte);
+                index += 4;
+            }
+            _ => {
+                bytes.push(raw[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The space available (to a non-root user) on the filesystem holding `path`
+#[allow(clippy::unnecessary_cast)]
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+    // statvfs fills stats when it succeeds
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stats = unsafe { stats.assume_init() };
+    // the sizes are u32 on some platforms
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// Makes `target` a reflink of `source`: a copy sharing its blocks until one of them is modified. Fails when the
+/// filesystem can't (or when they are on two filesystems)
+#[cfg(target_os = "linux")]
+pub fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+    // _IOW(0x94, 9, int)
+    const FICLONE: u64 = 0x4004_9409;
+    let source = File::open(source)?;
+    let target_file = File::create(target)?;
+    // the descriptors stay open for the call
+    if unsafe { libc::ioctl(target_file.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(target_file);
+        let _ = std::fs::remove_file(target);
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn reflink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are only made on Linux"))
+}
@@ -0,0 +1,100 @@
+// Where a target comes from, written as .iedup-source at its root by the runs that build it so that --verify and
+// --merge can be given the target alone:
+//
+// # written by iedup, the source of this target
+// source = "/gog/bg2ee"
+// game = "BG2EE"
+// version = "2.6.6.0"
+//
+// [options]
+// exclude = ["game/lang/de_DE"]
+// copy_all = false
+//
+// The source is canonicalized, the options are named like the fields of Config.
+
+use crate::plan::json_string;
+use crate::{Config, GameDescription, OverrideMode};
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// What the source file says about the source
+pub struct Origin {
+    pub source: PathBuf,
+    /// the id of the game (BG2EE...)
+    pub game: String,
+    /// whether the files of the target aren't at the paths of their sources (--flatten, --lowercase-names)
+    pub moved: bool,
+}
+
+pub fn to_toml(source: &Path, description: &GameDescription, config: &Config) -> String {
+    let mut toml = format!(
+        "# written by iedup, the source of this target\nsource = {}\ngame = {}\n",
+        json_string(&source.to_string_lossy()),
+        json_string(&description.game.to_string())
+    );
+    if let Some(version) = &description.version {
+        toml.push_str(&format!("version = {}\n", json_string(version)));
+    }
+    toml.push_str("\n[options]\n");
+    for (name, values) in &[
+        ("exclude", &config.exclude),
+        ("include_only", &config.include_only),
+        ("languages", &config.languages),
+        ("rules", &config.rules),
+        ("dlc_patterns", &config.dlc_patterns),
+    ] {
+        if !values.is_empty() {
+            let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+            toml.push_str(&format!("{} = [{}]\n", name, values.join(", ")));
+        }
+    }
+    if !config.skip.is_empty() {
+        let skipped: Vec<String> = config.skip.iter().map(|item| json_string(item.name())).collect();
+        toml.push_str(&format!("skip = [{}]\n", skipped.join(", ")));
+    }
+    if let Some(profile) = &config.profile {
+        toml.push_str(&format!("profile = {}\n", json_string(profile)));
+    }
+    if let Some(override_mode) = config.override_mode {
+        let override_mode = match override_mode {
+            OverrideMode::Copy => "copy",
+            OverrideMode::Link => "link",
+            OverrideMode::LinkDir => "link-dir",
+        };
+        toml.push_str(&format!("override_mode = {}\n", json_string(override_mode)));
+    }
+    if let Some(link_base) = &config.link_base {
+        toml.push_str(&format!("link_base = {}\n", json_string(&link_base.to_string_lossy())));
+    }
+    toml.push_str(&format!(
+        "copy_all = {}\ncopy_support = {}\nflatten = {}\nlowercase_names = {}\n",
+        config.copy_all, config.copy_support, config.flatten, config.lowercase_names
+    ));
+    toml
+}
+
+pub fn read(path: &Path) -> Result<Origin> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+    let value: toml::Value = content
+        .parse()
+        .with_context(|| format!("invalid source file {}", path.to_string_lossy()))?;
+    let field = |key: &str| {
+        value
+            .get(key)
+            .and_then(toml::Value::as_str)
+            .ok_or_else(|| anyhow!("no {} in the source file {}", key, path.to_string_lossy()))
+    };
+    // false in the files written before the options were recorded
+    let option = |key: &str| {
+        value
+            .get("options")
+            .and_then(|options| options.get(key))
+            .and_then(toml::Value::as_bool)
+            .unwrap_or(false)
+    };
+    Ok(Origin {
+        source: PathBuf::from(field("source")?),
+        game: field("game")?.to_string(),
+        moved: option("flatten") || option("lowercase_names"),
+    })
+}
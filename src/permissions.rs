@@ -0,0 +1,74 @@
+// The permissions and the owner given to the copies and the created dirs (--chmod, --chown), for the shared installs
+// where the game runs as another user.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// An octal mode (644, 0640...)
+pub fn parse_mode(value: &str) -> Result<u32> {
+    match u32::from_str_radix(value, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(anyhow!("invalid mode {} (expected an octal mode like 644)", value)),
+    }
+}
+
+/// The mode of a dir for the mode of the files: searchable by those who can read
+pub fn dir_mode(mode: u32) -> u32 {
+    mode | ((mode & 0o444) >> 2)
+}
+
+pub fn chmod(path: &Path, mode: u32) -> Result<()> {
+    std::fs::set_permissions(path, Permissions::from_mode(mode)).with_context(|| format!("failed to change the mode of {}", path.to_string_lossy()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl Owner {
+    /// user:group, user or :group, with names or numeric ids
+    pub fn parse(value: &str) -> Result<Owner> {
+        let (user, group) = match value.find(':') {
+            Some(index) => (&value[..index], &value[index + 1..]),
+            None => (value, ""),
+        };
+        if user.is_empty() && group.is_empty() {
+            return Err(anyhow!("invalid owner {} (expected user:group, user or :group)", value));
+        }
+        let id = |name: &str, database: &str| -> Result<Option<u32>> {
+            if name.is_empty() {
+                return Ok(None);
+            }
+            match name.parse() {
+                Ok(id) => Ok(Some(id)),
+                Err(_) => find_id(database, name)?
+                    .map(Some)
+                    .ok_or_else(|| anyhow!("unknown {} {}", if database == "/etc/passwd" { "user" } else { "group" }, name)),
+            }
+        };
+        Ok(Owner {
+            uid: id(user, "/etc/passwd")?,
+            gid: id(group, "/etc/group")?,
+        })
+    }
+
+    pub fn apply(&self, path: &Path) -> std::io::Result<()> {
+        std::os::unix::fs::chown(path, self.uid, self.gid)
+    }
+}
+
+/// the id of `name` in /etc/passwd or /etc/group (name:password:id:...)
+fn find_id(database: &str, name: &str) -> Result<Option<u32>> {
+    let content = std::fs::read_to_string(database).with_context(|| format!("failed to read {}", database))?;
+    Ok(content.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next() != Some(name) {
+            return None;
+        }
+        fields.nth(1).and_then(|id| id.parse().ok())
+    }))
+}
@@ -0,0 +1,179 @@
+// The actions of a run, collected by --dry-run and printed with --plan-format=json:
+//
+// [
+//   {"action": "copy", "source": "/gog/bg2ee/start.sh", "target": "/games/bg2ee/start.sh"},
+//   {"action": "link", "source": "/gog/bg2ee/support", "target": "/games/bg2ee/support"},
+//   {"action": "create_dir", "source": "/gog/bg2ee/game", "target": "/games/bg2ee/game"},
+//   ...
+// ]
+//
+// "remove" actions (cleaning a previous output with --force) have a null source, "backup" actions (--backup) move
+// their source, a file of the target about to be replaced, to their target. "hardlink" actions are those of the
+// hardlink rules, "extract" actions extract a file of a dlc archive, their source being <archive>/<path in the archive>
+// (--extract-dlc).
+//
+// --emit-script writes the same actions as a bash or PowerShell script.
+
+use crate::dlc;
+use anyhow::{anyhow, Error as AnyError, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ActionKind {
+    CreateDir,
+    Copy,
+    Link,
+    Hardlink,
+    /// extract a file of a dlc archive (the source, <archive>/<path in the archive>)
+    Extract,
+    Remove,
+    Backup,
+}
+
+impl ActionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ActionKind::CreateDir => "create_dir",
+            ActionKind::Copy => "copy",
+            ActionKind::Link => "link",
+            ActionKind::Hardlink => "hardlink",
+            ActionKind::Extract => "extract",
+            ActionKind::Remove => "remove",
+            ActionKind::Backup => "backup",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub source: Option<PathBuf>,
+    pub target: PathBuf,
+    /// the size of an extracted file, whose source can't be read
+    pub size: Option<u64>,
+}
+
+impl Action {
+    /// the size of the file a copy or an extract writes (0 if it can't be read)
+    pub fn file_size(&self) -> u64 {
+        match (self.size, &self.source) {
+            (Some(size), _) => size,
+            (None, Some(source)) => source.metadata().map_or(0, |metadata| metadata.len()),
+            (None, None) => 0,
+        }
+    }
+}
+
+/// The actions as a JSON array, one action per line
+pub fn to_json(actions: &[Action]) -> String {
+    let mut json = String::from("[");
+    for (index, action) in actions.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let source = match &action.source {
+            Some(source) => json_string(&source.to_string_lossy()),
+            None => String::from("null"),
+        };
+        json.push_str(&format!(
+            "\n  {{\"action\": {}, \"source\": {}, \"target\": {}}}",
+            json_string(action.kind.name()),
+            source,
+            json_string(&action.target.to_string_lossy())
+        ));
+    }
+    json.push_str("\n]");
+    json
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shell {
+    Bash,
+    PowerShell,
+}
+
+impl FromStr for Shell {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "bash" => Ok(Shell::Bash),
+            "powershell" => Ok(Shell::PowerShell),
+            _ => Err(anyhow!("unknown shell {} (expected bash or powershell)", value)),
+        }
+    }
+}
+
+/// The actions as a script doing them, `copy_command` is the bash command copying a file (cp -p...)
+pub fn to_script(actions: &[Action], shell: Shell, copy_command: &str) -> String {
+    let mut script = match shell {
+        Shell::Bash => String::from("#!/bin/bash\nset -e\n"),
+        Shell::PowerShell => String::from("$ErrorActionPreference = \"Stop\"\n"),
+    };
+    for action in actions {
+        let target = quote(shell, &action.target);
+        let source = action.source.as_deref().map(|source| quote(shell, source)).unwrap_or_default();
+        // the archive and the file in it of an extract
+        let extracted = action.source.as_deref().filter(|_| action.kind == ActionKind::Extract);
+        let (archive, entry) = match extracted.and_then(dlc::split_source) {
+            Some((archive, entry)) => (quote(shell, archive), quote(shell, entry)),
+            None => (String::new(), String::new()),
+        };
+        let line = match (shell, action.kind) {
+            (Shell::Bash, ActionKind::CreateDir) => format!("mkdir {}", target),
+            (Shell::Bash, ActionKind::Copy) => format!("{} {} {}", copy_command, source, target),
+            (Shell::Bash, ActionKind::Link) => format!("ln -s {} {}", source, target),
+            (Shell::Bash, ActionKind::Hardlink) => format!("ln {} {}", source, target),
+            (Shell::Bash, ActionKind::Extract) => format!("unzip -p {} {} > {}", archive, entry, target),
+            (Shell::Bash, ActionKind::Remove) => format!("rm -rf {}", target),
+            (Shell::Bash, ActionKind::Backup) => format!("mv {} {}", source, target),
+            (Shell::PowerShell, ActionKind::CreateDir) => format!("New-Item -ItemType Directory -Path {} | Out-Null", target),
+            (Shell::PowerShell, ActionKind::Copy) => format!("Copy-Item -LiteralPath {} -Destination {}", source, target),
+            (Shell::PowerShell, ActionKind::Link) => {
+                format!("New-Item -ItemType SymbolicLink -Path {} -Target {} | Out-Null", target, source)
+            }
+            (Shell::PowerShell, ActionKind::Hardlink) => {
+                format!("New-Item -ItemType HardLink -Path {} -Target {} | Out-Null", target, source)
+            }
+            (Shell::PowerShell, ActionKind::Extract) => format!(
+                // Windows PowerShell doesn't load the zip classes by itself
+                "Add-Type -AssemblyName System.IO.Compression.FileSystem; $zip = [IO.Compression.ZipFile]::OpenRead({}); \
+                 [IO.Compression.ZipFileExtensions]::ExtractToFile($zip.GetEntry({}), {}, $true); $zip.Dispose()",
+                archive, entry, target
+            ),
+            (Shell::PowerShell, ActionKind::Remove) => format!("Remove-Item -LiteralPath {} -Recurse -Force", target),
+            (Shell::PowerShell, ActionKind::Backup) => format!("Move-Item -LiteralPath {} -Destination {}", source, target),
+        };
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script
+}
+
+/// `path` between single quotes, where nothing is interpreted by the shell
+fn quote(shell: Shell, path: &Path) -> String {
+    let path = path.to_string_lossy();
+    match shell {
+        Shell::Bash => format!("'{}'", path.replace('\'', "'\\''")),
+        Shell::PowerShell => format!("'{}'", path.replace('\'', "''")),
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,120 @@
+// Named sets of options (--profile), built in or from profiles.toml in the config dir (~/.config/iedup on Linux):
+//
+// [profiles.eet]
+// description = "BG2EE prepared for the Enhanced Edition Trilogy"
+// override_mode = "copy"
+// exclude = ["game/movies/*.wbm"]
+// include_only = []
+//
+// [profiles.eet.rules]
+// "EET*" = "copy"
+//
+// All the keys are optional. A profile of profiles.toml replaces the built-in profile with the same name.
+
+use crate::config::{config_dir, parse_rules, string, strings, Rule};
+use crate::OverrideMode;
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+static BUILTIN_PROFILES: &str = r#"
+[profiles.play]
+description = "only playing, no mods: the override dirs are linked as a whole"
+override_mode = "link-dir"
+
+[profiles.modding]
+description = "installing mods: the override dirs and the usual mod frameworks and WeiDU files are copied"
+override_mode = "copy"
+
+[profiles.modding.rules]
+"EEex*" = "copy"
+"weidu_external" = "copy"
+"WeiDU*" = "copy"
+"setup-*" = "copy"
+
+[profiles.bg2ee-eet]
+description = "BG2EE for the Enhanced Edition Trilogy: like modding, with the EET dirs copied"
+override_mode = "copy"
+
+[profiles.bg2ee-eet.rules]
+"EET*" = "copy"
+"EEex*" = "copy"
+"weidu_external" = "copy"
+"WeiDU*" = "copy"
+"setup-*" = "copy"
+"#;
+
+pub struct Profile {
+    pub name: String,
+    pub description: String,
+    pub exclude: Vec<String>,
+    pub include_only: Vec<String>,
+    pub override_mode: Option<OverrideMode>,
+    /// applied after the rules of --config
+    pub rules: Vec<Rule>,
+}
+
+/// The profiles of a profiles.toml
+pub fn parse(content: &str) -> Result<Vec<Profile>> {
+    let value: toml::Value = content.parse()?;
+    let mut profiles = Vec::new();
+    for (key, value) in value.as_table().into_iter().flatten() {
+        if key != "profiles" {
+            return Err(anyhow!("unknown key {}", key));
+        }
+        let table = value.as_table().ok_or_else(|| anyhow!("profiles should be a table"))?;
+        for (name, value) in table {
+            profiles.push(parse_profile(name, value).with_context(|| format!("invalid profile {}", name))?);
+        }
+    }
+    Ok(profiles)
+}
+
+fn parse_profile(name: &str, value: &toml::Value) -> Result<Profile> {
+    let mut profile = Profile {
+        name: name.to_string(),
+        description: String::new(),
+        exclude: vec![],
+        include_only: vec![],
+        override_mode: None,
+        rules: vec![],
+    };
+    let table = value.as_table().ok_or_else(|| anyhow!("a profile should be a table"))?;
+    for (key, value) in table {
+        match key.as_str() {
+            "description" => profile.description = string(key, value)?.to_string(),
+            "exclude" => profile.exclude = strings(key, value)?,
+            "include_only" => profile.include_only = strings(key, value)?,
+            "override_mode" => profile.override_mode = Some(string(key, value)?.parse()?),
+            "rules" => profile.rules = parse_rules(value)?,
+            _ => return Err(anyhow!("unknown key {}", key)),
+        }
+    }
+    Ok(profile)
+}
+
+/// profiles.toml in the config dir, if there is a config dir
+pub fn user_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("profiles.toml"))
+}
+
+/// The built-in profiles and those of profiles.toml, by name
+pub fn all() -> Result<Vec<Profile>> {
+    let mut profiles = parse(BUILTIN_PROFILES)?;
+    if let Some(path) = user_file().filter(|path| path.exists()) {
+        let content = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+        let user_profiles = parse(&content).with_context(|| format!("invalid profiles file {}", path.to_string_lossy()))?;
+        for profile in user_profiles {
+            profiles.retain(|builtin| builtin.name != profile.name);
+            profiles.push(profile);
+        }
+    }
+    profiles.sort_by(|first, second| first.name.cmp(&second.name));
+    Ok(profiles)
+}
+
+pub fn find(name: &str) -> Result<Profile> {
+    all()?
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| anyhow!("unknown profile {} (see --list-profiles)", name))
+}
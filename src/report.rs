@@ -0,0 +1,92 @@
+// Summary of a run for the scripts and CI (--report-json), written even when the run fails at the end:
+//
+// {
+//   "error": null,
+//   "game": {"id": "BG2EE", "os": "Linux", "vendor": "Gog", "name": "...", "version": "2.6.6.0", "build": "27023"},
+//   "categories": {
+//     "game": {"copied": 2, "linked": 2, "dirs": 1, "copied_bytes": 1234, "linked_bytes": 0},
+//     ...
+//   },
+//   "total": {"copied": 10, "linked": 120, "dirs": 12, "copied_bytes": 1234, "linked_bytes": 2000000000,
+//             "saved_bytes": 2000000000},
+//   "elapsed": 1.234,
+//   "phases": {"scripts": 0.012, ...},
+//   "warnings": ["..."],
+//   "errors": ["..."],
+//   "vanished": ["/gog/bg2ee/game/override/patch.itm"]
+// }
+//
+// "error" is the message of the failure of the run, "errors" the items that failed with --continue-on-error,
+// "vanished" the source files that disappeared before they were copied or linked (and were skipped). The saved bytes
+// are those of the links and of the copies replaced by hard links (--dedup-copies).
+
+use crate::plan::json_string;
+use crate::stats::Stats;
+use crate::GameDescription;
+use std::collections::BTreeMap;
+
+pub struct Report<'a> {
+    pub error: Option<String>,
+    pub game: &'a GameDescription,
+    pub categories: &'a BTreeMap<String, Stats>,
+    pub total: &'a Stats,
+    pub warnings: &'a [String],
+    pub errors: &'a [String],
+    pub vanished: &'a [String],
+}
+
+pub fn to_json(report: &Report) -> String {
+    let game = report.game;
+    let mut json = format!(
+        "{{\n  \"error\": {},\n  \"game\": {{\"id\": {}, \"os\": {}, \"vendor\": {}, \"name\": {}, \"version\": {}, \"build\": {}}},\n  \"categories\": {{",
+        optional_string(report.error.as_deref()),
+        json_string(&game.game.to_string()),
+        json_string(&format!("{:?}", game.os)),
+        json_string(&format!("{:?}", game.vendor)),
+        optional_string(game.name.as_deref()),
+        optional_string(game.version.as_deref()),
+        optional_string(game.build.as_deref())
+    );
+    for (index, (category, stats)) in report.categories.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!("\n    {}: {{{}}}", json_string(category), counts(stats)));
+    }
+    let total = report.total;
+    json.push_str(&format!(
+        "\n  }},\n  \"total\": {{{}, \"saved_bytes\": {}}},\n  \"elapsed\": {:.3},\n  \"phases\": {{",
+        counts(total),
+        total.linked_bytes + total.deduped_bytes,
+        total.elapsed.as_secs_f64()
+    ));
+    let phases: Vec<String> = total
+        .phases
+        .iter()
+        .map(|(phase, duration)| format!("{}: {:.3}", json_string(phase), duration.as_secs_f64()))
+        .collect();
+    json.push_str(&phases.join(", "));
+    json.push_str(&format!(
+        "}},\n  \"warnings\": {},\n  \"errors\": {},\n  \"vanished\": {}\n}}\n",
+        strings(report.warnings),
+        strings(report.errors),
+        strings(report.vanished)
+    ));
+    json
+}
+
+fn counts(stats: &Stats) -> String {
+    format!(
+        "\"copied\": {}, \"linked\": {}, \"dirs\": {}, \"copied_bytes\": {}, \"linked_bytes\": {}",
+        stats.copied, stats.linked, stats.dirs, stats.bytes, stats.linked_bytes
+    )
+}
+
+fn strings(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn optional_string(value: Option<&str>) -> String {
+    value.map_or_else(|| String::from("null"), json_string)
+}
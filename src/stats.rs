@@ -0,0 +1,302 @@
+// Recap of a run, per category of the target:
+//
+// category         copied   linked     dirs       copied size
+// game                  2        2        1            1.2 KiB
+// game/data             0      120        1                0 B
+// ...
+//
+// The categories are the top-level entries of the target, and the dirs of game/ for the GOG layout. --stats-only sums
+// the sizes by category and by extension as the actions are planned (a Tally), without keeping them. iedup list counts
+// what an existing target holds the same way.
+
+use crate::plan::{Action, ActionKind};
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Stats {
+    pub copied: u64,
+    pub linked: u64,
+    pub dirs: u64,
+    /// size of the copied files
+    pub bytes: u64,
+    /// size of the linked files and dirs, the space saved by linking them
+    pub linked_bytes: u64,
+    /// the space saved by replacing the duplicate copies by hard links, only in the total returned by run
+    pub deduped_bytes: u64,
+    /// the time spent in each phase (scripts, data...), only in the total returned by run
+    pub phases: Vec<(String, Duration)>,
+    /// the time of the whole run, only in the total returned by run
+    pub elapsed: Duration,
+}
+
+/// The sizes of a run by category and by extension (`-` for the files without one), the files of the linked dirs each
+/// counted with its extension
+#[derive(Default)]
+pub struct Tally {
+    pub categories: BTreeMap<String, Stats>,
+    pub extensions: BTreeMap<String, Stats>,
+}
+
+impl Tally {
+    pub fn add(&mut self, action: &Action, target_root: &Path, writable_root: Option<&Path>) {
+        let category = match action_category(action, target_root, writable_root) {
+            Some(category) => self.categories.entry(category).or_default(),
+            None => return,
+        };
+        let extensions = &mut self.extensions;
+        let mut add_file = |path: &Path, size: u64, linked: bool| {
+            let extension = path
+                .extension()
+                .map_or_else(|| String::from("-"), |extension| extension.to_string_lossy().to_lowercase());
+            let stats = extensions.entry(extension).or_default();
+            if linked {
+                stats.linked += 1;
+                stats.linked_bytes += size;
+            } else {
+                stats.copied += 1;
+                stats.bytes += size;
+            }
+        };
+        match (action.kind, &action.source) {
+            (ActionKind::Copy, Some(source)) | (ActionKind::Extract, Some(source)) => {
+                let size = action.file_size();
+                category.copied += 1;
+                category.bytes += size;
+                add_file(source, size, false);
+            }
+            (ActionKind::Link, Some(source)) | (ActionKind::Hardlink, Some(source)) => {
+                category.linked += 1;
+                if source.is_dir() {
+                    for_each_file(source, &mut |path, size| {
+                        category.linked_bytes += size;
+                        add_file(path, size, true);
+                    });
+                } else {
+                    let size = source.metadata().map_or(0, |metadata| metadata.len());
+                    category.linked_bytes += size;
+                    add_file(source, size, true);
+                }
+            }
+            (ActionKind::CreateDir, _) => category.dirs += 1,
+            _ => {}
+        }
+    }
+}
+
+/// the category of the target of an action, None for the target itself (created by --into) and for the links to the
+/// copies of --writable-root (counted as copies)
+fn action_category(action: &Action, target_root: &Path, writable_root: Option<&Path>) -> Option<String> {
+    if let Some(writable_root) = writable_root {
+        if action.source.as_ref().map_or(false, |source| source.starts_with(writable_root)) {
+            return None;
+        }
+    }
+    let relative = match writable_root.and_then(|writable_root| action.target.strip_prefix(writable_root).ok()) {
+        Some(relative) => relative,
+        None => action.target.strip_prefix(target_root).unwrap_or(&action.target),
+    };
+    if relative.as_os_str().is_empty() {
+        return None;
+    }
+    let is_dir = match action.kind {
+        ActionKind::CreateDir => true,
+        ActionKind::Link => action.source.as_ref().map_or(false, |source| source.is_dir()),
+        _ => false,
+    };
+    Some(category(relative, is_dir))
+}
+
+/// Sums the actions of a run by category
+pub fn collect(actions: &[Action], target_root: &Path, writable_root: Option<&Path>) -> BTreeMap<String, Stats> {
+    let mut all_stats: BTreeMap<String, Stats> = BTreeMap::new();
+    for action in actions {
+        let stats = match action_category(action, target_root, writable_root) {
+            Some(category) => all_stats.entry(category).or_default(),
+            None => continue,
+        };
+        match action.kind {
+            ActionKind::Copy | ActionKind::Extract => {
+                stats.copied += 1;
+                stats.bytes += action.file_size();
+            }
+            ActionKind::Link | ActionKind::Hardlink => {
+                stats.linked += 1;
+                stats.linked_bytes += action.source.as_deref().map_or(0, size);
+            }
+            ActionKind::CreateDir => stats.dirs += 1,
+            ActionKind::Remove | ActionKind::Backup => {}
+        }
+    }
+    all_stats
+}
+
+/// the size of a file, or of all the files of a dir (0 for what can't be read)
+fn size(path: &Path) -> u64 {
+    match path.metadata() {
+        Ok(metadata) if metadata.is_dir() => dir_size(path),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// the size of the files of `dir`, without following the links inside it
+fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    for_each_file(dir, &mut |_, size| total += size);
+    total
+}
+
+/// calls `f` with each file of `dir` and its size, going down the dirs but not the links (what can't be read is skipped)
+fn for_each_file(dir: &Path, f: &mut dyn FnMut(&Path, u64)) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => for_each_file(&entry.path(), f),
+            Ok(metadata) => f(&entry.path(), metadata.len()),
+            Err(_) => {}
+        }
+    }
+}
+
+/// What an existing target holds by category: its copies (the regular files), links and dirs, without following the
+/// links. The entries of `generated` at its root (written by iedup itself) are left out
+pub fn inventory(target: &Path, generated: &[&str]) -> std::io::Result<BTreeMap<String, Stats>> {
+    let mut all_stats = BTreeMap::new();
+    add_entries(target, target, generated, &mut all_stats)?;
+    Ok(all_stats)
+}
+
+fn add_entries(target: &Path, dir: &Path, generated: &[&str], all_stats: &mut BTreeMap<String, Stats>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if dir == target && generated.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        let relative = path.strip_prefix(target).unwrap_or(&path);
+        let stats = all_stats.entry(category(relative, file_type.is_dir())).or_default();
+        if file_type.is_dir() {
+            stats.dirs += 1;
+            add_entries(target, &path, generated, all_stats)?;
+        } else if file_type.is_symlink() {
+            stats.linked += 1;
+        } else {
+            stats.copied += 1;
+            stats.bytes += entry.metadata()?.len();
+        }
+    }
+    Ok(())
+}
+
+/// the category of a target entry: its first dir (game/<dir> in game/), `.` for the files at the root
+fn category(relative: &Path, is_dir: bool) -> String {
+    let components: Vec<_> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    let depth = if components.len() > 1 && components[0] == "game" { 2 } else { 1 };
+    // the entries directly in a category dir belong to it, the dirs are their own category
+    let length = if components.len() > depth || is_dir {
+        depth.min(components.len())
+    } else {
+        components.len() - 1
+    };
+    if length == 0 {
+        String::from(".")
+    } else {
+        components[..length].join("/")
+    }
+}
+
+/// The stats as a table, one line per category plus the header and the total
+pub fn table(all_stats: &BTreeMap<String, Stats>) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<16} {:>8} {:>8} {:>8} {:>13}",
+        "category", "copied", "linked", "dirs", "copied size"
+    )];
+    for (category, stats) in all_stats {
+        lines.push(line(category, stats));
+    }
+    lines.push(line("total", &total(all_stats)));
+    lines
+}
+
+/// The sizes of the stats as a table (--stats-only), one line per category or extension (`name`) plus the header and
+/// the total
+pub fn size_table(name: &str, all_stats: &BTreeMap<String, Stats>) -> Vec<String> {
+    let size_line = |name: &str, stats: &Stats| {
+        format!(
+            "{:<16} {:>8} {:>8} {:>13} {:>13}",
+            name,
+            stats.copied,
+            stats.linked,
+            human_size(stats.bytes),
+            human_size(stats.linked_bytes)
+        )
+    };
+    let mut lines = vec![format!(
+        "{:<16} {:>8} {:>8} {:>13} {:>13}",
+        name, "copied", "linked", "copied size", "linked size"
+    )];
+    for (name, stats) in all_stats {
+        lines.push(size_line(name, stats));
+    }
+    lines.push(size_line("total", &total(all_stats)));
+    lines
+}
+
+pub fn total(all_stats: &BTreeMap<String, Stats>) -> Stats {
+    let mut total = Stats::default();
+    for stats in all_stats.values() {
+        total.copied += stats.copied;
+        total.linked += stats.linked;
+        total.dirs += stats.dirs;
+        total.bytes += stats.bytes;
+        total.linked_bytes += stats.linked_bytes;
+    }
+    total
+}
+
+/// The timings of `total` (--timings), one line per phase plus the total
+pub fn timings(total: &Stats) -> Vec<String> {
+    let mut lines = vec![format!("{:<16} {:>12}", "phase", "time")];
+    for (phase, duration) in &total.phases {
+        lines.push(format!("{:<16} {:>12.3?}", phase, duration));
+    }
+    lines.push(format!("{:<16} {:>12.3?}", "total", total.elapsed));
+    lines
+}
+
+fn line(category: &str, stats: &Stats) -> String {
+    format!(
+        "{:<16} {:>8} {:>8} {:>8} {:>13}",
+        category,
+        stats.copied,
+        stats.linked,
+        stats.dirs,
+        human_size(stats.bytes)
+    )
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
@@ -0,0 +1,68 @@
+// Steam app manifests (steamapps/appmanifest_<appid>.acf), in the "key" "value" VDF text format:
+//
+// "AppState"
+// {
+//     "appid"       "228280"
+//     "name"        "Baldur's Gate: Enhanced Edition"
+//     "installdir"  "Baldur's Gate Enhanced Edition"
+//     "buildid"     "5386523"
+//     "UserConfig"
+//     {
+//         "language"    "english"
+//     }
+// }
+
+use anyhow::Result;
+use glob::{glob, Pattern};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Reads the key/values of an .acf file. Nested blocks are flattened, the first occurrence of a key wins.
+pub fn read_acf(path: &Path) -> Result<HashMap<String, String>> {
+    let content = std::fs::read_to_string(path)?;
+    let mut values = HashMap::new();
+    let mut key: Option<String> = None;
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => token.extend(chars.next()),
+                        '"' => break,
+                        _ => token.push(c),
+                    }
+                }
+                match key.take() {
+                    Some(key) => {
+                        values.entry(key).or_insert(token);
+                    }
+                    None => key = Some(token),
+                }
+            }
+            // the pending key was the name of a block ("AppState", "UserConfig"...)
+            '{' | '}' => key = None,
+            _ => {}
+        }
+    }
+    Ok(values)
+}
+
+/// Finds the manifest of the app installed in `install_dir` (`steamapps/common/<installdir>`)
+pub fn find_app_manifest(install_dir: &Path) -> Result<Option<(PathBuf, HashMap<String, String>)>> {
+    let install_dir = install_dir.canonicalize()?;
+    let (dir_name, steamapps) = match (install_dir.file_name(), install_dir.parent().and_then(Path::parent)) {
+        (Some(dir_name), Some(steamapps)) => (dir_name, steamapps),
+        _ => return Ok(None),
+    };
+    let pattern = format!("{}/appmanifest_*.acf", Pattern::escape(&steamapps.to_string_lossy()));
+    for manifest in glob(&pattern)? {
+        let manifest = manifest?;
+        let values = read_acf(&manifest)?;
+        if values.get("installdir").map(|name| name.as_str()) == dir_name.to_str() {
+            return Ok(Some((manifest, values)));
+        }
+    }
+    Ok(None)
+}
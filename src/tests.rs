@@ -0,0 +1,2610 @@
+use super::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use tempfile::TempDir;
+
+/// Writes a KEY V1 file referencing `bifs` (and no resources)
+fn write_key(path: &Path, bifs: &[&str]) {
+    let entries_offset = 0x18;
+    let names_offset = entries_offset + 12 * bifs.len();
+    let mut entries = Vec::new();
+    let mut names = Vec::new();
+    for bif in bifs {
+        entries.extend_from_slice(&0u32.to_le_bytes());
+        entries.extend_from_slice(&((names_offset + names.len()) as u32).to_le_bytes());
+        entries.extend_from_slice(&((bif.len() + 1) as u16).to_le_bytes());
+        entries.extend_from_slice(&0u16.to_le_bytes());
+        names.extend_from_slice(bif.as_bytes());
+        names.push(0);
+    }
+    let mut bytes = b"KEY V1  ".to_vec();
+    bytes.extend_from_slice(&(bifs.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(entries_offset as u32).to_le_bytes());
+    bytes.extend_from_slice(&((names_offset + names.len()) as u32).to_le_bytes());
+    bytes.extend(entries);
+    bytes.extend(names);
+    fs::write(path, bytes).unwrap();
+}
+
+fn write_file(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// Builds a small linux GOG EE install
+fn fake_source() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_file(&root.join("start.sh"), "#!/bin/sh\n");
+    fs::set_permissions(root.join("start.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    write_file(&root.join("gameinfo"), "Baldur's Gate II: Enhanced Edition\n2.6.6.0\n27023\n");
+    write_file(&root.join("support/xdg-open"), "support");
+    write_file(&root.join("sod-dlc.zip"), "dlc");
+
+    let game = root.join("game");
+    fs::create_dir(&game).unwrap();
+    write_key(&game.join("chitin.key"), &["data\\Default.bif", "data\\AREA000A.bif"]);
+    write_file(&game.join("engine.lua"), "engine");
+    write_file(&game.join("BaldursGate"), "exe");
+    write_file(&game.join("Manuals/manual.pdf"), "manual");
+    write_file(&game.join("scripts/bdai.bs"), "script");
+    write_file(&game.join("data/Default.bif"), "bif");
+    write_file(&game.join("data/AREA000A.bif"), "bif");
+    write_file(&game.join("lang/en_US/dialog.tlk"), "tlk");
+    write_file(&game.join("lang/en_US/sounds/ab.wav"), "wav");
+    write_file(&game.join("lang/en_US/sounds/sndlist.txt"), "sndlist");
+    write_file(&game.join("movies/intro.wbm"), "movie");
+    write_file(&game.join("movies/480/intro.wbm"), "movie");
+    write_file(&game.join("movies/lo/intro.wbm"), "movie");
+    write_file(&game.join("music/bg1.mus"), "mus");
+    write_file(&game.join("music/lone.acm"), "acm");
+    write_file(&game.join("music/bg1/bg1a.acm"), "acm");
+    write_file(&game.join("override/sw1h01.itm"), "itm");
+    dir
+}
+
+/// Builds a Steam EE install (steamapps/common/<installdir> with its app manifest),
+/// returns the steam library and the install dir
+fn fake_steam_source() -> (TempDir, PathBuf) {
+    let library = tempfile::tempdir().unwrap();
+    let steamapps = library.path().join("steamapps");
+    let install_dir = steamapps.join("common/Baldur's Gate Enhanced Edition");
+    fs::create_dir_all(&install_dir).unwrap();
+    write_file(
+        &steamapps.join("appmanifest_228280.acf"),
+        r#""AppState"
+{
+	"appid"		"228280"
+	"name"		"Baldur's Gate: Enhanced Edition"
+	"installdir"		"Baldur's Gate Enhanced Edition"
+	"buildid"		"5386523"
+	"UserConfig"
+	{
+		"language"		"english"
+	}
+}
+"#,
+    );
+    write_key(&install_dir.join("chitin.key"), &["data\\Default.bif"]);
+    write_file(&install_dir.join("engine.lua"), "engine");
+    write_file(&install_dir.join("BaldursGate"), "exe");
+    write_file(&install_dir.join("scripts/bdai.bs"), "script");
+    write_file(&install_dir.join("data/Default.bif"), "bif");
+    write_file(&install_dir.join("lang/en_US/dialog.tlk"), "tlk");
+    fs::create_dir_all(install_dir.join("movies")).unwrap();
+    write_file(&install_dir.join("music/bg1.mus"), "mus");
+    (library, install_dir)
+}
+
+/// Builds a windows GOG EE install, the game dir is the root
+fn fake_windows_source() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_key(&root.join("chitin.key"), &["data\\Default.bif"]);
+    write_file(&root.join("Baldur.exe"), "exe");
+    write_file(&root.join("SDL2.dll"), "dll");
+    write_file(&root.join("goggame-1456460669.info"), "{}");
+    write_file(&root.join("Baldur.ini"), "ini");
+    write_file(&root.join("scripts/bdai.bs"), "script");
+    write_file(&root.join("data/Default.bif"), "bif");
+    write_file(&root.join("lang/en_US/dialog.tlk"), "tlk");
+    fs::create_dir_all(root.join("movies")).unwrap();
+    write_file(&root.join("music/bg1.mus"), "mus");
+    dir
+}
+
+/// Builds a classic install: dialog.tlk and the ini next to chitin.key, no lang/
+fn fake_classic_source() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_key(&root.join("chitin.key"), &["data\\AREA000A.bif"]);
+    write_file(&root.join("BGMain.exe"), "exe");
+    write_file(&root.join("dialog.tlk"), "tlk");
+    write_file(&root.join("Baldur.ini"), "ini");
+    write_file(&root.join("scripts/bdai.bs"), "script");
+    write_file(&root.join("data/AREA000A.bif"), "bif");
+    write_file(&root.join("CD2/Data/AREA100A.bif"), "bif");
+    write_file(&root.join("save/000000001-Quick-Save/BALDUR.gam"), "save");
+    write_file(&root.join("override/sw1h01.itm"), "item");
+    fs::create_dir_all(root.join("movies")).unwrap();
+    write_file(&root.join("music/bg1.mus"), "mus");
+    dir
+}
+
+fn fake_beamdog_source(exe: &str) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write_key(&root.join("chitin.key"), &["data\\Default.bif"]);
+    write_file(&root.join(".build-id"), "8216\n");
+    write_file(&root.join(exe), "exe");
+    write_file(&root.join("engine.lua"), "engine");
+    write_file(&root.join("scripts/bdai.bs"), "script");
+    write_file(&root.join("data/Default.bif"), "bif");
+    write_file(&root.join("lang/en_US/dialog.tlk"), "tlk");
+    fs::create_dir_all(root.join("movies")).unwrap();
+    write_file(&root.join("music/bg1.mus"), "mus");
+    dir
+}
+
+fn test_config(source: &Path, target: &Path) -> Config {
+    Config {
+        no_progress: true,
+        color: ColorChoice::Never,
+        ..Config::new(source, target)
+    }
+}
+
+fn is_link(path: &Path) -> bool {
+    path.symlink_metadata().map_or(false, |metadata| metadata.file_type().is_symlink())
+}
+
+fn is_copy(path: &Path) -> bool {
+    path.symlink_metadata().map_or(false, |metadata| metadata.is_file())
+}
+
+#[test]
+fn links_and_copies() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+
+    let root = target.path();
+    assert!(is_copy(&root.join("start.sh")));
+    assert!(is_link(&root.join("gameinfo")));
+    assert!(is_link(&root.join("support")));
+    assert!(is_link(&root.join("sod-dlc.zip")));
+    let game = root.join("game");
+    assert!(is_copy(&game.join("chitin.key")));
+    assert!(is_copy(&game.join("engine.lua")));
+    assert!(is_link(&game.join("BaldursGate")));
+    assert!(is_link(&game.join("Manuals")));
+    assert!(is_copy(&game.join("scripts/bdai.bs")));
+    assert!(is_link(&game.join("data/Default.bif")));
+    assert!(is_copy(&game.join("lang/en_US/dialog.tlk")));
+    assert!(is_link(&game.join("lang/en_US/sounds/ab.wav")));
+    assert!(is_copy(&game.join("lang/en_US/sounds/sndlist.txt")));
+    assert!(is_link(&game.join("movies/intro.wbm")));
+    assert!(is_link(&game.join("movies/480/intro.wbm")));
+    assert!(is_copy(&game.join("music/bg1.mus")));
+    assert!(is_link(&game.join("music/lone.acm")));
+    assert!(is_link(&game.join("music/bg1/bg1a.acm")));
+    assert!(is_copy(&game.join("override/sw1h01.itm")));
+    // the run completed, nothing to resume
+    assert!(!root.join(STATE_FILE).exists());
+}
+
+#[test]
+fn copies_dialog_f_when_present() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&source.path().join("game/lang/fr_FR/dialog.tlk"), "tlk");
+    write_file(&source.path().join("game/lang/fr_FR/dialogF.tlk"), "tlk");
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_copy(&target.path().join("game/lang/en_US/dialog.tlk")));
+    assert!(!target.path().join("game/lang/en_US/dialogF.tlk").exists());
+    assert!(is_copy(&target.path().join("game/lang/fr_FR/dialog.tlk")));
+    assert!(is_copy(&target.path().join("game/lang/fr_FR/dialogF.tlk")));
+
+    // the names of a pair may not have the same case
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&source.path().join("game/lang/fr_FR/DIALOG.TLK"), "tlk");
+    write_file(&source.path().join("game/lang/fr_FR/dialogf.tlk"), "tlk");
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_copy(&target.path().join("game/lang/fr_FR/DIALOG.TLK")));
+    assert!(is_copy(&target.path().join("game/lang/fr_FR/dialogf.tlk")));
+}
+
+#[test]
+fn requires_dialog_tlk() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&source.path().join("game/lang/fr_FR/dialogF.tlk"), "tlk");
+    assert!(run(test_config(source.path(), target.path())).is_err());
+    // whatever the case of its F
+    let source = fake_source();
+    write_file(&source.path().join("game/lang/fr_FR/dialogf.tlk"), "tlk");
+    let target = tempfile::tempdir().unwrap();
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    assert!(format!("{:#}", error).contains("has a dialogF.tlk but no dialog.tlk"), "{:#}", error);
+}
+
+#[test]
+fn ignores_case_when_asked() {
+    let source = fake_source();
+    let game = source.path().join("game");
+    fs::rename(game.join("chitin.key"), game.join("Chitin.KEY")).unwrap();
+    fs::rename(game.join("music/bg1.mus"), game.join("music/bg1.MUS")).unwrap();
+    fs::rename(game.join("lang/en_US/dialog.tlk"), game.join("lang/en_US/DIALOG.TLK")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        ignore_case: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/Chitin.KEY")));
+    assert!(is_copy(&target.path().join("game/music/bg1.MUS")));
+    assert!(is_copy(&target.path().join("game/lang/en_US/DIALOG.TLK")));
+}
+
+#[test]
+fn copies_keep_permissions_and_timestamps() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let old = FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_times(source.path().join("game/engine.lua"), old, old).unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+
+    let start_sh = target.path().join("start.sh").metadata().unwrap();
+    assert_ne!(start_sh.permissions().mode() & 0o111, 0);
+    let engine_lua = target.path().join("game/engine.lua").metadata().unwrap();
+    assert_eq!(FileTime::from_last_modification_time(&engine_lua), old);
+}
+
+#[test]
+fn detects_the_game() {
+    let source = fake_source();
+    let game = source.path().join("game");
+    assert_eq!(detect_game(&game).unwrap().0, Game::Bg1Ee);
+    write_key(&game.join("chitin.key"), &["data\\Default.bif", "data\\25AmbSnd.bif"]);
+    assert_eq!(detect_game(&game).unwrap().0, Game::Bg2Ee);
+}
+
+#[test]
+fn checks_the_expected_version() {
+    let source = fake_source();
+    let with_version = |target: &Path, expected: &str, loose: bool| Config {
+        expect_version: Some(expected.to_string()),
+        loose_version: loose,
+        ..test_config(source.path(), target)
+    };
+    let target = tempfile::tempdir().unwrap();
+    run(with_version(target.path(), "2.6.6.0", false)).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(with_version(target.path(), "2.6", true)).unwrap();
+
+    let target = tempfile::tempdir().unwrap();
+    let error = run(with_version(target.path(), "2.5.17.0", false)).unwrap_err();
+    assert!(error.to_string().contains("version 2.6.6.0, expected 2.5.17.0"), "{}", error);
+    let error = run(with_version(target.path(), "2.6.5.0", false)).unwrap_err();
+    assert!(error.to_string().contains("expected 2.6.5.0"), "{}", error);
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn checks_the_bifs() {
+    let source = fake_source();
+    let game = source.path().join("game");
+    // the bifs of a language are in its data/ dir
+    write_key(&game.join("chitin.key"), &["data\\Default.bif", "data\\AREA000A.bif", "data\\OH1000.bif"]);
+    write_file(&game.join("lang/en_US/data/OH1000.bif"), "bif");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        check_bifs: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+
+    fs::remove_file(game.join("data/AREA000A.bif")).unwrap();
+    fs::remove_file(game.join("lang/en_US/data/OH1000.bif")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        check_bifs: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("data\\AREA000A.bif, data\\OH1000.bif"), "{}", error);
+    assert_eq!(error.kind(), Some(FailureKind::InvalidSource));
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn steam_layout() {
+    let (_library, source) = fake_steam_source();
+    let description = check_source(&source, &guess_structure(&source)).unwrap();
+    assert_eq!(description.vendor, Vendor::Steam);
+    assert_eq!(description.name.as_deref(), Some("Baldur's Gate: Enhanced Edition"));
+    assert_eq!(description.build.as_deref(), Some("5386523"));
+
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(&source, target.path())).unwrap();
+    assert!(is_copy(&target.path().join("chitin.key")));
+    assert!(is_link(&target.path().join("BaldursGate")));
+    assert!(is_link(&target.path().join("data/Default.bif")));
+    assert!(is_copy(&target.path().join("lang/en_US/dialog.tlk")));
+}
+
+#[test]
+fn exclude_prunes_subtrees() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&source.path().join("game/lang/de_DE/dialog.tlk"), "tlk");
+    write_file(&source.path().join("game/lang/de_DE/sounds/ab.wav"), "wav");
+    let config = Config {
+        exclude: vec!["game/lang/de_DE".to_string(), "*.wbm".to_string()],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(!target.path().join("game/lang/de_DE").exists());
+    assert!(is_copy(&target.path().join("game/lang/en_US/dialog.tlk")));
+    assert!(!target.path().join("game/movies/intro.wbm").exists());
+    assert!(!target.path().join("game/movies/480/intro.wbm").exists());
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+}
+
+#[test]
+fn include_only_music() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        include_only: vec!["game/music/**".to_string()],
+        exclude: vec!["game/music/lone.acm".to_string()],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/music/bg1.mus")));
+    assert!(is_link(&target.path().join("game/music/bg1/bg1a.acm")));
+    assert!(!target.path().join("game/music/lone.acm").exists());
+    assert!(!target.path().join("start.sh").exists());
+    assert!(!target.path().join("support").exists());
+    assert!(!target.path().join("game/chitin.key").exists());
+    assert!(!target.path().join("game/data").exists());
+    assert!(!target.path().join("game/lang").exists());
+}
+
+#[test]
+fn empty_chitin_key() {
+    let source = fake_source();
+    write_file(&source.path().join("game/chitin.key"), "");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        fail_on_empty: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("chitin.key is empty"), "{}", error);
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    logged();
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        dry_run: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+    // each action is shown without -v
+    let records = logged();
+    let has = |line: String| records.iter().any(|(_, message)| message.starts_with(&line));
+    let (source, target) = (source.path().to_string_lossy(), target.path().to_string_lossy());
+    assert!(has(format!("copy {}/game/chitin.key to {}/game/chitin.key", source, target)));
+    assert!(has(format!("link {}/game/data/Default.bif to {}/game/data/Default.bif", source, target)));
+    assert!(has(format!("create directory {}/game/data", target)));
+    assert!(has(String::from("8 to copy, 13 to link, 12 dir(s) to create")));
+}
+
+#[test]
+fn plan_as_json() {
+    let actions = vec![
+        Action {
+            kind: ActionKind::Copy,
+            source: Some(PathBuf::from("/gog/bg2ee/start.sh")),
+            target: PathBuf::from("/games/bg2ee/start.sh"),
+            size: None,
+        },
+        Action {
+            kind: ActionKind::Remove,
+            source: None,
+            target: PathBuf::from("/games/bg2ee/\"quoted\""),
+            size: None,
+        },
+    ];
+    assert_eq!(
+        plan::to_json(&actions),
+        "[\n  {\"action\": \"copy\", \"source\": \"/gog/bg2ee/start.sh\", \"target\": \"/games/bg2ee/start.sh\"},\n  \
+         {\"action\": \"remove\", \"source\": null, \"target\": \"/games/bg2ee/\\\"quoted\\\"\"}\n]"
+    );
+}
+
+#[test]
+fn stats_per_category() {
+    let source = fake_source();
+    let target = Path::new("/games/bg2ee");
+    let action = |kind, source_item: &str, target_item: &str| Action {
+        kind,
+        source: Some(source.path().join(source_item)),
+        target: target.join(target_item),
+        size: None,
+    };
+    let actions = vec![
+        action(ActionKind::Copy, "start.sh", "start.sh"),
+        action(ActionKind::Link, "support", "support"),
+        action(ActionKind::CreateDir, "game", "game"),
+        action(ActionKind::Copy, "game/engine.lua", "game/engine.lua"),
+        action(ActionKind::CreateDir, "game/data", "game/data"),
+        action(ActionKind::Link, "game/data/Default.bif", "game/data/Default.bif"),
+        action(ActionKind::Link, "game/data/AREA000A.bif", "game/data/AREA000A.bif"),
+    ];
+    let all_stats = stats::collect(&actions, target, None);
+    let categories: Vec<&str> = all_stats.keys().map(String::as_str).collect();
+    assert_eq!(categories, [".", "game", "game/data", "support"]);
+    assert_eq!(
+        all_stats["game"],
+        stats::Stats {
+            copied: 1,
+            linked: 0,
+            dirs: 1,
+            bytes: 6,
+            ..stats::Stats::default()
+        }
+    );
+    assert_eq!(all_stats["game/data"].linked, 2);
+    assert_eq!(all_stats["."].bytes, 10);
+    assert_eq!(stats::table(&all_stats).len(), 6);
+}
+
+#[test]
+fn timings() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        timings: true,
+        max_links: Some(100),
+        ..test_config(source.path(), target.path())
+    };
+    let total = run(config).unwrap();
+    let phases: Vec<&str> = total.phases.iter().map(|(phase, _)| phase.as_str()).collect();
+    assert_eq!(phases, ["scripts", "data", "lang", "movies", "music", "override"]);
+    assert!(total.phases.iter().all(|(_, duration)| *duration <= total.elapsed));
+    assert_eq!(stats::timings(&total).len(), 8);
+}
+
+#[test]
+fn unexpected_game_entries() {
+    let source = fake_source();
+    let game = source.path().join("game");
+    write_file(&game.join("EEex.dll"), "dll");
+    write_file(&game.join("EEex/loader.lua"), "lua");
+    write_file(&game.join("weidu_external/data.txt"), "weidu");
+    write_file(&game.join("notes.txt"), "notes");
+
+    // not classified: the files are linked, the dirs left out, or refused with --strict
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        strict: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("unexpected entries"), "{}", error);
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_link(&target.path().join("game/notes.txt")));
+    assert!(!target.path().join("game/EEex").exists());
+
+    let config_path = source.path().join("iedup.toml");
+    write_file(
+        &config_path,
+        "[rules]\n\"EEex*\" = \"copy\"\n\"game/weidu_external\" = \"link\"\n\"notes.txt\" = \"skip\"\n",
+    );
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        strict: true,
+        config_file: Some(config_path),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/EEex.dll")));
+    assert!(is_copy(&target.path().join("game/EEex/loader.lua")));
+    assert!(is_link(&target.path().join("game/weidu_external")));
+    assert!(!target.path().join("game/notes.txt").exists());
+}
+
+#[test]
+fn invalid_config() {
+    assert!(config::ConfigFile::parse("[rules]\n\"*.bif\" = \"move\"\n").is_err());
+    assert!(config::ConfigFile::parse("[unknown]\n").is_err());
+    let config = config::ConfigFile::parse("[rules]\n\"*.bif\" = \"link\"\n").unwrap();
+    assert_eq!(config.rule_for(Path::new("game/data/Default.bif")), Some(config::RuleAction::Link));
+    assert_eq!(config.rule_for(Path::new("game/chitin.key")), None);
+}
+
+#[test]
+fn command_line_rules() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        rules: vec![
+            String::from("*.mus=copy"),
+            String::from("engine.lua=link"),
+            String::from("game/movies/lo=skip"),
+        ],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/music/bg1.mus")));
+    assert!(is_link(&target.path().join("game/music/lone.acm")));
+    assert!(is_link(&target.path().join("game/engine.lua")));
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+    assert!(!target.path().join("game/movies/lo").exists());
+
+    let parse = |rules: &[&str]| config::parse_rule_args(&rules.iter().map(|rule| rule.to_string()).collect::<Vec<_>>());
+    assert!(parse(&["*.bif=link", "*.bif=link"]).is_ok());
+    assert!(parse(&["*.bif=link", "*.bif=copy"]).is_err());
+    assert!(parse(&["*.bif=move"]).is_err());
+    assert!(parse(&["*.bif"]).is_err());
+    assert!(parse(&["=copy"]).is_err());
+}
+
+#[test]
+fn other_movie_dirs() {
+    let source = fake_source();
+    write_file(&source.path().join("game/movies/720/intro.wbm"), "movie");
+    fs::remove_dir_all(source.path().join("game/movies/lo")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_link(&target.path().join("game/movies/720/intro.wbm")));
+    assert!(is_link(&target.path().join("game/movies/480/intro.wbm")));
+    assert!(!target.path().join("game/movies/lo").exists());
+}
+
+#[test]
+fn verifies_copies() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        verify_copies: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+    assert!(is_copy(&target.path().join("start.sh")));
+
+    let game = target.path().join("game");
+    check_copy(&source.path().join("game/engine.lua"), &game.join("engine.lua")).unwrap();
+    write_file(&game.join("engine.lua"), "engin");
+    assert!(check_copy(&source.path().join("game/engine.lua"), &game.join("engine.lua")).is_err());
+}
+
+#[test]
+fn prunes_stale_links() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    fs::remove_file(source.path().join("game/data/AREA000A.bif")).unwrap();
+    fs::remove_file(source.path().join("game/engine.lua")).unwrap();
+    let data = target.path().join("game/data");
+    std::os::unix::fs::symlink("/nowhere/mod.bif", data.join("mod.bif")).unwrap();
+    write_file(&data.join("local.bif"), "mod");
+
+    let verify = Config {
+        verify: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(verify.clone()).unwrap();
+    assert!(data.join("AREA000A.bif").symlink_metadata().is_ok());
+    let prune = Config { prune: true, ..verify };
+    run(prune).unwrap();
+    assert!(data.join("AREA000A.bif").symlink_metadata().is_err());
+    // only the links into the source are pruned
+    assert!(data.join("mod.bif").symlink_metadata().is_ok());
+    assert!(data.join("local.bif").is_file());
+    assert!(is_copy(&target.path().join("game/engine.lua")));
+    assert!(is_link(&data.join("Default.bif")));
+}
+
+#[test]
+fn copy_all() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_all: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("support/xdg-open")));
+    assert!(is_copy(&target.path().join("game/data/Default.bif")));
+    assert!(is_copy(&target.path().join("game/Manuals/manual.pdf")));
+    assert!(is_copy(&target.path().join("game/music/bg1/bg1a.acm")));
+}
+
+#[test]
+fn deterministic_plan() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let names = ["b.bif", "z.bif", "a.bif", "m.bif", "c.bif"];
+    let data = source.path().join("game/data");
+    let plan = |name: &str| {
+        let script = scripts.path().join(name);
+        let config = Config {
+            emit_script: Some(Shell::Bash),
+            script_out: Some(script.clone()),
+            ..test_config(source.path(), target.path())
+        };
+        run(config).unwrap();
+        fs::read(script).unwrap()
+    };
+    for name in &names {
+        write_file(&data.join(name), "bif");
+    }
+    let first = plan("first.sh");
+    // the same files, created in the other order
+    for name in &names {
+        fs::remove_file(data.join(name)).unwrap();
+    }
+    for name in names.iter().rev() {
+        write_file(&data.join(name), "bif");
+    }
+    assert_eq!(first, plan("second.sh"));
+}
+
+#[test]
+fn refreshes_since() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let old = FileTime::from_unix_time(1_600_000_000, 0);
+    filetime::set_file_mtime(source.path().join("start.sh"), old).unwrap();
+    write_file(&target.path().join("start.sh"), "#!/bin/sh\n# modified\n");
+    write_file(&source.path().join("game/engine.lua"), "updated");
+    write_file(&target.path().join("game/engine.lua"), "modified");
+
+    let config = Config {
+        merge: true,
+        since: Some(timestamp::parse("2021-01-01T00:00:00Z").unwrap()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_to_string(target.path().join("game/engine.lua")).unwrap(), "updated");
+    assert!(fs::read_to_string(target.path().join("start.sh")).unwrap().contains("modified"));
+}
+
+#[test]
+fn backs_up_refreshed_copies() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let engine = target.path().join("game/engine.lua");
+    let config = Config {
+        merge: true,
+        since: Some(timestamp::parse("2021-01-01T00:00:00Z").unwrap()),
+        backup: true,
+        ..test_config(source.path(), target.path())
+    };
+    for content in &["first change", "second change"] {
+        write_file(&engine, content);
+        run(config.clone()).unwrap();
+    }
+    assert_eq!(fs::read_to_string(&engine).unwrap(), "engine");
+    let backup = |name: &str| fs::read_to_string(target.path().join("game").join(name)).unwrap();
+    assert_eq!(backup("engine.lua.iedup.bak"), "first change");
+    assert_eq!(backup("engine.lua.iedup.bak.2"), "second change");
+}
+
+#[test]
+fn records_the_source() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let recorded = recorded_source(target.path()).unwrap();
+    assert_eq!(recorded, source.path().canonicalize().unwrap());
+    // not a local addition
+    run(Config {
+        verify: true,
+        strict: true,
+        ..test_config(&recorded, target.path())
+    })
+    .unwrap();
+    fs::remove_dir_all(source.path().join("game")).unwrap();
+    let error = recorded_source(target.path()).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::InvalidSource));
+    let empty = tempfile::tempdir().unwrap();
+    let error = recorded_source(empty.path()).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Usage));
+}
+
+#[test]
+fn flattens_the_game_dir() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        flatten: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config.clone()).unwrap();
+    assert!(is_copy(&target.path().join("chitin.key")));
+    assert!(is_copy(&target.path().join("start.sh")));
+    assert!(is_link(&target.path().join("support")));
+    assert!(!target.path().join("game").exists());
+    // the entries of the root and of game/ aren't local additions
+    run(Config {
+        verify: true,
+        strict: true,
+        ..config
+    })
+    .unwrap();
+    let windows = fake_windows_source();
+    let error = run(Config {
+        flatten: true,
+        ..test_config(windows.path(), tempfile::tempdir().unwrap().path())
+    })
+    .unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Usage));
+}
+
+#[test]
+fn merge_copies_truncated_files_again() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let engine = target.path().join("game/engine.lua");
+    let start = target.path().join("start.sh");
+    write_file(&engine, "eng");
+    // changed locally, not the start of the source
+    write_file(&start, "exec");
+    write_file(&target.path().join("game/lang/en_US/dialog.tlk"), "");
+    run(Config {
+        merge: true,
+        ..test_config(source.path(), target.path())
+    })
+    .unwrap();
+    assert_eq!(fs::read_to_string(&engine).unwrap(), "engine");
+    assert_eq!(fs::read_to_string(target.path().join("game/lang/en_US/dialog.tlk")).unwrap(), "tlk");
+    assert_eq!(fs::read_to_string(&start).unwrap(), "exec");
+}
+
+#[test]
+fn merge_links_over_empty_dirs() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let manuals = target.path().join("game/Manuals");
+    fs::remove_file(&manuals).unwrap();
+    fs::create_dir(&manuals).unwrap();
+    // not empty, may be something else than a left over
+    let support = target.path().join("support");
+    fs::remove_file(&support).unwrap();
+    write_file(&support.join("notes.txt"), "notes");
+    let config = Config {
+        merge: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config.clone()).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Mismatch));
+    assert!(is_link(&manuals));
+    assert!(support.join("notes.txt").is_file());
+}
+
+#[test]
+fn lowercases_the_names() {
+    let source = fake_source();
+    write_file(&source.path().join("game/override/SPWI101.SPL"), "spl");
+    write_file(&source.path().join("game/movies/BGLogo.wbm"), "movie");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        lowercase_names: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config.clone()).unwrap();
+    assert!(target.path().join("game/override/spwi101.spl").is_file());
+    assert!(!target.path().join("game/override/SPWI101.SPL").exists());
+    let movie = target.path().join("game/movies/bglogo.wbm");
+    assert!(is_link(&movie));
+    assert_eq!(fs::read_link(&movie).unwrap(), source.path().join("game/movies/BGLogo.wbm"));
+    assert!(is_link(&target.path().join("game/manuals")));
+    // the renamed entries aren't local additions
+    run(Config {
+        verify: true,
+        strict: true,
+        ..config.clone()
+    })
+    .unwrap();
+
+    write_file(&source.path().join("game/override/spwi101.spl"), "other spl");
+    let error = run(Config {
+        target: tempfile::tempdir().unwrap().path().to_path_buf(),
+        ..config
+    })
+    .unwrap_err();
+    assert!(format!("{:#}", error).contains("would both be"));
+}
+
+#[test]
+fn music_by_extension() {
+    let source = fake_source();
+    write_file(&source.path().join("game/music/bg1/bg1b.ogg"), "ogg");
+    write_file(&source.path().join("game/music/readme.txt"), "readme");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let music = target.path().join("game/music");
+    assert!(music.join("bg1").is_dir() && !is_link(&music.join("bg1")));
+    assert!(is_link(&music.join("bg1/bg1b.ogg")));
+    assert!(is_link(&music.join("bg1/bg1a.acm")));
+    assert!(is_copy(&music.join("bg1.mus")));
+    assert!(is_link(&music.join("readme.txt")));
+
+    let config = config::ConfigFile::parse("[music]\nogg = \"copy\"\n").unwrap();
+    assert_eq!(config.music, vec![(String::from("ogg"), config::RuleAction::Copy)]);
+    assert!(config::ConfigFile::parse("[music]\nogg = \"move\"\n").is_err());
+}
+
+#[test]
+fn parses_timestamps() {
+    let at = |seconds: u64| std::time::UNIX_EPOCH + Duration::from_secs(seconds);
+    assert_eq!(timestamp::parse("@1600000000").unwrap(), at(1_600_000_000));
+    assert_eq!(timestamp::parse("2020-09-13T12:26:40Z").unwrap(), at(1_600_000_000));
+    assert_eq!(timestamp::parse("2020-09-13T14:26:40.5+02:00").unwrap(), at(1_600_000_000));
+    assert_eq!(timestamp::parse("2000-02-29T00:00:00Z").unwrap(), at(951_782_400));
+    assert!(timestamp::parse("2020-13-01T00:00:00Z").is_err());
+    assert!(timestamp::parse("2020-09-13 12:26").is_err());
+    assert!(timestamp::parse("@soon").is_err());
+}
+
+#[test]
+fn beamdog_install() {
+    let source = fake_beamdog_source("BaldursGate");
+    let description = detect(source.path()).unwrap();
+    assert_eq!((description.os, description.vendor), (Os::Linux, Vendor::Beamdog));
+    assert_eq!(description.game, Game::Bg1Ee);
+    assert_eq!(description.build.as_deref(), Some("8216"));
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_copy(&target.path().join("engine.lua")));
+    assert!(is_link(&target.path().join("data/Default.bif")));
+    assert!(is_link(&target.path().join("BaldursGate")));
+
+    let source = fake_beamdog_source("Baldur.exe");
+    let description = detect(source.path()).unwrap();
+    assert_eq!((description.os, description.vendor), (Os::Win, Vendor::Beamdog));
+    assert!(description.manifest.is_none());
+}
+
+#[test]
+fn ignores_junk_files_in_the_target() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&target.path().join(".DS_Store"), "junk");
+    let strict = Config {
+        strict_empty: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(strict).unwrap_err();
+    assert!(error.to_string().contains("not empty"), "{}", error);
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_copy(&target.path().join("start.sh")));
+    assert!(target.path().join(".DS_Store").is_file());
+}
+
+#[test]
+fn only_one_section() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let music = target.path().join("game/music");
+    fs::remove_dir_all(&music).unwrap();
+    let only_music = Config {
+        only: Some(Section::Music),
+        ..test_config(source.path(), target.path())
+    };
+    run(only_music.clone()).unwrap();
+    assert!(is_copy(&music.join("bg1.mus")));
+    assert!(is_link(&music.join("bg1/bg1a.acm")));
+
+    // the section must be new, unless forced
+    write_file(&music.join("damaged.mus"), "mus");
+    assert!(run(only_music.clone()).is_err());
+    run(Config { force: true, ..only_music }).unwrap();
+    assert!(!music.join("damaged.mus").exists());
+    assert!(is_copy(&music.join("bg1.mus")));
+
+    // in a new target, only the section is built
+    let target = tempfile::tempdir().unwrap();
+    let only_override = Config {
+        only: Some(Section::Override),
+        ..test_config(source.path(), target.path())
+    };
+    run(only_override).unwrap();
+    assert!(is_copy(&target.path().join("game/override/sw1h01.itm")));
+    assert!(!target.path().join("game/data").exists());
+    assert!(!target.path().join("start.sh").exists());
+
+    assert!("sound".parse::<Section>().is_err());
+}
+
+#[test]
+fn sets_permissions() {
+    use std::os::unix::fs::MetadataExt;
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let uid = target.path().metadata().unwrap().uid();
+    let config = Config {
+        chmod: Some(permissions::parse_mode("640").unwrap()),
+        chown: Some(uid.to_string()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let mode = |path: &Path| path.metadata().unwrap().permissions().mode() & 0o7777;
+    assert_eq!(mode(&target.path().join("start.sh")), 0o640);
+    assert_eq!(mode(&target.path().join("game/engine.lua")), 0o640);
+    assert_eq!(mode(&target.path().join("game/data")), 0o750);
+    assert_eq!(target.path().join("start.sh").metadata().unwrap().uid(), uid);
+
+    assert!(permissions::parse_mode("9").is_err());
+    assert_eq!(
+        permissions::Owner::parse("0:0").unwrap(),
+        permissions::Owner { uid: Some(0), gid: Some(0) }
+    );
+    assert_eq!(permissions::Owner::parse("root").unwrap().uid, Some(0));
+    assert!(permissions::Owner::parse(":").is_err());
+}
+
+#[test]
+fn continues_on_error() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    // a file where dirs should be
+    for dir in &["game/music", "game/movies"] {
+        fs::remove_dir_all(target.path().join(dir)).unwrap();
+        write_file(&target.path().join(dir), "in the way");
+    }
+    // after music/ and movies/
+    fs::remove_file(target.path().join("game/override/sw1h01.itm")).unwrap();
+    let merge = Config {
+        merge: true,
+        ..test_config(source.path(), target.path())
+    };
+    assert!(run(merge.clone()).is_err());
+    assert!(!target.path().join("game/override/sw1h01.itm").exists());
+
+    let error = run(Config {
+        continue_on_error: true,
+        ..merge
+    })
+    .unwrap_err();
+    assert!(error.to_string().contains("2 item(s) failed"), "{}", error);
+    assert_eq!(error.kind(), Some(FailureKind::Partial));
+    assert!(is_copy(&target.path().join("game/override/sw1h01.itm")));
+}
+
+#[test]
+fn writes_a_json_report() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let reports = tempfile::tempdir().unwrap();
+    let report = reports.path().join("report.json");
+    run(Config {
+        report_json: Some(report.clone()),
+        ..test_config(source.path(), target.path())
+    })
+    .unwrap();
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.starts_with("{\n  \"error\": null,"), "{}", json);
+    assert!(json.contains("\"version\": \"2.6.6.0\""), "{}", json);
+    assert!(
+        json.contains("\"game/data\": {\"copied\": 0, \"linked\": 2, \"dirs\": 1, \"copied_bytes\": 0, \"linked_bytes\": 6}"),
+        "{}",
+        json
+    );
+
+    // written for a partial failure too
+    fs::remove_dir_all(target.path().join("game/music")).unwrap();
+    write_file(&target.path().join("game/music"), "in the way");
+    assert!(run(Config {
+        merge: true,
+        continue_on_error: true,
+        report_json: Some(report.clone()),
+        ..test_config(source.path(), target.path())
+    })
+    .is_err());
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains("\"error\": \"1 item(s) failed"), "{}", json);
+    assert!(json.contains("\"errors\": [\""), "{}", json);
+}
+
+#[test]
+fn handles_unusual_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dirs = tempfile::tempdir().unwrap();
+    let fake = fake_source();
+    let source = dirs.path().join("Baldur's Gate II [Édition améliorée]");
+    fs::rename(fake.path(), &source).unwrap();
+    // not valid UTF-8
+    let dlc = OsStr::from_bytes(b"caf\xe9-dlc.zip");
+    let item = OsStr::from_bytes(b"caf\xe9.itm");
+    write_file(&source.join(dlc), "dlc");
+    write_file(&source.join("game/override").join(item), "itm");
+    let target = dirs.path().join("BG2 modé");
+    fs::create_dir(&target).unwrap();
+    run(test_config(&source, &target)).unwrap();
+    assert!(is_link(&target.join("sod-dlc.zip")));
+    assert!(is_link(&target.join(dlc)));
+    assert!(is_copy(&target.join("game/override").join(item)));
+    assert_eq!(
+        fs::read_link(target.join("game/music/lone.acm")).unwrap(),
+        source.join("game/music/lone.acm")
+    );
+}
+
+#[test]
+fn follows_symlinked_game_dirs() {
+    let source = fake_source();
+    let elsewhere = tempfile::tempdir().unwrap();
+    // game/ on another drive, and lang/ somewhere else again
+    let game = elsewhere.path().join("game");
+    fs::rename(source.path().join("game"), &game).unwrap();
+    std::os::unix::fs::symlink(&game, source.path().join("game")).unwrap();
+    fs::rename(game.join("lang"), elsewhere.path().join("lang")).unwrap();
+    std::os::unix::fs::symlink(elsewhere.path().join("lang"), game.join("lang")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let game = target.path().join("game");
+    assert!(game.symlink_metadata().unwrap().is_dir());
+    assert!(is_copy(&game.join("chitin.key")));
+    assert!(is_link(&game.join("data/Default.bif")));
+    assert!(game.join("lang").symlink_metadata().unwrap().is_dir());
+    assert!(is_copy(&game.join("lang/en_US/dialog.tlk")));
+    assert!(is_link(&game.join("lang/en_US/sounds/ab.wav")));
+
+    let config = Config {
+        verify: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+}
+
+#[test]
+fn skips_what_is_not_a_language() {
+    let source = fake_source();
+    let lang = source.path().join("game/lang");
+    write_file(&lang.join("backup/dialog.tlk"), "tlk");
+    write_file(&lang.join("readme.txt"), "readme");
+    write_file(&lang.join("xx_YY/dialog.tlk"), "tlk");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let lang = target.path().join("game/lang");
+    assert!(is_copy(&lang.join("en_US/dialog.tlk")));
+    assert!(is_copy(&lang.join("xx_YY/dialog.tlk")));
+    assert!(!lang.join("backup").exists());
+    assert!(!lang.join("readme.txt").exists());
+    assert!(is_locale("pt_BR") && !is_locale("backup") && !is_locale("en_US.old"));
+}
+
+#[test]
+fn probes_symlink_support() {
+    let target = tempfile::tempdir().unwrap();
+    assert!(supports_symlinks(target.path()).unwrap());
+    // the probe is removed
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+    assert!(supports_symlinks(&target.path().join("missing")).is_err());
+}
+
+#[test]
+fn writes_a_manifest() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        write_manifest: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let manifest = fs::read_to_string(target.path().join(MANIFEST_FILE)).unwrap();
+    assert!(manifest.starts_with(
+        "{\n  \"game\": {\"id\": \"BG1EE\", \"name\": \"Baldur's Gate II: Enhanced Edition\", \"version\": \"2.6.6.0\", \"build\": \"27023\"},"
+    ));
+    let start_sh = format!(
+        "{{\"path\": \"start.sh\", \"type\": \"copy\", \"source\": \"{}\", \"size\": 10}}",
+        source.path().join("start.sh").to_string_lossy()
+    );
+    assert!(manifest.contains(&start_sh));
+    assert!(manifest.contains("{\"path\": \"game/data/Default.bif\", \"type\": \"link\""));
+    assert!(manifest.find("\"path\": \"game\"").unwrap() < manifest.find("\"path\": \"support\"").unwrap());
+    // the manifest isn't a local addition
+    let config = Config {
+        verify: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+}
+
+#[test]
+fn dedups_copies() {
+    let source = fake_source();
+    write_file(&source.path().join("game/override/sw1h02.itm"), "itm");
+    write_file(&source.path().join("game/override/sw1h03.itm"), "other");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        dedup_copies: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let override_dir = target.path().join("game/override");
+    let inode = |path: &Path| std::os::unix::fs::MetadataExt::ino(&path.metadata().unwrap());
+    assert_eq!(inode(&override_dir.join("sw1h01.itm")), inode(&override_dir.join("sw1h02.itm")));
+    assert_ne!(inode(&override_dir.join("sw1h01.itm")), inode(&override_dir.join("sw1h03.itm")));
+    assert_eq!(fs::read_to_string(override_dir.join("sw1h03.itm")).unwrap(), "other");
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+}
+
+#[test]
+fn finds_the_mount() {
+    let mounts = "/dev/sda1 / ext4 rw,relatime 0 0\n\
+                  /dev/sr0 /run/media/user/BG2\\040CD1 iso9660 ro,nosuid,nodev 0 0\n\
+                  tmpfs /tmp tmpfs rw 0 0\n";
+    let mount = mounts::find_mount(mounts, Path::new("/run/media/user/BG2 CD1/game")).unwrap();
+    assert_eq!(mount.mount_point, Path::new("/run/media/user/BG2 CD1"));
+    assert!(mount.read_only && mount.is_removable());
+    let mount = mounts::find_mount(mounts, Path::new("/home/user/games")).unwrap();
+    assert_eq!(mount.mount_point, Path::new("/"));
+    assert!(!mount.read_only && !mount.is_removable());
+}
+
+#[test]
+fn plan_as_script() {
+    let actions = vec![
+        Action {
+            kind: ActionKind::CreateDir,
+            source: Some(PathBuf::from("/gog/bg2ee/game")),
+            target: PathBuf::from("/games/Baldur's Gate/game"),
+            size: None,
+        },
+        Action {
+            kind: ActionKind::Link,
+            source: Some(PathBuf::from("/gog/bg2ee/game/data/Default.bif")),
+            target: PathBuf::from("/games/Baldur's Gate/game/data/Default.bif"),
+            size: None,
+        },
+    ];
+    assert_eq!(
+        plan::to_script(&actions, Shell::Bash, "cp -p"),
+        "#!/bin/bash\nset -e\nmkdir '/games/Baldur'\\''s Gate/game'\n\
+         ln -s '/gog/bg2ee/game/data/Default.bif' '/games/Baldur'\\''s Gate/game/data/Default.bif'\n"
+    );
+    assert_eq!(
+        plan::to_script(&actions, Shell::PowerShell, "cp -p"),
+        "$ErrorActionPreference = \"Stop\"\nNew-Item -ItemType Directory -Path '/games/Baldur''s Gate/game' | Out-Null\n\
+         New-Item -ItemType SymbolicLink -Path '/games/Baldur''s Gate/game/data/Default.bif' -Target '/gog/bg2ee/game/data/Default.bif' | Out-Null\n"
+    );
+}
+
+#[test]
+fn emits_a_script() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let script_dir = tempfile::tempdir().unwrap();
+    let script = script_dir.path().join("iedup.sh");
+    let config = Config {
+        emit_script: Some(Shell::Bash),
+        script_out: Some(script.clone()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+    let script = fs::read_to_string(script).unwrap();
+    assert!(script.contains(&format!("cp -p '{}/start.sh'", source.path().to_string_lossy())));
+    assert!(script.contains(&format!("mkdir '{}/game'", target.path().to_string_lossy())));
+    // without script_out, the caller gets it
+    let output = Arc::new(std::sync::Mutex::new(String::new()));
+    let written = output.clone();
+    let config = Config {
+        emit_script: Some(Shell::Bash),
+        plan_output: Some(Arc::new(move |text: &str| written.lock().unwrap().push_str(text))),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(*output.lock().unwrap(), script);
+}
+
+#[test]
+fn music_root_files() {
+    let source = fake_source();
+    write_file(&source.path().join("game/music/readme.txt"), "txt");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let music = target.path().join("game/music");
+    assert!(is_copy(&music.join("bg1.mus")));
+    assert!(is_link(&music.join("lone.acm")));
+    assert!(is_link(&music.join("readme.txt")));
+}
+
+#[test]
+fn verbosity_levels() {
+    let source = fake_source();
+    for (verbose, quiet) in &[(3, false), (0, true)] {
+        let target = tempfile::tempdir().unwrap();
+        let config = Config {
+            verbose: *verbose,
+            quiet: *quiet,
+            ..test_config(source.path(), target.path())
+        };
+        run(config).unwrap();
+        assert!(is_link(&target.path().join("game/data/Default.bif")));
+    }
+}
+
+#[test]
+fn links_into_the_link_base() {
+    let source = fake_source();
+    let link_base = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        link_base: Some(link_base.path().to_path_buf()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let link = fs::read_link(target.path().join("game/data/Default.bif")).unwrap();
+    assert_eq!(link, link_base.path().join("game/data/Default.bif"));
+    assert!(is_copy(&target.path().join("start.sh")));
+
+    // the link base must have everything that's linked
+    fs::remove_file(link_base.path().join("game/data/AREA000A.bif")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        link_base: Some(link_base.path().to_path_buf()),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("isn't in the link base"), "{}", error);
+}
+
+#[test]
+fn refuses_non_empty_target() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&target.path().join("something"), "");
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::TargetNotEmpty));
+}
+
+#[test]
+fn refuses_invalid_source() {
+    let source = tempfile::tempdir().unwrap();
+    write_file(&source.path().join("readme.txt"), "not a game");
+    let target = tempfile::tempdir().unwrap();
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    // the kind is found under the error of the detection
+    assert_eq!(error.kind(), Some(FailureKind::InvalidSource));
+    let error = run(test_config(&source.path().join("missing"), target.path())).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::InvalidSource));
+}
+
+#[test]
+fn force_cleans_previous_output() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    write_file(&target.path().join("game/override/local.itm"), "");
+    let config = Config {
+        force: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(!target.path().join("game/override/local.itm").exists());
+    // the links were removed, not what they point to
+    assert!(source.path().join("support/xdg-open").exists());
+}
+
+#[test]
+fn force_refuses_unknown_target() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&target.path().join("precious"), "");
+    let config = Config {
+        force: true,
+        ..test_config(source.path(), target.path())
+    };
+    assert!(run(config).is_err());
+    assert!(target.path().join("precious").exists());
+}
+
+#[test]
+fn builds_into_a_subdir() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    write_file(&target.path().join("other/start.sh"), "#!/bin/sh");
+    let config = Config {
+        into: Some("bg2ee".to_string()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config.clone()).unwrap();
+    assert!(is_copy(&target.path().join("bg2ee/start.sh")));
+    assert!(is_link(&target.path().join("bg2ee/support")));
+    assert!(target.path().join("other/start.sh").is_file());
+
+    // the subdir must be new
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("already exists"), "{}", error);
+    let config = Config {
+        into: Some("../escape".to_string()),
+        ..test_config(source.path(), target.path())
+    };
+    assert!(run(config).is_err());
+}
+
+#[test]
+fn override_modes() {
+    let source = fake_source();
+    write_file(&source.path().join("game/lang/en_US/override/bdai.bs"), "script");
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        override_mode: Some(OverrideMode::Link),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.path().join("game/override/sw1h01.itm")));
+    assert!(is_link(&target.path().join("game/lang/en_US/override/bdai.bs")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        override_mode: Some(OverrideMode::LinkDir),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.path().join("game/override")));
+    assert!(is_link(&target.path().join("game/lang/en_US/override")));
+}
+
+#[test]
+fn nested_override_dirs() {
+    let source = fake_source();
+    write_file(&source.path().join("game/override/subdir/foo.itm"), "itm");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(target.path().join("game/override/subdir").is_dir());
+    assert!(is_copy(&target.path().join("game/override/subdir/foo.itm")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        override_mode: Some(OverrideMode::Link),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(!is_link(&target.path().join("game/override/subdir")));
+    assert!(is_link(&target.path().join("game/override/subdir/foo.itm")));
+}
+
+#[test]
+fn profiles() {
+    let profiles = profiles::parse(
+        "[profiles.mine]\ndescription = \"mine\"\noverride_mode = \"link\"\nexclude = [\"game/movies\"]\n\n\
+         [profiles.mine.rules]\n\"EEex.dll\" = \"copy\"\n",
+    )
+    .unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].exclude, ["game/movies"]);
+    assert!(profiles[0].override_mode == Some(OverrideMode::Link));
+    assert_eq!(profiles[0].rules.len(), 1);
+    assert!(profiles::parse("[profiles.mine]\noverride = \"link\"\n").is_err());
+    assert!(profiles::find("unknown").is_err());
+
+    let source = fake_source();
+    write_file(&source.path().join("game/EEex.dll"), "dll");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        profile: Some(String::from("modding")),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/EEex.dll")));
+
+    // --override-mode wins over the profile
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        profile: Some(String::from("play")),
+        override_mode: Some(OverrideMode::Link),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.path().join("game/override/sw1h01.itm")));
+    assert!(!is_link(&target.path().join("game/override")));
+}
+
+#[test]
+fn windows_install() {
+    let source = fake_windows_source();
+    let hint = guess_structure(source.path());
+    assert!(hint.os == Os::Win && hint.vendor == Vendor::Gog);
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let root = target.path();
+    assert!(!root.join("start.sh").exists());
+    assert!(is_link(&root.join("Baldur.exe")));
+    assert!(is_link(&root.join("SDL2.dll")));
+    assert!(is_copy(&root.join("goggame-1456460669.info")));
+    assert!(is_copy(&root.join("Baldur.ini")));
+    assert!(is_copy(&root.join("chitin.key")));
+    assert!(is_link(&root.join("data/Default.bif")));
+}
+
+#[test]
+fn selected_languages() {
+    let source = fake_source();
+    write_file(&source.path().join("game/lang/fr_FR/dialog.tlk"), "tlk");
+    write_file(&source.path().join("game/lang/de_DE/dialog.tlk"), "tlk");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        languages: vec![String::from("en_US"), String::from("it_IT")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let languages: Vec<_> = fs::read_dir(target.path().join("game/lang"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(languages, ["en_US"]);
+    assert!(is_copy(&target.path().join("game/lang/en_US/dialog.tlk")));
+}
+
+#[test]
+fn reports_dangling_links() {
+    let source = fake_source();
+    std::os::unix::fs::symlink(source.path().join("missing.bif"), source.path().join("game/data/Broken.bif")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_link(&target.path().join("game/data/Broken.bif")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        fail_on_dangling: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("1 dangling link(s)"), "{}", error);
+}
+
+#[test]
+fn limits_the_depth() {
+    let source = fake_source();
+    let mut deep = source.path().join("game/override");
+    for level in 0..40 {
+        deep = deep.join(format!("level{}", level));
+    }
+    write_file(&deep.join("deep.itm"), "itm");
+    let target = tempfile::tempdir().unwrap();
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    assert!(error.to_string().contains("more than --max-depth 32"), "{}", error);
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        max_depth: 64,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join(deep.strip_prefix(source.path()).unwrap()).join("deep.itm")));
+}
+
+#[test]
+fn copies_support_when_asked() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_support: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(target.path().join("support").is_dir() && !is_link(&target.path().join("support")));
+    assert!(is_copy(&target.path().join("support/xdg-open")));
+    assert!(is_link(&target.path().join("gameinfo")));
+
+    let config_path = source.path().join("iedup.toml");
+    write_file(&config_path, "[rules]\n\"gameinfo\" = \"copy\"\n");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        config_file: Some(config_path),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("gameinfo")));
+    assert!(is_link(&target.path().join("support")));
+}
+
+#[test]
+fn caps_the_links() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        max_links: Some(3),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("more than --max-links 3"));
+    assert_eq!(error.kind(), Some(FailureKind::Usage));
+    // nothing was done
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+
+    let config = Config {
+        max_links: Some(100),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+}
+
+#[test]
+fn checks_free_space() {
+    let target = tempfile::tempdir().unwrap();
+    assert!(mounts::available_space(target.path()).unwrap() > 0);
+    check_free_space(1024, 2048).unwrap();
+    let error = check_free_space(3 * 1024 * 1024, 1024 * 1024).unwrap_err();
+    assert!(error.to_string().contains("2.0 MiB more"), "{}", error);
+}
+
+#[test]
+fn interactive_with_yes_proceeds() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        interactive: true,
+        yes: true,
+        ..test_config(source.path(), target.path())
+    };
+    let stats = run(config).unwrap();
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+    // the planning pass isn't counted
+    assert_eq!(
+        stats.linked,
+        run(test_config(source.path(), tempfile::tempdir().unwrap().path())).unwrap().linked
+    );
+    // the answer is asked to the caller
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        interactive: true,
+        confirm: Some(Arc::new(|question: &str| question != "Proceed?")),
+        ..test_config(source.path(), target.path())
+    };
+    assert!(run(config).unwrap_err().to_string().contains("cancelled"));
+}
+
+#[test]
+fn links_dlc_archives() {
+    let source = fake_source();
+    write_file(&source.path().join("dlc/sod-dlc.zip"), "dlc");
+    write_file(&source.path().join("dlc/readme.txt"), "readme");
+    write_file(&source.path().join("siege.mod"), "mod");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_link(&target.path().join("sod-dlc.zip")));
+    assert!(is_link(&target.path().join("dlc/sod-dlc.zip")));
+    assert!(!target.path().join("dlc/readme.txt").exists());
+    assert!(!target.path().join("siege.mod").exists());
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        dlc_patterns: vec![String::from("*.mod"), String::from("*.txt")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.path().join("siege.mod")));
+    assert!(is_link(&target.path().join("dlc/readme.txt")));
+    assert!(!target.path().join("sod-dlc.zip").exists());
+}
+
+#[test]
+fn sanity_check_lists_what_is_missing() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        sanity_check: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let description = detect(source.path()).unwrap();
+    assert!(missing_for_launch(&description, source.path(), target.path(), false).unwrap().is_empty());
+
+    fs::remove_file(target.path().join("game/engine.lua")).unwrap();
+    fs::remove_file(target.path().join("game/lang/en_US/dialog.tlk")).unwrap();
+    // a broken link is missing too
+    fs::remove_file(target.path().join("start.sh")).unwrap();
+    std::os::unix::fs::symlink(source.path().join("nothing"), target.path().join("start.sh")).unwrap();
+    assert_eq!(
+        missing_for_launch(&description, source.path(), target.path(), false).unwrap(),
+        vec!["start.sh", "game/engine.lua", "game/lang/*/dialog.tlk"]
+    );
+
+    let (_library, install_dir) = fake_steam_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(&install_dir, target.path())).unwrap();
+    let description = detect(&install_dir).unwrap();
+    assert!(missing_for_launch(&description, &install_dir, target.path(), false).unwrap().is_empty());
+    fs::remove_file(target.path().join("BaldursGate")).unwrap();
+    assert_eq!(
+        missing_for_launch(&description, &install_dir, target.path(), false).unwrap(),
+        vec!["the game executable"]
+    );
+}
+
+#[test]
+fn size_thresholds() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_under: Some(4),
+        link_over: Some(5),
+        rules: vec![String::from("AREA*.bif=link")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let game = target.path().join("game");
+    // "bif" is 3 bytes, "engine" 6
+    assert!(is_copy(&game.join("data/Default.bif")));
+    assert!(is_link(&game.join("data/AREA000A.bif")));
+    assert!(is_link(&game.join("engine.lua")));
+    assert!(is_copy(&game.join("lang/en_US/dialog.tlk")));
+    assert!(is_link(&game.join("movies/intro.wbm")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_under: Some(10),
+        link_over: Some(5),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Usage));
+}
+
+#[test]
+fn hardlink_rules() {
+    use std::os::unix::fs::MetadataExt;
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        rules: vec![String::from("*.bif=hardlink"), String::from("*.wav=symlink")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let (source_bif, bif) = (source.path().join("game/data/Default.bif"), target.path().join("game/data/Default.bif"));
+    assert!(is_copy(&bif));
+    assert_eq!(bif.metadata().unwrap().ino(), source_bif.metadata().unwrap().ino());
+    assert!(bif.metadata().unwrap().nlink() > 1);
+    assert!(is_link(&target.path().join("game/lang/en_US/sounds/ab.wav")));
+
+    // the hard links are kept by a merge, found by a verify
+    let config = Config {
+        merge: true,
+        rules: vec![String::from("*.bif=hardlink")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let config = Config {
+        verify: true,
+        rules: vec![String::from("*.bif=hardlink")],
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+}
+
+#[test]
+fn puts_the_copies_under_the_writable_root() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let writable = tempfile::tempdir().unwrap();
+    let writable_root = writable.path().canonicalize().unwrap();
+    let config = Config {
+        writable_root: Some(writable.path().to_path_buf()),
+        ..test_config(source.path(), target.path())
+    };
+    let stats = run(config.clone()).unwrap();
+    let engine = target.path().join("game/engine.lua");
+    assert_eq!(fs::read_link(&engine).unwrap(), writable_root.join("game/engine.lua"));
+    assert!(is_copy(&writable_root.join("game/engine.lua")));
+    assert_eq!(fs::read_link(target.path().join("start.sh")).unwrap(), writable_root.join("start.sh"));
+    assert_eq!(
+        fs::read_link(target.path().join("game/data/Default.bif")).unwrap(),
+        source.path().join("game/data/Default.bif")
+    );
+    assert!(!writable_root.join("game/data").exists());
+    assert!(stats.copied > 0);
+    run(Config {
+        verify: true,
+        ..config.clone()
+    })
+    .unwrap();
+    // the links to the copies don't lead out of the source
+    let target = tempfile::tempdir().unwrap();
+    let writable = tempfile::tempdir().unwrap();
+    run(Config {
+        strict: true,
+        target: target.path().to_path_buf(),
+        writable_root: Some(writable.path().to_path_buf()),
+        ..config
+    })
+    .unwrap();
+
+    let error = run(Config {
+        merge: true,
+        writable_root: Some(target.path().join("game")),
+        ..test_config(source.path(), target.path())
+    })
+    .unwrap_err();
+    assert!(format!("{}", error).contains("overlaps the target"));
+}
+
+#[test]
+fn detects_the_vanished_source_files() {
+    let source = fake_source();
+    let failed = |original: PathBuf, kind: std::io::ErrorKind| {
+        AnyError::new(IedupError::CopyFailed {
+            path: PathBuf::from("/target/game/engine.lua"),
+            original,
+            source: std::io::Error::from(kind),
+        })
+    };
+    let gone = source.path().join("game/override/patch.itm");
+    assert_eq!(vanished_source(&failed(gone.clone(), std::io::ErrorKind::NotFound)), Some(gone.as_path()));
+    // still there, the error is something else
+    let engine = source.path().join("game/engine.lua");
+    assert_eq!(vanished_source(&failed(engine, std::io::ErrorKind::NotFound)), None);
+    assert_eq!(vanished_source(&failed(gone, std::io::ErrorKind::PermissionDenied)), None);
+    assert_eq!(vanished_source(&anyhow!("no chitin.key")), None);
+}
+
+#[test]
+fn extracts_the_dlc_archives() {
+    use std::io::Write;
+    let source = fake_source();
+    // the fake archive isn't one
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        extract_dlc: true,
+        ..test_config(source.path(), target.path())
+    };
+    assert!(format!("{:#}", run(config).unwrap_err()).contains("is not a zip archive"));
+
+    let mut zip = zip::ZipWriter::new(File::create(source.path().join("sod-dlc.zip")).unwrap());
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, content) in &[
+        ("data/sod.bif", "sod bif"),
+        ("data/Default.bif", "dlc bif"),
+        ("lang/en_US/sounds/sod.wav", "sod wav"),
+        ("lang/fr_FR/dialog.tlk", "sod tlk"),
+        ("override/sod.itm", "sod itm"),
+    ] {
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = |target: &Path| Config {
+        extract_dlc: true,
+        exclude: vec![String::from("game/lang/fr_FR")],
+        rules: vec![String::from("*.itm=skip"), String::from("sod.wav=link")],
+        ..test_config(source.path(), target)
+    };
+    // the dry run lists each file, as planned for an empty target
+    let output = Arc::new(std::sync::Mutex::new(String::new()));
+    let written = output.clone();
+    run(Config {
+        dry_run: true,
+        plan_format: Some(PlanFormat::Json),
+        plan_output: Some(Arc::new(move |text: &str| written.lock().unwrap().push_str(text))),
+        ..config(target.path())
+    })
+    .unwrap();
+    let plan = output.lock().unwrap().clone();
+    let game = target.path().join("game");
+    let extract = |entry: &str| {
+        format!(
+            "{{\"action\": \"extract\", \"source\": \"{}\", \"target\": \"{}\"}}",
+            source.path().join("sod-dlc.zip").join(entry).to_string_lossy(),
+            game.join(entry).to_string_lossy()
+        )
+    };
+    assert!(plan.contains(&extract("data/sod.bif")), "{}", plan);
+    assert!(plan.contains(&extract("lang/en_US/sounds/sod.wav")), "{}", plan);
+    assert!(!plan.contains("sod.itm") && !plan.contains("fr_FR/dialog.tlk"), "{}", plan);
+    let output = Arc::new(std::sync::Mutex::new(String::new()));
+    let written = output.clone();
+    run(Config {
+        emit_script: Some(Shell::Bash),
+        plan_output: Some(Arc::new(move |text: &str| written.lock().unwrap().push_str(text))),
+        ..config(target.path())
+    })
+    .unwrap();
+    let script = output.lock().unwrap().clone();
+    assert!(script.contains(&format!(
+        "unzip -p '{}' 'data/sod.bif' > '{}'",
+        source.path().join("sod-dlc.zip").to_string_lossy(),
+        game.join("data/sod.bif").to_string_lossy()
+    )));
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+
+    let build = Config {
+        write_manifest: true,
+        ..config(target.path())
+    };
+    run(build).unwrap();
+    assert!(!target.path().join("sod-dlc.zip").exists());
+    assert_eq!(fs::read_to_string(game.join("data/sod.bif")).unwrap(), "sod bif");
+    // the dlc wins, the source is left alone
+    assert!(is_copy(&game.join("data/Default.bif")));
+    assert_eq!(fs::read_to_string(game.join("data/Default.bif")).unwrap(), "dlc bif");
+    assert_eq!(fs::read_to_string(source.path().join("game/data/Default.bif")).unwrap(), "bif");
+    assert!(is_copy(&game.join("lang/en_US/sounds/sod.wav")));
+    assert!(is_link(&game.join("lang/en_US/sounds/ab.wav")));
+    assert!(!source.path().join("game/lang/en_US/sounds/sod.wav").exists());
+    assert!(!game.join("lang/fr_FR").exists());
+    assert!(!game.join("override/sod.itm").exists());
+    let manifest = fs::read_to_string(target.path().join(MANIFEST_FILE)).unwrap();
+    assert!(manifest.contains(&format!(
+        "{{\"path\": \"game/data/sod.bif\", \"type\": \"extract\", \"source\": \"{}\", \"size\": 7}}",
+        source.path().join("sod-dlc.zip/data/sod.bif").to_string_lossy()
+    )));
+    // nothing to change
+    let diff = Config {
+        diff: true,
+        fail_on_diff: true,
+        ..config(target.path())
+    };
+    run(diff).unwrap();
+
+    // the manifest extracts them again
+    let rebuilt = tempfile::tempdir().unwrap();
+    let config = Config {
+        from_manifest: Some(target.path().join(MANIFEST_FILE)),
+        ..test_config(source.path(), rebuilt.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_to_string(rebuilt.path().join("game/data/sod.bif")).unwrap(), "sod bif");
+}
+
+#[test]
+fn fails_up_front_when_the_target_is_not_writable() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    fs::set_permissions(target.path(), fs::Permissions::from_mode(0o555)).unwrap();
+    // root writes anyway
+    if fs::File::create(target.path().join("probe")).is_ok() {
+        return;
+    }
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    fs::set_permissions(target.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(matches!(error, IedupError::TargetNotWritable { .. }));
+    assert_eq!(error.kind(), Some(FailureKind::Permission));
+}
+
+/// Keeps the records logged by each test thread
+struct TestLogger {
+    records: std::sync::Mutex<Vec<(std::thread::ThreadId, log::Level, String)>>,
+}
+
+impl log::Log for TestLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let entry = (std::thread::current().id(), record.level(), record.args().to_string());
+        self.records.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+static TEST_LOGGER: Lazy<&'static TestLogger> = Lazy::new(|| {
+    let logger = Box::leak(Box::new(TestLogger {
+        records: std::sync::Mutex::new(Vec::new()),
+    }));
+    log::set_logger(logger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    logger
+});
+
+/// The records logged by this thread since the last call
+fn logged() -> Vec<(log::Level, String)> {
+    let mut records = TEST_LOGGER.records.lock().unwrap();
+    let thread = std::thread::current().id();
+    let (mine, others) = records.drain(..).partition(|(id, _, _)| *id == thread);
+    *records = others;
+    mine.into_iter().map(|(_, level, message)| (level, message)).collect()
+}
+
+#[test]
+fn logs_the_messages() {
+    logged();
+    let source = fake_source();
+    write_file(&source.path().join("game/lang/backup/dialog.tlk"), "tlk");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        verbose: 1,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let records = logged();
+    let has = |level: log::Level, start: &str| {
+        records
+            .iter()
+            .any(|(record_level, message)| *record_level == level && message.starts_with(start))
+    };
+    assert!(has(log::Level::Warn, "skip "));
+    assert!(has(log::Level::Debug, "copy "));
+    assert!(has(log::Level::Info, "detected "));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        quiet: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let records = logged();
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(records[0].1.ends_with(&target.path().to_string_lossy().into_owned()));
+}
+
+#[test]
+fn default_options() {
+    let mut config = test_config(Path::new("/gog/bg2ee"), Path::new("/games/bg2ee"));
+    config.exclude = vec![String::from("game/movies")];
+    let applied = defaults::apply(
+        "color = \"always\"\nverbose = 2\noverride_mode = \"link\"\nexclude = [\"game/lang/de_DE\"]\ncopy_all = true\n\
+         strict = false\n",
+        &mut config,
+        |name| name == "verbose",
+    )
+    .unwrap();
+    assert_eq!(applied, ["color", "copy_all", "exclude", "override_mode"]);
+    assert!(matches!(config.color, ColorChoice::Always));
+    // given on the command line
+    assert_eq!(config.verbose, 0);
+    assert!(config.override_mode == Some(OverrideMode::Link));
+    assert_eq!(config.exclude, ["game/lang/de_DE", "game/movies"]);
+    assert!(config.copy_all && !config.strict);
+    assert!(defaults::apply("colour = \"always\"\n", &mut config, |_| false).is_err());
+    assert!(defaults::apply("color = \"blue\"\n", &mut config, |_| false).is_err());
+}
+
+#[test]
+fn refuses_swapped_arguments() {
+    let source = fake_source();
+    let empty = tempfile::tempdir().unwrap();
+    let error = run(test_config(empty.path(), source.path())).unwrap_err();
+    assert!(error.to_string().contains("reversed"));
+    // nothing was touched
+    assert!(is_copy(&source.path().join("start.sh")));
+}
+
+#[test]
+fn refuses_overlapping_directories() {
+    let source = fake_source();
+    assert!(run(test_config(source.path(), source.path())).is_err());
+    let nested = source.path().join("game/nested");
+    fs::create_dir(&nested).unwrap();
+    assert!(run(test_config(source.path(), &nested)).is_err());
+    assert!(run(test_config(&source.path().join("game"), source.path())).is_err());
+}
+
+#[test]
+fn merge_keeps_local_additions() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    // a mod added a file in override/ and changed a copied one
+    write_file(&target.path().join("game/override/mod.itm"), "mod");
+    write_file(&target.path().join("game/override/sw1h01.itm"), "modded");
+    fs::remove_file(target.path().join("game/data/Default.bif")).unwrap();
+
+    let config = Config {
+        merge: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_to_string(target.path().join("game/override/mod.itm")).unwrap(), "mod");
+    assert_eq!(fs::read_to_string(target.path().join("game/override/sw1h01.itm")).unwrap(), "modded");
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+
+    let config = Config {
+        verify: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+}
+
+#[test]
+fn merge_with_existing_links() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    // a link made by hand to the same file, and one to another file
+    let data = target.path().join("game/data");
+    fs::remove_file(data.join("Default.bif")).unwrap();
+    let relative = PathBuf::from("../../../..")
+        .join(source.path().strip_prefix("/").unwrap())
+        .join("game/data/Default.bif");
+    std::os::unix::fs::symlink(&relative, data.join("Default.bif")).unwrap();
+    fs::remove_file(data.join("AREA000A.bif")).unwrap();
+    std::os::unix::fs::symlink(source.path().join("game/data/Default.bif"), data.join("AREA000A.bif")).unwrap();
+
+    let config = Config {
+        merge: true,
+        ..test_config(source.path(), target.path())
+    };
+    assert!(run(config).is_err());
+    assert_eq!(fs::read_link(data.join("Default.bif")).unwrap(), relative);
+
+    let config = Config {
+        merge: true,
+        follow_existing: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_link(data.join("Default.bif")).unwrap(), relative);
+    assert_eq!(
+        fs::read_link(data.join("AREA000A.bif")).unwrap(),
+        source.path().join("game/data/AREA000A.bif")
+    );
+}
+
+#[test]
+fn verify_reports_differences() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    fs::remove_file(target.path().join("game/music/lone.acm")).unwrap();
+    let config = Config {
+        verify: true,
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Mismatch));
+    // verify doesn't repair anything
+    assert!(!is_link(&target.path().join("game/music/lone.acm")));
+}
+
+#[test]
+fn resume_redoes_unrecorded_copies() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    // simulate an interruption while copying engine.lua
+    write_file(&target.path().join("game/engine.lua"), "eng");
+    write_file(&target.path().join(STATE_FILE), &format!("{}\ngame/chitin.key\n", STATE_HEADER));
+    let config = Config {
+        resume: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_to_string(target.path().join("game/engine.lua")).unwrap(), "engine");
+    assert!(!target.path().join(STATE_FILE).exists());
+}
+
+#[test]
+fn resume_without_state_merges() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    write_file(&target.path().join("game/engine.lua"), "modified");
+    let config = Config {
+        resume: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert_eq!(fs::read_to_string(target.path().join("game/engine.lua")).unwrap(), "modified");
+}
+
+#[test]
+fn glob_special_chars_in_source_path() {
+    let source = fake_source();
+    let parent = tempfile::tempdir().unwrap();
+    let source_path = parent.path().join("bg2ee [GOG]");
+    fs::rename(source.path(), &source_path).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(&source_path, target.path())).unwrap();
+    assert!(is_link(&target.path().join("sod-dlc.zip")));
+}
+
+#[test]
+fn errors_name_the_path() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    fs::remove_dir_all(source.path().join("game/scripts")).unwrap();
+    let error = run(test_config(source.path(), target.path())).unwrap_err();
+    let scripts_dir = source.path().join("game/scripts").to_string_lossy().into_owned();
+    assert!(AnyError::new(error).chain().any(|cause| cause.to_string().contains(&scripts_dir)));
+}
+
+#[test]
+fn library_api() {
+    let source = fake_source();
+    let description = detect(source.path()).unwrap();
+    assert_eq!(
+        (description.os, description.vendor, description.variant),
+        (Os::Linux, Vendor::Gog, Variant::Ee)
+    );
+    let target = tempfile::tempdir().unwrap();
+    let stats = IedupBuilder::new(source.path(), target.path())
+        .no_progress(true)
+        .quiet(true)
+        .exclude("game/movies")
+        .run()
+        .unwrap();
+    assert!(stats.copied > 0 && stats.linked > 0);
+    assert!(!target.path().join("game/movies").exists());
+}
+
+#[test]
+fn notes_the_localized_bifs() {
+    logged();
+    let source = fake_source();
+    // de_DE has its own version of some bifs of data/
+    let de_de = source.path().join("game/lang/de_DE");
+    write_file(&de_de.join("dialog.tlk"), "tlk");
+    write_file(&de_de.join("data/DEFAULT.BIF"), "bif");
+    write_file(&de_de.join("data/Localized.bif"), "bif");
+    write_file(&de_de.join("data/Default.txt"), "txt");
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(is_link(&target.path().join("game/lang/de_DE/data/DEFAULT.BIF")));
+    let notes: Vec<String> = logged()
+        .into_iter()
+        .filter(|(level, message)| *level == log::Level::Info && message.contains("the game uses the localized ones"))
+        .map(|(_, message)| message)
+        .collect();
+    assert_eq!(notes.len(), 1, "{:?}", notes);
+    assert!(notes[0].starts_with("1 bif(s) of "));
+    assert!(notes[0].ends_with(": DEFAULT.BIF"));
+}
+
+#[test]
+fn skips_root_items() {
+    logged();
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(Config {
+        skip: vec![RootItem::Gameinfo, RootItem::Support],
+        ..test_config(source.path(), target.path())
+    })
+    .unwrap();
+    assert!(!target.path().join("gameinfo").exists());
+    assert!(!target.path().join("support").exists());
+    assert!(is_copy(&target.path().join("start.sh")));
+    assert!(is_link(&target.path().join("sod-dlc.zip")));
+    assert!(logged().iter().any(|(_, message)| message == "skipped gameinfo, support"));
+    assert_eq!("dlc".parse::<RootItem>().unwrap(), RootItem::Dlc);
+    assert!("game".parse::<RootItem>().is_err());
+}
+
+#[test]
+fn diffs_an_existing_target() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    let config = Config {
+        diff: true,
+        fail_on_diff: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config.clone()).unwrap();
+
+    write_file(&source.path().join("game/movies/new.wbm"), "movie");
+    write_file(&target.path().join("game/notes.txt"), "notes");
+    write_file(&target.path().join("game/engine.lua"), "changed");
+    let bif = target.path().join("game/data/Default.bif");
+    fs::remove_file(&bif).unwrap();
+    std::os::unix::fs::symlink("/elsewhere/Default.bif", &bif).unwrap();
+    logged();
+    let error = run(config).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Mismatch));
+    let records = logged();
+    let has = |line: &str| records.iter().any(|(_, message)| message.starts_with(line));
+    assert!(has("+ game/movies/new.wbm (link)"));
+    assert!(has("- game/notes.txt"));
+    assert!(has("~ game/engine.lua: the content differs"));
+    assert!(has("~ game/data/Default.bif: links to /elsewhere/Default.bif"));
+    assert!(has("1 added, 1 removed, 2 changed"));
+    // nothing was changed
+    assert_eq!(fs::read_to_string(target.path().join("game/engine.lua")).unwrap(), "changed");
+    assert!(!target.path().join("game/movies/new.wbm").exists());
+}
+
+#[test]
+fn retries_only_transient_errors() {
+    assert!(is_transient(&std::io::Error::from_raw_os_error(16)));
+    assert!(!is_transient(&std::io::Error::from(std::io::ErrorKind::NotFound)));
+    assert!(!is_transient(&std::io::Error::from_raw_os_error(13)));
+}
+
+#[test]
+fn builds_from_a_manifest() {
+    let source = fake_source();
+    let first = tempfile::tempdir().unwrap();
+    let config = Config {
+        write_manifest: true,
+        ..test_config(source.path(), first.path())
+    };
+    run(config).unwrap();
+    let manifest_path = first.path().join(MANIFEST_FILE);
+    // the entries follow the manifest, not the source
+    write_file(&source.path().join("game/extra.txt"), "new");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        from_manifest: Some(manifest_path.clone()),
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("start.sh")));
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+    assert!(is_link(&target.path().join("game/data/Default.bif")));
+    assert!(!target.path().join("game/extra.txt").exists());
+
+    fs::remove_file(source.path().join("game/data/Default.bif")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        from_manifest: Some(manifest_path.clone()),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Mismatch));
+    let config = Config {
+        from_manifest: Some(manifest_path),
+        allow_drift: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+    assert!(target.path().join("game/data").symlink_metadata().is_ok());
+    assert!(target.path().join("game/data/Default.bif").symlink_metadata().is_err());
+}
+
+#[test]
+fn reads_a_manifest() {
+    let manifest = manifest::parse(
+        "{\"source\": \"/gog/bg2ee\", \"entries\": [{\"path\": \"game\", \"type\": \"dir\", \"source\": \"/gog/bg2ee/game\", \
+         \"size\": null}, {\"path\": \"game/caf\\u00e9 \\\"1\\\".txt\", \"type\": \"copy\", \"source\": \"/x\", \"size\": 12}]}",
+    )
+    .unwrap();
+    assert_eq!(manifest.source, Some(PathBuf::from("/gog/bg2ee")));
+    assert_eq!(manifest.entries.len(), 2);
+    assert_eq!(manifest.entries[1].path, PathBuf::from("game/café \"1\".txt"));
+    assert_eq!(manifest.entries[1].size, Some(12));
+    assert!(manifest::parse("{\"entries\": [{\"path\": \"../etc\", \"type\": \"dir\"}]}").is_err());
+    assert!(manifest::parse("{\"entries\": [").is_err());
+}
+
+#[test]
+fn duplicates_a_classic_install() {
+    let source = fake_classic_source();
+    let description = detect(source.path()).unwrap();
+    assert_eq!(description.variant, Variant::Classic);
+    assert_eq!(description.game_dir, source.path());
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        sanity_check: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+
+    let root = target.path();
+    assert!(is_copy(&root.join("chitin.key")));
+    assert!(is_copy(&root.join("dialog.tlk")));
+    assert!(is_copy(&root.join("Baldur.ini")));
+    assert!(is_link(&root.join("BGMain.exe")));
+    assert!(is_link(&root.join("data/AREA000A.bif")));
+    assert!(is_link(&root.join("CD2")));
+    assert!(is_copy(&root.join("override/sw1h01.itm")));
+    assert!(!root.join("lang").exists());
+    assert!(!root.join("save").exists());
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        only: Some(Section::Lang),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert_eq!(error.kind(), Some(FailureKind::Usage));
+}
+
+#[test]
+fn describes_the_changes_watched() {
+    use notify::DebouncedEvent;
+    assert_eq!(
+        watch::describe(&DebouncedEvent::NoticeWrite(PathBuf::from("/gog/bg2ee/game/engine.lua"))),
+        None
+    );
+    assert_eq!(
+        watch::describe(&DebouncedEvent::Write(PathBuf::from("/gog/bg2ee/game/engine.lua"))).as_deref(),
+        Some("/gog/bg2ee/game/engine.lua modified")
+    );
+    assert_eq!(
+        watch::describe(&DebouncedEvent::Rename(PathBuf::from("/a"), PathBuf::from("/b"))).as_deref(),
+        Some("/a renamed to /b")
+    );
+}
+
+#[test]
+fn reflinks_or_copies_plainly() {
+    logged();
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_mode: CopyMode::Reflink,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    // either way the copies are independent files with the content of their source
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+    assert_eq!(
+        fs::read(target.path().join("start.sh")).unwrap(),
+        fs::read(source.path().join("start.sh")).unwrap()
+    );
+    assert!(!target.path().join(".iedup-reflink-probe").exists());
+    let records = logged();
+    assert!(records
+        .iter()
+        .any(|(_, message)| message == "the copies are reflinks" || message.ends_with("copying plainly")));
+}
+
+#[test]
+fn reports_the_links_leading_out_of_the_source() {
+    logged();
+    let source = fake_source();
+    let elsewhere = tempfile::tempdir().unwrap();
+    write_file(&elsewhere.path().join("Evil.bif"), "bif");
+    std::os::unix::fs::symlink(elsewhere.path().join("Evil.bif"), source.path().join("game/data/Evil.bif")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    run(test_config(source.path(), target.path())).unwrap();
+    assert!(logged()
+        .iter()
+        .any(|(level, message)| *level == log::Level::Error && message.contains("leads out of the source")));
+
+    let target = tempfile::tempdir().unwrap();
+    let reports = tempfile::tempdir().unwrap();
+    let report = reports.path().join("report.json");
+    let config = Config {
+        strict: true,
+        report_json: Some(report.clone()),
+        ..test_config(source.path(), target.path())
+    };
+    let error = run(config).unwrap_err();
+    assert!(error.to_string().contains("lead out of the source"), "{:#}", error);
+    assert_eq!(error.kind(), Some(FailureKind::InvalidSource));
+    // the report tells it too
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains("lead out of the source"), "{}", json);
+    assert!(json.contains("\"game/data\": {\"copied\": 0, \"linked\": 3"), "{}", json);
+}
+
+#[test]
+fn weighs_the_progress_by_bytes() {
+    let source = fake_source();
+    let action = |kind: ActionKind, name: &str| Action {
+        kind,
+        source: Some(source.path().join(name)),
+        target: PathBuf::from("/games/bg2ee").join(name),
+        size: None,
+    };
+    assert_eq!(progress_weight(&action(ActionKind::Copy, "start.sh")), 10);
+    assert_eq!(progress_weight(&action(ActionKind::Link, "support")), LINK_WEIGHT);
+    assert_eq!(progress_weight(&action(ActionKind::CreateDir, "game")), 0);
+}
+
+#[test]
+fn creates_the_target_when_asked() {
+    let source = fake_source();
+    let parent = tempfile::tempdir().unwrap();
+    let target = parent.path().join("games/bg2ee");
+    assert!(run(test_config(source.path(), &target)).is_err());
+    assert!(!target.exists());
+
+    let config = Config {
+        create_target: true,
+        into: Some(String::from("copy")),
+        ..test_config(source.path(), &target)
+    };
+    run(config).unwrap();
+    assert!(is_link(&target.join("copy/game/data/Default.bif")));
+
+    // never inside the source
+    let config = Config {
+        create_target: true,
+        ..test_config(source.path(), &source.path().join("new"))
+    };
+    assert!(run(config).is_err());
+    assert!(!source.path().join("new").exists());
+}
+
+#[test]
+fn skips_the_movies_and_sounds_of_the_languages() {
+    let source = fake_source();
+    write_file(&source.path().join("game/lang/en_US/movies/intro.wbm"), "movie");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        skip_lang_movies: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let lang = target.path().join("game/lang/en_US");
+    assert!(is_copy(&lang.join("dialog.tlk")));
+    assert!(!lang.join("movies").exists());
+    assert!(is_link(&lang.join("sounds/ab.wav")));
+
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        skip_lang_sounds: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let lang = target.path().join("game/lang/en_US");
+    assert!(is_copy(&lang.join("dialog.tlk")));
+    assert!(!lang.join("sounds").exists());
+    assert!(lang.join("movies").is_dir());
+}
+
+#[test]
+fn warns_when_engine_lua_is_missing() {
+    logged();
+    let source = fake_source();
+    fs::remove_file(source.path().join("game/engine.lua")).unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        sanity_check: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    assert!(is_copy(&target.path().join("game/chitin.key")));
+    assert!(logged()
+        .iter()
+        .any(|(level, message)| *level == log::Level::Warn && message.starts_with("no engine.lua in ")));
+}
+
+#[test]
+fn prints_the_sizes_only() {
+    logged();
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        stats_only: true,
+        ..test_config(source.path(), target.path())
+    };
+    let sized = run(config).unwrap();
+    assert_eq!(fs::read_dir(target.path()).unwrap().count(), 0);
+    let messages: Vec<String> = logged().into_iter().map(|(_, message)| message).collect();
+    assert!(messages.iter().any(|message| message.starts_with("extension ")));
+    // the files of the linked lang/en_US/sounds each with its extension
+    assert!(messages.iter().any(|message| message.starts_with("wav ")));
+    let done = run(test_config(source.path(), target.path())).unwrap();
+    assert_eq!(
+        (sized.copied, sized.linked, sized.dirs, sized.bytes, sized.linked_bytes),
+        (done.copied, done.linked, done.dirs, done.bytes, done.linked_bytes)
+    );
+}
+
+#[test]
+fn honors_the_ignore_files_of_the_source() {
+    logged();
+    let source = fake_source();
+    let game = source.path().join("game");
+    write_file(&source.path().join(".iedupignore"), "# no sound list\nsndlist.txt\n");
+    write_file(&game.join("movies/.iedupignore"), "lo/\n*.wbm\n!480/intro.wbm\n");
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        verbose: 1,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let movies = target.path().join("game/movies");
+    assert!(!movies.join("intro.wbm").exists());
+    assert!(!movies.join("lo").exists());
+    assert!(!movies.join(".iedupignore").exists());
+    assert!(is_link(&movies.join("480/intro.wbm")));
+    assert!(is_link(&target.path().join("game/lang/en_US/sounds/ab.wav")));
+    assert!(!target.path().join("game/lang/en_US/sounds/sndlist.txt").exists());
+    assert!(!target.path().join(".iedupignore").exists());
+    let ignored = format!("*.wbm in {}", game.join("movies/.iedupignore").to_string_lossy());
+    assert!(logged()
+        .iter()
+        .any(|(level, message)| *level == log::Level::Debug && message.ends_with(&format!("intro.wbm ({})", ignored))));
+}
+
+#[test]
+fn relinks_the_copies_of_the_source() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = Config {
+        copy_all: true,
+        ..test_config(source.path(), target.path())
+    };
+    run(config).unwrap();
+    let game = target.path().join("game");
+    write_file(&game.join("movies/intro.wbm"), "modded movie");
+    let config = Config {
+        relink: true,
+        ..test_config(source.path(), target.path())
+    };
+    let relinked = run(config).unwrap();
+    assert!(is_link(&game.join("data/Default.bif")));
+    assert!(is_link(&game.join("movies/480/intro.wbm")));
+    assert!(is_link(&target.path().join("support/xdg-open")));
+    assert!(is_copy(&game.join("movies/intro.wbm")));
+    assert!(is_copy(&game.join("chitin.key")));
+    assert!(is_copy(&game.join("engine.lua")));
+    assert_eq!(fs::read_to_string(game.join("data/Default.bif")).unwrap(), "bif");
+    assert!(relinked.linked > 0);
+    assert_eq!(relinked.copied, 0);
+}
+
+#[test]
+fn lists_and_removes_a_target() {
+    let source = fake_source();
+    let parent = tempfile::tempdir().unwrap();
+    let target = parent.path().join("built");
+    fs::create_dir(&target).unwrap();
+    run(test_config(source.path(), &target)).unwrap();
+    let listing = list(&target).unwrap();
+    let (recorded, _) = listing.source.unwrap();
+    assert_eq!(recorded, source.path().canonicalize().unwrap());
+    assert_eq!(listing.categories["game/data"].linked, 2);
+    assert_eq!(listing.categories["game"].copied, 2);
+
+    let other = tempfile::tempdir().unwrap();
+    write_file(&other.path().join("notes.txt"), "notes");
+    assert!(matches!(remove(other.path(), true, false), Err(IedupError::Usage(_))));
+    assert!(other.path().join("notes.txt").exists());
+    let removed = remove(&target, false, true).unwrap();
+    assert!(removed.contains(&target.join("game/chitin.key")));
+    assert!(!removed.contains(&target.join("game/data/Default.bif")));
+    assert!(target.exists());
+    // the files added or changed since the build are only removed with force
+    write_file(&target.join("game/override/mod.itm"), "mod");
+    let error = remove(&target, false, false).unwrap_err();
+    assert!(error.to_string().contains("override/mod.itm"), "{}", error);
+    assert!(target.join("game/override/mod.itm").exists());
+    remove(&target, true, false).unwrap();
+    assert!(!target.exists());
+    assert_eq!(fs::read_to_string(source.path().join("game/data/Default.bif")).unwrap(), "bif");
+}
+
+#[test]
+fn removes_a_flattened_target_by_its_manifest() {
+    let source = fake_source();
+    let target = tempfile::tempdir().unwrap();
+    let config = |target: &Path| Config {
+        flatten: true,
+        ..test_config(source.path(), target)
+    };
+    run(config(target.path())).unwrap();
+    // its copies aren't where their sources are
+    let error = remove(target.path(), false, true).unwrap_err();
+    assert!(error.to_string().contains("--flatten"), "{}", error);
+    assert!(remove(target.path(), true, true).is_ok());
+
+    let target = tempfile::tempdir().unwrap();
+    run(Config {
+        write_manifest: true,
+        ..config(target.path())
+    })
+    .unwrap();
+    let removed = remove(target.path(), false, true).unwrap();
+    assert!(removed.contains(&target.path().join("chitin.key")));
+    write_file(&target.path().join("override/mod.itm"), "mod");
+    let error = remove(target.path(), false, true).unwrap_err();
+    assert!(error.to_string().contains("override/mod.itm"), "{}", error);
+}
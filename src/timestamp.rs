@@ -0,0 +1,79 @@
+// Timestamps of the command line (--since): RFC 3339 (2021-03-01T12:00:00Z, 2021-03-01T14:00:00+02:00) or a number of
+// seconds since the epoch prefixed with @, as `date +@%s` prints it.
+
+use anyhow::{anyhow, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub fn parse(value: &str) -> Result<SystemTime> {
+    let invalid = || anyhow!("invalid time {} (expected 2021-03-01T12:00:00Z or @<seconds since the epoch>)", value);
+    if let Some(seconds) = value.strip_prefix('@') {
+        return seconds
+            .parse()
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+            .map_err(|_| invalid());
+    }
+    let seconds = parse_rfc3339(value).ok_or_else(invalid)?;
+    if seconds < 0 {
+        return Err(anyhow!("{} is before 1970", value));
+    }
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+}
+
+/// the seconds since the epoch (the fractional seconds are ignored)
+fn parse_rfc3339(value: &str) -> Option<i64> {
+    if !value.is_ascii() || value.len() < 20 {
+        return None;
+    }
+    let (date, rest) = value.split_at(10);
+    let (time, mut zone) = rest.split_at(9);
+    if !matches!(&time[..1], "T" | "t" | " ") {
+        return None;
+    }
+    let number = |text: &str| {
+        if text.bytes().all(|byte| byte.is_ascii_digit()) {
+            text.parse::<i64>().ok()
+        } else {
+            None
+        }
+    };
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time[1..].split(':').collect();
+    if date.len() != 3 || time.len() != 3 {
+        return None;
+    }
+    let (year, month, day) = (number(date[0])?, number(date[1])?, number(date[2])?);
+    let (hour, minute, second) = (number(time[0])?, number(time[1])?, number(time[2])?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    if let Some(fraction) = zone.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        zone = &fraction[digits..];
+    }
+    let offset = match zone {
+        "Z" | "z" => 0,
+        _ if zone.len() == 6 && &zone[3..4] == ":" => {
+            let minutes = number(&zone[1..3])? * 60 + number(&zone[4..])?;
+            match &zone[..1] {
+                "+" => minutes * 60,
+                "-" => -minutes * 60,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// the days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
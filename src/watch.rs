@@ -0,0 +1,80 @@
+// Watch mode (iedup watch): the target is merged from the source once, then again each time the source changes, the
+// copies being refreshed only when their source changed since the previous sync (like --merge --since). The changes
+// are debounced: a sync waits for the source to stay unchanged for a while, and takes all the changes seen meanwhile.
+
+use crate::{Config, IedupError};
+use anyhow::{Context, Result};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, SystemTime};
+
+/// Syncs `config.target` with `config.source` at each change of the source until interrupted (or the watch fails).
+/// Watching implies merging, a failing sync is reported and done again at the next change
+pub fn run(config: Config, debounce: Duration) -> Result<(), IedupError> {
+    watch(config, debounce).map_err(IedupError::from_any)
+}
+
+fn watch(config: Config, debounce: Duration) -> Result<()> {
+    let config = Config {
+        merge: true,
+        verify: false,
+        resume: false,
+        ..config
+    };
+    let source = config.source.clone();
+    let (sender, receiver) = channel();
+    let mut watcher = notify::watcher(sender, debounce).context("failed to start watching")?;
+    watcher
+        .watch(&source, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {}", source.to_string_lossy()))?;
+    // the first sync fails the watch, a source or a target it can't use won't get better
+    let mut synced = SystemTime::now();
+    crate::run(config.clone())?;
+    loop {
+        if !config.quiet {
+            log::info!("watching {} for changes", source.to_string_lossy());
+        }
+        let changes = wait_for_changes(&receiver)?;
+        if !config.quiet {
+            for change in &changes {
+                log::info!("changed: {}", change);
+            }
+        }
+        let started = SystemTime::now();
+        match crate::run(Config {
+            since: Some(synced),
+            ..config.clone()
+        }) {
+            Ok(_) => synced = started,
+            Err(error) => log::error!("sync failed: {:#}", error),
+        }
+    }
+}
+
+/// Waits for a change of the source, then gathers those that came with it, returns them described
+fn wait_for_changes(receiver: &Receiver<DebouncedEvent>) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+    while changes.is_empty() {
+        let event = receiver.recv().context("the watch of the source stopped")?;
+        changes.extend(describe(&event));
+        changes.extend(receiver.try_iter().filter_map(|event| describe(&event)));
+    }
+    Ok(changes)
+}
+
+/// What `event` changed in the source, None for the notices that come before the changes
+pub fn describe(event: &DebouncedEvent) -> Option<String> {
+    let path = |path: &Path| path.to_string_lossy().into_owned();
+    match event {
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => None,
+        DebouncedEvent::Create(created) => Some(format!("{} created", path(created))),
+        DebouncedEvent::Write(written) | DebouncedEvent::Chmod(written) => Some(format!("{} modified", path(written))),
+        DebouncedEvent::Remove(removed) => Some(format!("{} removed", path(removed))),
+        DebouncedEvent::Rename(from, to) => Some(format!("{} renamed to {}", path(from), path(to))),
+        // too many changes at once, everything is checked anyway
+        DebouncedEvent::Rescan => Some(String::from("the whole source")),
+        DebouncedEvent::Error(error, Some(failed)) => Some(format!("{} (error: {})", path(failed), error)),
+        DebouncedEvent::Error(error, None) => Some(format!("unknown (error: {})", error)),
+    }
+}